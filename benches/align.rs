@@ -0,0 +1,90 @@
+//! Benchmarks of the alignment engine on generated files, run with
+//! `cargo bench --bench align`. Save a baseline with
+//! `cargo bench --bench align -- --save-baseline main` before a change and
+//! compare against it with `cargo bench --bench align -- --baseline main`.
+
+use cargo_align::align::AlignOptions;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use std::fmt::Write;
+use std::hint::black_box;
+
+/// A source file with an alignment statement every few lines, which is
+/// mostly parsing statements and aligning small blocks.
+fn directive_heavy(blocks: usize) -> String {
+    let mut s = String::new();
+    for block in 0..blocks {
+        match block % 4 {
+            0 => s.push_str("// align_by \"=\"\n"),
+            1 => s.push_str("// align_by sort:2 \"= ;\"\n"),
+            2 => s.push_str("// align_by dedup \"=\" \":\"\n"),
+            _ => s.push_str("// align_by group(g) \"=\"\n"),
+        }
+        for line in 0..4 {
+            let name = "x".repeat((block * 7 + line * 3) % 13 + 1);
+            writeln!(s, "let {name} = {}: {};", line * block, block % 5).unwrap();
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// A large file without any alignment statement, which is all scanning.
+fn no_directives(lines: usize) -> String {
+    let mut s = String::new();
+    for line in 0..lines {
+        writeln!(
+            s,
+            "    let value_{line} = compute(value_{}, \"{}\"); // {line}",
+            line / 2,
+            "a".repeat(line % 40)
+        )
+        .unwrap();
+    }
+    s
+}
+
+/// A Markdown table of `rows` rows with `columns` columns under `align_by table`.
+fn wide_table(rows: usize, columns: usize) -> String {
+    let mut s = String::from("<!-- align_by table -->\n");
+    let row = |s: &mut String, cell: &dyn Fn(usize) -> String| {
+        s.push('|');
+        for column in 0..columns {
+            write!(s, " {} |", cell(column)).unwrap();
+        }
+        s.push('\n');
+    };
+    row(&mut s, &|column| format!("column {column}"));
+    row(&mut s, &|column| {
+        ["-", ":-", "-:", ":-:"][column % 4].to_string()
+    });
+    for index in 0..rows {
+        row(&mut s, &|column| {
+            "c".repeat((index * 31 + column * 17) % 23 + 1)
+        });
+    }
+    s
+}
+
+fn bench(c: &mut Criterion) {
+    let options = AlignOptions::new();
+    let fixtures = [
+        ("directive_heavy", 1_000, directive_heavy(1_000)),
+        ("no_directives", 100_000, no_directives(100_000)),
+        ("wide_table", 1_000, wide_table(1_000, 40)),
+    ];
+    let mut group = c.benchmark_group("align_string");
+    for (name, size, fixture) in &fixtures {
+        group.throughput(Throughput::Bytes(fixture.len() as u64));
+        group.bench_with_input(BenchmarkId::new(*name, size), fixture, |b, fixture| {
+            b.iter(|| options.align_string(black_box(fixture)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+/* C interface to the alignment engine of cargo-align, from
+ * `cargo build --release --no-default-features --features ffi`.
+ * See src/ffi.rs for the details of each function. */
+
+#ifndef CARGO_ALIGN_H
+#define CARGO_ALIGN_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The text was aligned. */
+#define CARGO_ALIGN_OK 0
+/* The text has an invalid alignment statement, described by the error. */
+#define CARGO_ALIGN_INVALID 1
+/* A pointer was null or the text wasn't valid UTF-8. */
+#define CARGO_ALIGN_BAD_ARGUMENT 2
+
+/* An invalid alignment statement. */
+typedef struct CargoAlignError {
+    /* 1 based line number. */
+    size_t line;
+    /* 1 based column, in characters. */
+    size_t column;
+    /* The code `cargo align --explain` takes, like "A0001". */
+    char code[6];
+    /* What is wrong, freed with cargo_align_free_string. */
+    char *message;
+} CargoAlignError;
+
+/* Aligns the UTF-8 `input` and stores the result in `output`, to be freed with
+ * cargo_align_free_string. Fills `err` if CARGO_ALIGN_INVALID is returned. */
+int cargo_align_format(const char *input, char **output, CargoAlignError *err);
+
+/* Like cargo_align_format, with `marker` instead of `align_by` starting the
+ * alignment statements. A null `marker` uses `align_by`. */
+int cargo_align_format_with_marker(const char *input, const char *marker, char **output,
+                                   CargoAlignError *err);
+
+/* Frees a string returned by this library. Does nothing if `s` is null. */
+void cargo_align_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
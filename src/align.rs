@@ -0,0 +1,3027 @@
+//! The alignment engine, which aligns the blocks of a string under their
+//! alignment statements without touching the filesystem.
+
+use crate::directive;
+use crate::directive::Alignment;
+use crate::directive::Directive;
+use crate::directive::InvalidAlignmentStatement;
+use crate::table;
+use std::borrow::Cow;
+use std::ops::Not;
+use std::ops::Range;
+use std::ops::RangeInclusive;
+
+/// An alignment statement that stops a string from being aligned.
+#[derive(Debug, PartialEq)]
+pub enum AlignmentError {
+    InvalidAlignmentStatement {
+        /// 1 based line number.
+        line: usize,
+        /// 1 based column, in characters.
+        column: usize,
+        kind: InvalidAlignmentStatement,
+    },
+    /// A word that isn't a keyword after the marker, with `--strict`.
+    UnknownKeyword {
+        /// 1 based line number.
+        line: usize,
+        /// 1 based column, in characters.
+        column: usize,
+        word: String,
+    },
+    /// A `sort:<name>` naming a comparator that isn't registered.
+    UnknownComparator {
+        /// 1 based line number.
+        line: usize,
+        /// 1 based column, in characters.
+        column: usize,
+        name: String,
+    },
+    /// A line of a block longer than [`AlignOptions::max_line_length`].
+    LineTooLong {
+        /// 1 based line number.
+        line: usize,
+        /// The length of the line in bytes.
+        length: usize,
+        limit: usize,
+    },
+    /// A block with more lines than [`AlignOptions::max_block_lines`].
+    BlockTooLong {
+        /// 1 based line number of the statement.
+        line: usize,
+        limit: usize,
+    },
+}
+
+impl std::fmt::Display for AlignmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlignmentError::InvalidAlignmentStatement { line, column, kind } => write!(
+                f,
+                "{line}:{column}: error[{}]: {}, run `cargo align --explain {}` for more information",
+                kind.code(),
+                kind.message(),
+                kind.code()
+            ),
+            AlignmentError::UnknownKeyword { line, column, .. } => write!(
+                f,
+                "{line}:{column}: error[{code}]: {}, run `cargo align --explain {code}` for the list of keywords",
+                self.message(),
+                code = self.code(),
+            ),
+            AlignmentError::UnknownComparator { .. }
+            | AlignmentError::LineTooLong { .. }
+            | AlignmentError::BlockTooLong { .. } => write!(
+                f,
+                "{}:{}: error[{code}]: {}, run `cargo align --explain {code}` for more information",
+                self.line(),
+                self.column(),
+                self.message(),
+                code = self.code(),
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AlignmentError {}
+
+impl AlignmentError {
+    /// The error at `lines` lines further down, for a string that was aligned
+    /// as a part of a longer one.
+    pub(crate) fn moved_down(self, lines: usize) -> AlignmentError {
+        match self {
+            AlignmentError::InvalidAlignmentStatement { line, column, kind } => {
+                AlignmentError::InvalidAlignmentStatement {
+                    line: line + lines,
+                    column,
+                    kind,
+                }
+            }
+            AlignmentError::UnknownKeyword { line, column, word } => {
+                AlignmentError::UnknownKeyword {
+                    line: line + lines,
+                    column,
+                    word,
+                }
+            }
+            AlignmentError::UnknownComparator { line, column, name } => {
+                AlignmentError::UnknownComparator {
+                    line: line + lines,
+                    column,
+                    name,
+                }
+            }
+            AlignmentError::LineTooLong {
+                line,
+                length,
+                limit,
+            } => AlignmentError::LineTooLong {
+                line: line + lines,
+                length,
+                limit,
+            },
+            AlignmentError::BlockTooLong { line, limit } => AlignmentError::BlockTooLong {
+                line: line + lines,
+                limit,
+            },
+        }
+    }
+
+    /// 1 based line number.
+    pub fn line(&self) -> usize {
+        match self {
+            AlignmentError::InvalidAlignmentStatement { line, .. }
+            | AlignmentError::UnknownKeyword { line, .. }
+            | AlignmentError::UnknownComparator { line, .. }
+            | AlignmentError::LineTooLong { line, .. }
+            | AlignmentError::BlockTooLong { line, .. } => *line,
+        }
+    }
+
+    /// 1 based column, in characters.
+    pub fn column(&self) -> usize {
+        match self {
+            AlignmentError::InvalidAlignmentStatement { column, .. }
+            | AlignmentError::UnknownKeyword { column, .. }
+            | AlignmentError::UnknownComparator { column, .. } => *column,
+            AlignmentError::LineTooLong { .. } | AlignmentError::BlockTooLong { .. } => 1,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AlignmentError::InvalidAlignmentStatement { kind, .. } => kind.code(),
+            AlignmentError::UnknownKeyword { .. } => {
+                InvalidAlignmentStatement::UnknownKeyword.code()
+            }
+            AlignmentError::UnknownComparator { .. } => {
+                InvalidAlignmentStatement::UnknownComparator.code()
+            }
+            AlignmentError::LineTooLong { .. } => InvalidAlignmentStatement::LineTooLong.code(),
+            AlignmentError::BlockTooLong { .. } => InvalidAlignmentStatement::BlockTooLong.code(),
+        }
+    }
+
+    /// What is wrong, without the location or the hint to `--explain`.
+    pub fn message(&self) -> String {
+        match self {
+            AlignmentError::InvalidAlignmentStatement { kind, .. } => kind.message().to_string(),
+            AlignmentError::UnknownKeyword { word, .. } => match directive::suggest_keyword(word) {
+                Some(keyword) => format!("unknown keyword `{word}` (did you mean `{keyword}`?)"),
+                None => format!("unknown keyword `{word}`"),
+            },
+            AlignmentError::UnknownComparator { name, .. } => {
+                format!("unknown comparator `{name}`")
+            }
+            AlignmentError::LineTooLong { length, limit, .. } => {
+                format!("a line of the block is {length} bytes long, over the limit of {limit}")
+            }
+            AlignmentError::BlockTooLong { limit, .. } => {
+                format!("the block has more than {limit} lines")
+            }
+        }
+    }
+}
+
+/// Checks that every marker of `s` before the stop marker starts a valid
+/// alignment statement, for `--strict`. Escaped markers are still prose.
+fn check_strict(s: &str, marker: &str, modes: &[&str]) -> Result<(), AlignmentError> {
+    for (line_index, line) in s.lines().enumerate() {
+        if directive::is_stop(line, marker) {
+            break;
+        }
+        if let Err(err) = directive::parse(line, marker, true, modes) {
+            let column = err.span.column(line);
+            return Err(match err.kind {
+                InvalidAlignmentStatement::UnknownKeyword => AlignmentError::UnknownKeyword {
+                    line: line_index + 1,
+                    column,
+                    word: line[err.span.start..err.span.end].to_string(),
+                },
+                kind => AlignmentError::InvalidAlignmentStatement {
+                    line: line_index + 1,
+                    column,
+                    kind,
+                },
+            });
+        }
+    }
+    Ok(())
+}
+
+/// A problem with the alignment statements of a file that doesn't stop it
+/// from being aligned.
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    /// 1 based line number of the offending directive.
+    pub line: usize,
+    pub message: String,
+}
+
+/// What aligning a string found and did, for previews of the changes.
+#[derive(Debug, Default, PartialEq)]
+pub struct AlignReport {
+    /// The alignment statements of the string with their 1 based line, in
+    /// the order they appear.
+    pub statements: Vec<(usize, Directive)>,
+    /// The blocks the statements aligned, in the order they appear.
+    pub blocks: Vec<Block>,
+    /// The smallest changes that turn the original string into the aligned
+    /// one, in order and without overlapping.
+    pub edits: Vec<Edit>,
+    pub warnings: Vec<Warning>,
+}
+
+impl AlignReport {
+    /// Redoes the edits past the first `kept` bytes of the untrimmed aligned
+    /// string, now that the rest of it has become the end of `aligned`.
+    fn end_edits(&mut self, original: &str, aligned: &str, kept: usize) {
+        let cut = self
+            .edits
+            .iter()
+            .position(|edit| edit.aligned.end > kept)
+            .unwrap_or(self.edits.len());
+        let aligned_start = self
+            .edits
+            .get(cut)
+            .map_or(kept, |edit| edit.aligned.start.min(kept));
+        self.edits.truncate(cut);
+        // Both strings are the same between the last edit and the cut.
+        let original_start = self.edits.last().map_or(aligned_start, |edit| {
+            edit.original.end + aligned_start - edit.aligned.end
+        });
+        self.edits.extend(edit(
+            &original[original_start..],
+            &aligned[aligned_start..],
+            original_start,
+            aligned_start,
+        ));
+    }
+}
+
+/// A change aligning made, replacing the `original` bytes of the original
+/// string with the `aligned` bytes of the aligned one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub original: Range<usize>,
+    pub aligned: Range<usize>,
+}
+
+/// The lines one alignment statement aligned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// 1 based line of the statement, after the block for `above N`.
+    pub statement_line: usize,
+    /// 1 based lines of the block in the original string.
+    pub lines: RangeInclusive<usize>,
+    /// Whether aligning changed any line of the block.
+    pub changed: bool,
+}
+
+/// Orders two sort keys for `sort:<name>`, registered with
+/// [`AlignOptions::comparator`].
+pub type Comparator = std::sync::Arc<dyn Fn(&str, &str) -> std::cmp::Ordering + Send + Sync>;
+
+/// The comparators registered with [`AlignOptions::comparator`] by name.
+#[derive(Clone, Default)]
+struct Comparators(Vec<(String, Comparator)>);
+
+/// The built in `natural` comparator, see [`natural_order`].
+static NATURAL: std::sync::LazyLock<Comparator> =
+    std::sync::LazyLock::new(|| std::sync::Arc::new(natural_order));
+
+impl Comparators {
+    /// The comparator called `name`, registered or built in.
+    fn get(&self, name: &str) -> Option<&Comparator> {
+        let registered = self
+            .0
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .map(|(_, comparator)| comparator);
+        registered.or(match name {
+            "natural" => Some(&*NATURAL),
+            _ => None,
+        })
+    }
+
+    /// Registers `comparator` as `name`, replacing the one that had it.
+    fn insert(&mut self, name: String, comparator: Comparator) {
+        self.0.retain(|(registered, _)| *registered != name);
+        self.0.push((name, comparator));
+    }
+}
+
+impl std::fmt::Debug for Comparators {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl PartialEq for Comparators {
+    fn eq(&self, other: &Comparators) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| a.0 == b.0 && std::sync::Arc::ptr_eq(&a.1, &b.1))
+    }
+}
+
+impl Eq for Comparators {}
+
+/// A directive mode, the keyword of a `<marker> <keyword>` statement that
+/// transforms the block under it instead of aligning it on delimiters.
+/// `table` is built in, others are registered with [`AlignOptions::mode`].
+pub trait Mode: Send + Sync {
+    /// Whether `line` is part of the block, which ends before the first line
+    /// that isn't or at the next statement.
+    fn continues(&self, line: &str) -> bool {
+        !line.trim().is_empty()
+    }
+
+    /// The lines of the block as they should be, from its lines without their
+    /// line endings. The lines keep their line endings by position.
+    fn transform(&self, lines: &[&str]) -> Vec<String>;
+}
+
+/// The modes registered with [`AlignOptions::mode`] by keyword.
+#[derive(Clone, Default)]
+struct Modes(Vec<(String, std::sync::Arc<dyn Mode>)>);
+
+impl Modes {
+    /// The mode started by `keyword`, registered or built in.
+    fn get(&self, keyword: &str) -> Option<&dyn Mode> {
+        let registered = self
+            .0
+            .iter()
+            .find(|(registered, _)| registered == keyword)
+            .map(|(_, mode)| &**mode);
+        registered.or(match keyword {
+            "table" => Some(&table::Table),
+            _ => None,
+        })
+    }
+
+    /// The keywords of every mode, built in or registered.
+    fn keywords(&self) -> Vec<&str> {
+        let mut keywords = directive::BUILTIN_MODES.to_vec();
+        keywords.extend(self.0.iter().map(|(keyword, _)| keyword.as_str()));
+        keywords
+    }
+
+    /// Registers `mode` as `keyword`, replacing the one that had it.
+    fn insert(&mut self, keyword: String, mode: std::sync::Arc<dyn Mode>) {
+        self.0.retain(|(registered, _)| *registered != keyword);
+        self.0.push((keyword, mode));
+    }
+}
+
+impl std::fmt::Debug for Modes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(keyword, _)| keyword))
+            .finish()
+    }
+}
+
+impl PartialEq for Modes {
+    fn eq(&self, other: &Modes) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| a.0 == b.0 && std::sync::Arc::ptr_eq(&a.1, &b.1))
+    }
+}
+
+impl Eq for Modes {}
+
+/// How the engine aligns, set up once by the command line tool from its flags
+/// or by library users, who then align with [`AlignOptions::align_string`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignOptions {
+    pub(crate) marker: String,
+    pad_to: Option<usize>,
+    gap: Option<usize>,
+    tab_width: Option<usize>,
+    indent_with_tabs: bool,
+    skip_literals: bool,
+    unicode: bool,
+    trim_trailing: bool,
+    strict: bool,
+    max_line_length: Option<usize>,
+    max_block_lines: Option<usize>,
+    comparators: Comparators,
+    modes: Modes,
+}
+
+/// The longest a line of a block can be by default, in bytes.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 10_000;
+
+/// The most lines a block can have by default.
+pub const DEFAULT_MAX_BLOCK_LINES: usize = 100_000;
+
+impl Default for AlignOptions {
+    fn default() -> AlignOptions {
+        AlignOptions::new()
+    }
+}
+
+impl AlignOptions {
+    /// Options that align like `cargo align` without any flags.
+    pub fn new() -> AlignOptions {
+        AlignOptions {
+            marker: directive::DEFAULT_MARKER.to_string(),
+            pad_to: None,
+            gap: None,
+            tab_width: None,
+            indent_with_tabs: false,
+            skip_literals: true,
+            unicode: false,
+            trim_trailing: false,
+            strict: false,
+            max_line_length: Some(DEFAULT_MAX_LINE_LENGTH),
+            max_block_lines: Some(DEFAULT_MAX_BLOCK_LINES),
+            comparators: Comparators::default(),
+            modes: Modes::default(),
+        }
+    }
+
+    /// The word that starts alignment statements, `align_by` by default.
+    pub fn marker(&mut self, marker: impl Into<String>) -> &mut AlignOptions {
+        self.marker = marker.into();
+        self
+    }
+
+    /// Pads cells so each delimiter starts at a multiple of `multiple`.
+    pub fn pad_to(&mut self, multiple: Option<usize>) -> &mut AlignOptions {
+        self.pad_to = multiple.filter(|&multiple| multiple > 0);
+        self
+    }
+
+    /// The least number of spaces between a cell and the delimiter after it,
+    /// for statements without a `gap=N` of their own.
+    pub fn gap(&mut self, gap: Option<usize>) -> &mut AlignOptions {
+        self.gap = gap;
+        self
+    }
+
+    /// Counts a tab as reaching the next multiple of `width` columns from the
+    /// start of its cell, instead of as a single column.
+    pub fn tab_width(&mut self, width: Option<usize>) -> &mut AlignOptions {
+        self.tab_width = width.filter(|&width| width > 0);
+        self
+    }
+
+    /// Whether delimiters inside string and char literals, like the `=` in
+    /// `let a = "b=c";`, are skipped, which they are by default. Text where
+    /// quotes aren't literals, like prose with apostrophes, is better off
+    /// without.
+    pub fn skip_literals(&mut self, enabled: bool) -> &mut AlignOptions {
+        self.skip_literals = enabled;
+        self
+    }
+
+    /// Writes the hanging indents `wrap=N` wraps lines onto with as many tabs
+    /// as [`AlignOptions::tab_width`] fits before spaces, instead of only
+    /// spaces. Without a tab width they are spaces either way.
+    pub fn indent_with_tabs(&mut self, enabled: bool) -> &mut AlignOptions {
+        self.indent_with_tabs = enabled;
+        self
+    }
+
+    /// Measures cells in characters instead of bytes, so text with non-ASCII
+    /// characters lines up.
+    pub fn unicode(&mut self, enabled: bool) -> &mut AlignOptions {
+        self.unicode = enabled;
+        self
+    }
+
+    /// Removes whitespace at the end of every line of every block, as if each
+    /// statement had a `trim` modifier.
+    pub fn trim_trailing(&mut self, enabled: bool) -> &mut AlignOptions {
+        self.trim_trailing = enabled;
+        self
+    }
+
+    /// Rejects markers that don't start a valid statement, like `--strict`.
+    pub fn strict(&mut self, enabled: bool) -> &mut AlignOptions {
+        self.strict = enabled;
+        self
+    }
+
+    /// Rejects blocks with a line longer than `length` bytes, which are usually
+    /// minified or generated content, [`DEFAULT_MAX_LINE_LENGTH`] by default.
+    /// `None` allows any length.
+    pub fn max_line_length(&mut self, length: Option<usize>) -> &mut AlignOptions {
+        self.max_line_length = length;
+        self
+    }
+
+    /// Rejects blocks with more than `lines` lines, [`DEFAULT_MAX_BLOCK_LINES`]
+    /// by default. `None` allows any number.
+    pub fn max_block_lines(&mut self, lines: Option<usize>) -> &mut AlignOptions {
+        self.max_block_lines = lines;
+        self
+    }
+
+    /// Lets `sort:<name>` order the keys of a block with `compare`, ie
+    /// semantic versions. The comparator orders the keys ascending, `desc`
+    /// and `ci` still apply. Registering a name again replaces the comparator,
+    /// including the built in `natural`.
+    pub fn comparator(
+        &mut self,
+        name: impl Into<String>,
+        compare: impl Fn(&str, &str) -> std::cmp::Ordering + Send + Sync + 'static,
+    ) -> &mut AlignOptions {
+        self.comparators
+            .insert(name.into(), std::sync::Arc::new(compare));
+        self
+    }
+
+    /// Makes `<marker> <keyword>` a statement that transforms the block under
+    /// it with `mode`. The keywords of other statements take precedence, and
+    /// registering a keyword again replaces the mode, including the built in
+    /// `table`.
+    pub fn mode(
+        &mut self,
+        keyword: impl Into<String>,
+        mode: impl Mode + 'static,
+    ) -> &mut AlignOptions {
+        self.modes.insert(keyword.into(), std::sync::Arc::new(mode));
+        self
+    }
+
+    /// Parses the statement on `line` like [`directive::parse_directive`],
+    /// with this marker and the registered modes.
+    pub fn parse_directive(&self, line: &str) -> Result<Option<Directive>, directive::ParseError> {
+        directive::parse_directive_with_modes(line, &self.marker, &self.modes.keywords())
+    }
+
+    /// Aligns the blocks of `s` under its alignment statements.
+    pub fn align_string(&self, s: &str) -> Result<String, AlignmentError> {
+        self.align_string_with_report(s).map(|(aligned, _)| aligned)
+    }
+
+    /// Like [`AlignOptions::align_string`], along with the statements and
+    /// blocks it found and the problems that didn't stop `s` from being aligned.
+    pub fn align_string_with_report(
+        &self,
+        s: &str,
+    ) -> Result<(String, AlignReport), AlignmentError> {
+        let (aligned, mut report) = self.align_untrimmed(s)?;
+        let kept = aligned.trim_end().len();
+        let aligned = end_with(&aligned, final_line_ending(s));
+        report.end_edits(s, &aligned, kept);
+        Ok((aligned, report))
+    }
+
+    /// Aligns `s` without touching the blank lines and the whitespace at its
+    /// end, so it can be a part of a longer string.
+    pub(crate) fn align_untrimmed<'a>(
+        &self,
+        s: &'a str,
+    ) -> Result<(Cow<'a, str>, AlignReport), AlignmentError> {
+        // Most files have no statements at all, which leaves them as they are.
+        if !s.contains(self.marker.as_str()) {
+            return Ok((Cow::Borrowed(s), AlignReport::default()));
+        }
+        if self.strict {
+            check_strict(s, &self.marker, &self.modes.keywords())?;
+        }
+        // Blocks of a named group can come after each other in any order, so the
+        // widths of every group are measured first and then applied to each block.
+        let (aligned, report, group_widths) =
+            align_string_with_group_widths(s, self, &GroupWidths::new())?;
+        if group_widths.is_empty() {
+            return Ok((Cow::Owned(aligned), report));
+        }
+        let (aligned, report, _) = align_string_with_group_widths(s, self, &group_widths)?;
+        Ok((Cow::Owned(aligned), report))
+    }
+
+    /// An error if the 0 based `index`-th line is longer than the limit.
+    fn check_line_length(&self, index: usize, line: &str) -> Result<(), AlignmentError> {
+        match self.max_line_length {
+            Some(limit) if line.len() > limit => Err(AlignmentError::LineTooLong {
+                line: index + 1,
+                length: line.len(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// An error if the block of the statement on the 1 based `statement_line`
+    /// has more lines than the limit.
+    fn check_block_lines(&self, statement_line: usize, lines: usize) -> Result<(), AlignmentError> {
+        match self.max_block_lines {
+            Some(limit) if lines > limit => Err(AlignmentError::BlockTooLong {
+                line: statement_line,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `line` could still be part of the block of `directive` that
+    /// already has `block_len` lines, erring towards yes. Once it can't, the
+    /// block ended before it.
+    pub(crate) fn may_continue_block(
+        &self,
+        directive: &Directive,
+        line: &str,
+        block_len: usize,
+    ) -> bool {
+        if let Some(count) = directive.count {
+            return block_len < count;
+        }
+        if let Some(keyword) = directive.block_mode.as_deref() {
+            return self
+                .modes
+                .get(keyword)
+                .is_none_or(|mode| mode.continues(line));
+        }
+        let blank = line.trim().is_empty();
+        let runs = directive.sort.as_ref().and_then(|sort| sort.runs.as_ref());
+        if (blank && (directive.keep_blank || directive.groups))
+            || directive
+                .skip
+                .as_ref()
+                .is_some_and(|skip| skip.matches(line))
+            || runs.is_some_and(|runs| runs.matches(line))
+            || (directive.wrap.is_some() && !blank && line.starts_with(char::is_whitespace))
+        {
+            return true;
+        }
+        // Skipping literals can only stop a delimiter from being found, so a
+        // line missing one doesn't split.
+        let collapsed = line.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+        let line = if directive.raw { line } else { &collapsed };
+        let delimiters = match directive.all {
+            true => &directive.delimiters[..directive.delimiters.len().min(1)],
+            false => &directive.delimiters[..],
+        };
+        let mut rest = line;
+        delimiters
+            .iter()
+            .all(|delimiter| match rest.find(&delimiter.text) {
+                Some(start) => {
+                    rest = &rest[start + delimiter.text.len()..];
+                    true
+                }
+                None => false,
+            })
+    }
+
+    /// The number of columns `s` takes up.
+    fn width(&self, s: &str) -> usize {
+        if !self.unicode && self.tab_width.is_none() {
+            return s.len();
+        }
+        s.chars().fold(0, |width, c| match (c, self.tab_width) {
+            ('\t', Some(tab_width)) => (width / tab_width + 1) * tab_width,
+            _ if self.unicode => width + 1,
+            _ => width + c.len_utf8(),
+        })
+    }
+}
+
+/// The column widths of every named group, from `group(<name>)`.
+type GroupWidths = std::collections::HashMap<String, Vec<(usize, usize)>>;
+
+/// Aligns `s` with the blocks of each named group at least as wide as
+/// `group_widths`, returning the widths the groups actually needed.
+fn align_string_with_group_widths(
+    s: &str,
+    options: &AlignOptions,
+    group_widths: &GroupWidths,
+) -> Result<(String, AlignReport, GroupWidths), AlignmentError> {
+    let marker = options.marker.as_str();
+    // A byte order mark would otherwise be part of the first line.
+    let (bom, s) = match s.strip_prefix('\u{FEFF}') {
+        Some(rest) => ("\u{FEFF}", rest),
+        None => ("", s),
+    };
+    // Lines keep their own terminator, this is only used for lines that lack one.
+    let default_line_ending = detect_newline_style(s);
+    // The byte offset of each line in the original string, for the edits.
+    let mut line_starts = vec![bom.len()];
+    for line in s.split_inclusive('\n') {
+        line_starts.push(line_starts[line_starts.len() - 1] + line.len());
+    }
+    let mut output_offsets = OutputOffsets {
+        pieces: 0,
+        len: bom.len(),
+    };
+    // The line at a 0 based index of `s`, with its line ending.
+    let original_line =
+        |index: usize| &s[line_starts[index] - bom.len()..line_starts[index + 1] - bom.len()];
+    let mut lines = s
+        .split_inclusive('\n')
+        .map(split_line_ending)
+        .enumerate()
+        .peekable();
+    let mut aligned_file = Vec::new();
+    let mut report = AlignReport::default();
+    let mut measured_widths = GroupWidths::new();
+    let mut stopped = false;
+    let mut blocks_above = blocks_above(s, options);
+
+    while let Some(&(line_index, (line, line_ending))) = lines.peek() {
+        // An `above N` statement further down aligns the block starting here.
+        let (statement_line, directive) = match blocks_above.remove(&line_index) {
+            Some((statement_index, directive)) => (statement_index + 1, directive),
+            None => {
+                lines.next();
+                aligned_file.push(line.to_string());
+                aligned_file.push(line_ending.to_string());
+                if directive::is_stop(line, marker) {
+                    stopped = true;
+                }
+
+                if stopped {
+                    continue;
+                }
+
+                match options.parse_directive(line) {
+                    Ok(Some(directive)) => {
+                        let comparator = directive
+                            .sort
+                            .as_ref()
+                            .and_then(|sort| sort.comparator.as_ref());
+                        if let Some(name) =
+                            comparator.filter(|name| options.comparators.get(name).is_none())
+                        {
+                            let start = line.find(&format!("sort:{name}")).unwrap_or(0);
+                            return Err(AlignmentError::UnknownComparator {
+                                line: line_index + 1,
+                                column: line[..start].chars().count() + 1,
+                                name: name.clone(),
+                            });
+                        }
+                        report.statements.push((line_index + 1, directive.clone()));
+                        if directive.above.is_some() {
+                            continue;
+                        }
+                        (line_index + 1, directive)
+                    }
+                    Ok(None) => continue,
+                    Err(err) => {
+                        return Err(AlignmentError::InvalidAlignmentStatement {
+                            line: line_index + 1,
+                            column: err.span.column(line),
+                            kind: err.kind,
+                        })
+                    }
+                }
+            }
+        };
+        // The index of the first line of the block, which follows the statement
+        // unless it is an `above N` one.
+        let first_index = lines.peek().map_or(line_index, |&(index, _)| index);
+        let mode = directive
+            .block_mode
+            .as_deref()
+            .and_then(|keyword| options.modes.get(keyword));
+        if let Some(mode) = mode {
+            let mut rows = Vec::new();
+            while let Some(&(next_index, (next_line, next_line_ending))) = lines.peek() {
+                if !mode.continues(next_line)
+                    || !matches!(options.parse_directive(next_line), Ok(None))
+                {
+                    break;
+                }
+                options.check_line_length(next_index, next_line)?;
+                options.check_block_lines(statement_line, rows.len() + 1)?;
+                lines.next();
+                rows.push((next_line, next_line_ending));
+            }
+            let original_rows = (first_index..first_index + rows.len())
+                .map(original_line)
+                .collect::<Vec<_>>();
+            if lines
+                .peek()
+                .is_some_and(|(_, (next_line, _))| directive::is_cancel_block(next_line, marker))
+            {
+                aligned_file.extend(original_rows.into_iter().map(str::to_string));
+                continue;
+            }
+            let output_start = aligned_file.len();
+            let transformed_rows =
+                mode.transform(&rows.iter().map(|(row, _)| *row).collect::<Vec<_>>());
+            for (index, row) in transformed_rows.into_iter().enumerate() {
+                aligned_file.push(row);
+                aligned_file.push(
+                    match rows.get(index).map_or("", |(_, row_ending)| *row_ending) {
+                        "" => default_line_ending.to_string(),
+                        ending => ending.to_string(),
+                    },
+                );
+            }
+            record_block(
+                &mut report,
+                statement_line,
+                first_index,
+                (line_starts[first_index], &original_rows),
+                (
+                    output_offsets.of(&aligned_file, output_start),
+                    &aligned_file[output_start..],
+                ),
+            );
+            continue;
+        }
+        if directive.cancel_block {
+            continue;
+        }
+
+        let alignment_parts = directive
+            .delimiters
+            .iter()
+            .map(|delimiter| delimiter.text.clone())
+            .collect::<Vec<_>>();
+
+        let mut lines_to_be_modified = Vec::new();
+        // Lines matching the skip pattern and kept blank lines, with their index in the block.
+        let mut skipped_lines = Vec::new();
+        // Indices into `lines_to_be_modified` that start a new group, aligned on its own.
+        let mut group_starts = vec![0];
+        // Indices into `lines_to_be_modified` that start a new run, sorted on its own.
+        let mut run_starts = vec![0];
+        // With `wrap=N`, the column the text after the last delimiter of the
+        // last line starts at, the width of the last line and the lines
+        // wrapped onto it so far.
+        let mut wrap_column = None;
+        let mut last_line_width = 0;
+        let mut continuation_lines = 0;
+
+        while let Some(&(next_index, (next_line, next_line_ending))) = lines.peek() {
+            let block_len = lines_to_be_modified.len() + skipped_lines.len();
+            if directive.count == Some(block_len) || blocks_above.contains_key(&next_index) {
+                break;
+            }
+            if !matches!(options.parse_directive(next_line), Ok(None)) {
+                if block_len == 0 && !directive::is_cancel_block(next_line, marker) {
+                    report.warnings.push(Warning {
+                        line: line_index + 1,
+                        message: format!(
+                            "this alignment statement is shadowed by the one on line {} and aligns nothing",
+                            line_index + 2
+                        ),
+                    });
+                }
+                break;
+            }
+
+            if directive.groups && next_line.trim().is_empty() {
+                lines.next();
+                skipped_lines.push((block_len, original_line(next_index)));
+                group_starts.push(lines_to_be_modified.len());
+                continue;
+            }
+            if let Some(runs) = directive.sort.as_ref().and_then(|sort| sort.runs.as_ref()) {
+                if runs.matches(next_line) {
+                    lines.next();
+                    skipped_lines.push((block_len, original_line(next_index)));
+                    run_starts.push(lines_to_be_modified.len());
+                    continue;
+                }
+            }
+            if (directive.keep_blank && next_line.trim().is_empty())
+                || directive
+                    .skip
+                    .as_ref()
+                    .is_some_and(|skip| skip.matches(next_line))
+            {
+                lines.next();
+                skipped_lines.push((block_len, original_line(next_index)));
+                continue;
+            }
+
+            let collapsed_line = if directive.raw {
+                next_line.trim_end().to_string()
+            } else {
+                let mut collapsed = String::with_capacity(next_line.len());
+                for word in next_line.split_ascii_whitespace() {
+                    if !collapsed.is_empty() {
+                        collapsed.push(' ');
+                    }
+                    collapsed.push_str(word);
+                }
+                collapsed
+            };
+            let broken_str = if directive.all {
+                seperate_str_on_repeated_alignments(
+                    &collapsed_line,
+                    &alignment_parts,
+                    options.skip_literals,
+                )
+            } else {
+                seperate_str_on_alignments(&collapsed_line, &alignment_parts, options.skip_literals)
+            };
+            if let Some(cells) = broken_str {
+                options.check_line_length(next_index, next_line)?;
+                options.check_block_lines(statement_line, block_len + 1)?;
+                lines.next();
+                // The cells are copied only now that the line is part of the
+                // block and gets padded, with the placeholder replaced by the
+                // line ending.
+                let line_ending = match next_line_ending {
+                    "" => default_line_ending,
+                    ending => ending,
+                };
+                let mut broken_str = cells[..cells.len() - 1]
+                    .iter()
+                    .chain([&line_ending])
+                    .map(|part| part.to_string())
+                    .collect::<Vec<_>>();
+                if !directive.reorder.is_empty() {
+                    reorder_cells(&mut broken_str, &directive.reorder);
+                }
+                if directive.raw {
+                    // Whitespace next to a delimiter is padding from an earlier run,
+                    // keeping it verbatim would never let a column shrink. The text
+                    // after the last delimiter is never padded.
+                    let cells = broken_str.len() - 2;
+                    for (index, cell) in broken_str[..cells].iter_mut().enumerate().step_by(2) {
+                        let start = match index {
+                            0 => cell.len() - cell.trim_start().len(),
+                            _ => 0,
+                        };
+                        *cell = [&cell[..start], &collapse_edges(&cell[start..])].concat();
+                    }
+                }
+                if let Some(gap) = directive.gap.or(options.gap) {
+                    // The last two parts are the text after the last delimiter and the line ending.
+                    let cells = broken_str.len() - 2;
+                    for cell in broken_str[..cells].iter_mut().step_by(2) {
+                        *cell = [cell.trim_end(), &" ".repeat(gap)].concat();
+                    }
+                }
+                lines_to_be_modified.push(broken_str);
+                if directive.wrap.is_some() {
+                    wrap_column = Some(rest_column(
+                        next_line,
+                        &alignment_parts,
+                        &directive,
+                        options,
+                    ));
+                    last_line_width = options.width(next_line.trim_end());
+                }
+            } else if let (Some(column), Some(wrap), Some(line)) =
+                (wrap_column, directive.wrap, lines_to_be_modified.last_mut())
+            {
+                // Lines without the delimiters indented to the text after the
+                // last delimiter of the line before continue it, like `wrap`
+                // leaves them. Their first word didn't fit on the line before,
+                // otherwise they are just code that happens to line up.
+                let indentation = next_line.len() - next_line.trim_start().len();
+                let wrapped = next_line
+                    .split_ascii_whitespace()
+                    .next()
+                    .is_some_and(|word| last_line_width + 1 + options.width(word) > wrap);
+                if !wrapped || options.width(&next_line[..indentation]) != column {
+                    break;
+                }
+                last_line_width = options.width(next_line.trim_end());
+                options.check_line_length(next_index, next_line)?;
+                lines.next();
+                continuation_lines += 1;
+                let rest_index = line.len() - 2;
+                let rest = &mut line[rest_index];
+                for word in next_line.split_ascii_whitespace() {
+                    if !rest.ends_with(|c: char| c.is_ascii_whitespace()) {
+                        rest.push(' ');
+                    }
+                    rest.push_str(word);
+                }
+            } else if directive.count.is_some() {
+                // `count N` always takes the next N lines, so lines without
+                // the delimiters are passed through like skipped ones.
+                lines.next();
+                skipped_lines.push((block_len, original_line(next_index)));
+            } else {
+                break;
+            }
+        }
+
+        let trim = directive.trim || options.trim_trailing;
+        let emitted = |line: &str| {
+            if trim {
+                trim_trailing_whitespace(line)
+            } else {
+                line.to_string()
+            }
+        };
+        if lines_to_be_modified.is_empty() {
+            aligned_file.extend(skipped_lines.into_iter().map(|(_, line)| emitted(line)));
+            continue;
+        }
+        // Every line of the block as it was, in case a `cancel_block` follows it.
+        let original_lines = (first_index
+            ..first_index + lines_to_be_modified.len() + skipped_lines.len() + continuation_lines)
+            .map(original_line)
+            .collect::<Vec<_>>();
+        if lines
+            .peek()
+            .is_some_and(|(_, (next_line, _))| directive::is_cancel_block(next_line, marker))
+        {
+            aligned_file.extend(original_lines.into_iter().map(str::to_string));
+            continue;
+        }
+        if directive.all {
+            // Lines with fewer delimiters get empty cells, so every line has the same columns.
+            let parts = lines_to_be_modified.iter().map(Vec::len).max().unwrap();
+            for line in &mut lines_to_be_modified {
+                let line_ending = line.pop().unwrap();
+                line.resize(parts - 1, String::new());
+                line.push(line_ending);
+            }
+        }
+        group_starts.push(lines_to_be_modified.len());
+
+        let modified_lines = group_starts
+            .windows(2)
+            .filter(|group| group[0] < group[1])
+            .flat_map(|group| {
+                let lines = &lines_to_be_modified[group[0]..group[1]];
+                let run_starts = std::iter::once(0)
+                    .chain(
+                        run_starts
+                            .iter()
+                            .filter(|&&start| group[0] < start && start < group[1])
+                            .map(|start| start - group[0]),
+                    )
+                    .collect::<Vec<_>>();
+                let minimum_widths = match &directive.group {
+                    Some(name) => {
+                        merge_widths(
+                            measured_widths.entry(name.clone()).or_default(),
+                            column_widths(lines, &directive, options),
+                        );
+                        group_widths.get(name).map_or(&[][..], Vec::as_slice)
+                    }
+                    None => &[],
+                };
+                align_lines(lines, &directive, options, minimum_widths, &run_starts)
+            })
+            .collect::<Vec<_>>();
+
+        // Skipped lines keep their place, aligned lines fill the rest in order.
+        let output_start = aligned_file.len();
+        let block_len = modified_lines.len() + skipped_lines.len();
+        let mut modified_lines = modified_lines.into_iter();
+        let mut skipped_lines = skipped_lines.into_iter().peekable();
+        let mut seen = std::collections::HashSet::new();
+        for index in 0..block_len {
+            if let Some((_, line)) = skipped_lines.next_if(|(skipped, _)| *skipped == index) {
+                aligned_file.push(emitted(line));
+            } else if let Some(line) = modified_lines.next() {
+                if !directive.dedup || seen.insert(line.trim_end().to_string()) {
+                    aligned_file.push(if trim { emitted(&line) } else { line });
+                }
+            }
+        }
+        record_block(
+            &mut report,
+            statement_line,
+            first_index,
+            (line_starts[first_index], &original_lines),
+            (
+                output_offsets.of(&aligned_file, output_start),
+                &aligned_file[output_start..],
+            ),
+        );
+    }
+
+    // The offset of the end is the length of the aligned string.
+    let mut aligned = String::with_capacity(output_offsets.of(&aligned_file, aligned_file.len()));
+    aligned.push_str(bom);
+    aligned.extend(aligned_file);
+    Ok((aligned, report, measured_widths))
+}
+
+/// Byte offsets into the aligned string, measured as far as they are needed.
+struct OutputOffsets {
+    /// How many parts of the aligned file are measured.
+    pieces: usize,
+    len: usize,
+}
+
+impl OutputOffsets {
+    /// The byte offset `aligned_file[index]` starts at, which can't be before
+    /// the one asked for last.
+    fn of(&mut self, aligned_file: &[String], index: usize) -> usize {
+        self.len += aligned_file[self.pieces..index]
+            .iter()
+            .map(String::len)
+            .sum::<usize>();
+        self.pieces = index;
+        self.len
+    }
+}
+
+/// Adds the block of lines starting at the 0 based `first_index` and its edits
+/// to `report`. The `original` lines start at a byte offset of the original
+/// string, and the `aligned` parts they turned into at one of the aligned
+/// string. Empty blocks aren't added.
+fn record_block(
+    report: &mut AlignReport,
+    statement_line: usize,
+    first_index: usize,
+    (original_start, original): (usize, &[&str]),
+    (aligned_start, aligned): (usize, &[String]),
+) {
+    let (original, aligned) = (original.concat(), aligned.concat());
+    if original.is_empty() {
+        return;
+    }
+    // The last line of a string can lack the line ending aligning gives it.
+    let changed = original != aligned
+        && (original.ends_with('\n') || aligned.trim_end_matches(['\r', '\n']) != original);
+    let line_count = original.split_inclusive('\n').count();
+    report.blocks.push(Block {
+        statement_line,
+        lines: first_index + 1..=first_index + line_count,
+        changed,
+    });
+    if line_count != aligned.split_inclusive('\n').count() {
+        // `dedup` dropped lines, so they no longer pair up.
+        report
+            .edits
+            .extend(edit(&original, &aligned, original_start, aligned_start));
+        return;
+    }
+    let (mut original_offset, mut aligned_offset) = (original_start, aligned_start);
+    for (original_line, aligned_line) in original
+        .split_inclusive('\n')
+        .zip(aligned.split_inclusive('\n'))
+    {
+        report.edits.extend(edit(
+            original_line,
+            aligned_line,
+            original_offset,
+            aligned_offset,
+        ));
+        original_offset += original_line.len();
+        aligned_offset += aligned_line.len();
+    }
+}
+
+/// The edit turning `original` into `aligned` without the text they start
+/// and end with, as offsets from `original_start` and `aligned_start`.
+fn edit(
+    original: &str,
+    aligned: &str,
+    original_start: usize,
+    aligned_start: usize,
+) -> Option<Edit> {
+    if original == aligned {
+        return None;
+    }
+    let prefix = original
+        .char_indices()
+        .zip(aligned.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(original.len().min(aligned.len()), |((index, _), _)| index);
+    let suffix = original[prefix..]
+        .chars()
+        .rev()
+        .zip(aligned[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum::<usize>();
+    Some(Edit {
+        original: original_start + prefix..original_start + original.len() - suffix,
+        aligned: aligned_start + prefix..aligned_start + aligned.len() - suffix,
+    })
+}
+
+/// `aligned` without the blank lines and whitespace at its end, and its last
+/// line ending in `line_ending`.
+pub(crate) fn end_with(aligned: &str, line_ending: &str) -> String {
+    [aligned.trim_end(), line_ending].concat()
+}
+
+/// The line ending of the last line of `s` that isn't blank, or the most
+/// common one of `s` if that line has none.
+fn final_line_ending(s: &str) -> &'static str {
+    let final_line_ending = s
+        .split_inclusive('\n')
+        .rev()
+        .map(split_line_ending)
+        .find(|(line, _)| !line.trim().is_empty())
+        .map_or("", |(_, ending)| ending);
+    match final_line_ending {
+        "\r\n" => "\r\n",
+        "\n" => "\n",
+        _ => detect_newline_style(s),
+    }
+}
+
+/// Moves the text of the cells of a split line into the order of `reorder`,
+/// where the n-th entry is the 1 based cell whose text ends up n-th. The
+/// whitespace around each cell stays where it was, so indentation and the
+/// space after a delimiter don't move with the text.
+fn reorder_cells(broken_str: &mut [String], reorder: &[usize]) {
+    // Cells are every other part, the last part is the line ending.
+    let cells = broken_str.len() / 2;
+    let texts = broken_str
+        .iter()
+        .step_by(2)
+        .take(cells)
+        .map(|cell| cell.trim().to_string())
+        .collect::<Vec<_>>();
+    for (position, &cell) in reorder.iter().enumerate().take(cells) {
+        let text = texts.get(cell - 1).map_or("", String::as_str);
+        let target = &mut broken_str[position * 2];
+        let start = target.len() - target.trim_start().len();
+        let end = start + target.trim().len();
+        target.replace_range(start..end, text);
+    }
+}
+
+/// The `above N` statements of `s` by the index of the first line they align.
+///
+/// The lines only go back as far as the previous statement, so a block never
+/// contains one.
+fn blocks_above(
+    s: &str,
+    options: &AlignOptions,
+) -> std::collections::HashMap<usize, (usize, Directive)> {
+    let mut blocks = std::collections::HashMap::new();
+    let mut previous_statement = None;
+    for (index, (line, _)) in s.split_inclusive('\n').map(split_line_ending).enumerate() {
+        if directive::is_stop(line, &options.marker) {
+            break;
+        }
+        let Ok(Some(directive)) = options.parse_directive(line) else {
+            continue;
+        };
+        if let Some(above) = directive.above {
+            let start = index
+                .saturating_sub(above)
+                .max(previous_statement.map_or(0, |previous| previous + 1));
+            if start < index {
+                blocks.insert(start, (index, directive));
+            }
+        }
+        previous_statement = Some(index);
+    }
+    blocks
+}
+
+/// How the cells of `column` are padded, counting delimiters as columns.
+fn column_alignment(directive: &Directive, column: usize) -> Alignment {
+    // Even columns are the cells before each delimiter, odd ones the delimiters themselves.
+    // With `all` the delimiters repeat, and so do their alignments.
+    match column % 2 {
+        0 => directive.delimiters[column / 2 % directive.delimiters.len()].alignment,
+        _ => Alignment::Left,
+    }
+}
+
+/// The width every padded column of `lines_to_be_modified` needs, split into
+/// the part before and after the decimal point for [`Alignment::Decimal`]
+/// columns. Other columns only have the first part.
+///
+/// Cells wider than the `max=` of the directive don't count, they are left
+/// unpadded instead.
+fn column_widths(
+    lines_to_be_modified: &[Vec<String>],
+    directive: &Directive,
+    options: &AlignOptions,
+) -> Vec<(usize, usize)> {
+    (0..lines_to_be_modified[0].len() - 2)
+        .map(|column| {
+            let cells = lines_to_be_modified
+                .iter()
+                .map(|line| &line[column])
+                .filter(|cell| {
+                    directive
+                        .max_width
+                        .is_none_or(|max| options.width(cell) <= max)
+                });
+            match column_alignment(directive, column) {
+                Alignment::Decimal => cells
+                    .map(|cell| {
+                        let split = Alignment::decimal_split(cell);
+                        (options.width(&cell[..split]), options.width(&cell[split..]))
+                    })
+                    .fold((0, 0), |(a, b), (c, d)| (a.max(c), b.max(d))),
+                _ => (cells.map(|cell| options.width(cell)).max().unwrap_or(0), 0),
+            }
+        })
+        .collect()
+}
+
+/// Widens the columns of `widths` to fit `other`, which may have more columns.
+fn merge_widths(widths: &mut Vec<(usize, usize)>, other: Vec<(usize, usize)>) {
+    for (column, (integer, fraction)) in other.into_iter().enumerate() {
+        match widths.get_mut(column) {
+            Some(width) => *width = (width.0.max(integer), width.1.max(fraction)),
+            None => widths.push((integer, fraction)),
+        }
+    }
+}
+
+/// The column the text after the last delimiter of `line` starts at, which
+/// the lines `wrap` continues it on are indented to.
+fn rest_column(
+    line: &str,
+    alignment_parts: &[String],
+    directive: &Directive,
+    options: &AlignOptions,
+) -> usize {
+    let line = line.trim_end();
+    let cells = if directive.all {
+        seperate_str_on_repeated_alignments(line, alignment_parts, options.skip_literals)
+    } else {
+        seperate_str_on_alignments(line, alignment_parts, options.skip_literals)
+    };
+    // Collapsing whitespace can let a line split that didn't before.
+    let Some(cells) = cells else {
+        return usize::MAX;
+    };
+    let rest = cells[cells.len() - 2].trim_start();
+    options.width(&line[..line.len() - rest.len()])
+}
+
+/// Pushes `rest` onto `line`, wrapping it between words onto lines indented
+/// to where it starts so they stay within `width` columns where the words
+/// allow it. `rest` is left whole if a wrapped line would split on the
+/// delimiters, which would make it a line of the block the next time.
+fn push_wrapped(
+    line: &mut String,
+    rest: &str,
+    width: usize,
+    line_ending: &str,
+    directive: &Directive,
+    options: &AlignOptions,
+) {
+    let alignment_parts = directive
+        .delimiters
+        .iter()
+        .map(|delimiter| delimiter.text.clone())
+        .collect::<Vec<_>>();
+    let splits = |text: &str| {
+        if directive.all {
+            seperate_str_on_repeated_alignments(text, &alignment_parts, options.skip_literals)
+                .is_some()
+        } else {
+            seperate_str_on_alignments(text, &alignment_parts, options.skip_literals).is_some()
+        }
+    };
+    let leading = &rest[..rest.len() - rest.trim_start().len()];
+    let column = options.width(line) + options.width(leading);
+    let mut wrapped = vec![String::new()];
+    for word in rest.split_ascii_whitespace() {
+        let current = wrapped.last_mut().unwrap();
+        if current.is_empty() {
+            current.push_str(word);
+        } else if column + options.width(current) + 1 + options.width(word) > width {
+            wrapped.push(word.to_string());
+        } else {
+            current.push(' ');
+            current.push_str(word);
+        }
+    }
+    if wrapped[1..].iter().any(|text| splits(text)) {
+        line.push_str(rest);
+        return;
+    }
+    let indentation = &line[..line.len() - line.trim_start().len()];
+    let mut hanging = indentation.to_string();
+    let mut hanging_width = options.width(indentation);
+    if let (true, Some(tab_width)) = (options.indent_with_tabs, options.tab_width) {
+        while (hanging_width / tab_width + 1) * tab_width <= column {
+            hanging.push('\t');
+            hanging_width = (hanging_width / tab_width + 1) * tab_width;
+        }
+    }
+    hanging.push_str(&" ".repeat(column - hanging_width));
+    line.push_str(leading);
+    line.push_str(&wrapped[0]);
+    for text in &wrapped[1..] {
+        line.push_str(line_ending);
+        line.push_str(&hanging);
+        line.push_str(text);
+    }
+}
+
+/// Pads the cells of each line in `lines_to_be_modified`, which alternate
+/// with the delimiters they were split on, and sorts the lines if asked to.
+/// Lines are only sorted among the others of their run, which start at the
+/// indices in `run_starts`.
+///
+/// Columns are at least as wide as `minimum_widths`, from [`column_widths`]
+/// of other blocks in the same named group.
+fn align_lines(
+    lines_to_be_modified: &[Vec<String>],
+    directive: &Directive,
+    options: &AlignOptions,
+    minimum_widths: &[(usize, usize)],
+    run_starts: &[usize],
+) -> Vec<String> {
+    let widths = column_widths(lines_to_be_modified, directive, options);
+    let parts = lines_to_be_modified[0].len();
+    // The alignment, integer part width and padded width of each column,
+    // which are the same for every line.
+    let mut layout = Vec::with_capacity(parts - 2);
+    // Every line has the same length up to the current column, since all earlier columns are padded.
+    let mut position = 0;
+    for (column, &(integer_width, fraction_width)) in widths.iter().enumerate() {
+        let (mut integer_max_len, mut fraction_max_len) = (integer_width, fraction_width);
+        if let Some(&(integer, fraction)) = minimum_widths.get(column) {
+            integer_max_len = integer_max_len.max(integer);
+            fraction_max_len = fraction_max_len.max(fraction);
+        }
+        let mut column_max_len = integer_max_len + fraction_max_len;
+        if let (0, Some(&target)) = (column % 2, directive.columns.get(column / 2)) {
+            column_max_len = column_max_len.max(target.saturating_sub(position));
+        }
+        let column_width = match options.pad_to {
+            Some(multiple) if column % 2 == 0 => {
+                (position + column_max_len).next_multiple_of(multiple) - position
+            }
+            _ => column_max_len,
+        };
+        position += column_width;
+        layout.push((
+            column_alignment(directive, column),
+            integer_max_len,
+            column_width,
+        ));
+    }
+
+    let push_spaces =
+        |line: &mut String, count: usize| line.extend(std::iter::repeat_n(' ', count));
+    let mut modified_lines = lines_to_be_modified
+        .iter()
+        .map(|cells| {
+            let (rest, line_ending) = (&cells[parts - 2], &cells[parts - 1]);
+            let mut line = String::with_capacity(position + rest.len() + line_ending.len());
+            for (cell, &(alignment, integer_max_len, column_width)) in cells.iter().zip(&layout) {
+                let padding = column_width.saturating_sub(options.width(cell));
+                match alignment {
+                    Alignment::Left => {
+                        line.push_str(cell);
+                        push_spaces(&mut line, padding);
+                    }
+                    Alignment::Right => {
+                        push_spaces(&mut line, padding);
+                        line.push_str(cell);
+                    }
+                    Alignment::Decimal => {
+                        let split = Alignment::decimal_split(cell);
+                        let leading = integer_max_len.saturating_sub(options.width(&cell[..split]));
+                        push_spaces(&mut line, leading);
+                        line.push_str(cell);
+                        push_spaces(&mut line, padding.saturating_sub(leading));
+                    }
+                    Alignment::Center => {
+                        // Odd padding puts the extra space after the cell.
+                        push_spaces(&mut line, padding / 2);
+                        line.push_str(cell);
+                        push_spaces(&mut line, padding - padding / 2);
+                    }
+                }
+            }
+            match directive.wrap {
+                Some(width) if options.width(&line) + options.width(rest) > width => {
+                    push_wrapped(&mut line, rest, width, line_ending, directive, options)
+                }
+                _ => line.push_str(rest),
+            }
+            if directive.all {
+                // Empty cells at the end of short lines leave padding behind.
+                line.truncate(line.trim_end().len());
+            }
+            line.push_str(line_ending);
+            line
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(sort) = &directive.sort {
+        let mut rows = modified_lines
+            .into_iter()
+            .zip(lines_to_be_modified)
+            .collect::<Vec<_>>();
+        // Cells and delimiters alternate, so column N is at index 2 * (N - 1).
+        // `sort_by` is stable, rows with equal keys keep their order.
+        fn cell(cells: &[String], column: usize) -> &str {
+            cells.get(2 * (column - 1)).map_or("", |cell| cell.trim())
+        }
+        // Unknown comparators are an error once the statement is reached.
+        let comparator = sort
+            .comparator
+            .as_ref()
+            .and_then(|name| options.comparators.get(name));
+        // Parsing the statement rejected locales this build can't sort by.
+        let collator = sort.locale.as_deref().and_then(crate::collation::collator);
+        let compare = |a: &str, b: &str| match (comparator, &collator) {
+            (Some(comparator), _) => sort.compare_by(a, b, comparator.as_ref()),
+            (None, Some(collator)) => sort.compare_by(a, b, collator),
+            (None, None) => sort.compare(a, b),
+        };
+        let run_ends = run_starts.iter().skip(1).copied().chain([rows.len()]);
+        for (&start, end) in run_starts.iter().zip(run_ends) {
+            rows[start..end].sort_by(|(a, a_cells), (b, b_cells)| {
+                if sort.columns.is_empty() {
+                    return compare(a, b);
+                }
+                sort.columns
+                    .iter()
+                    .map(|&column| compare(cell(a_cells, column), cell(b_cells, column)))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        modified_lines = rows.into_iter().map(|(line, _)| line).collect();
+    }
+
+    modified_lines
+}
+
+/// Orders runs of digits by their value and everything else as text, so
+/// `item2` comes before `item10` and `1.9.0` before `1.10.0`.
+fn natural_order(a: &str, b: &str) -> std::cmp::Ordering {
+    /// The next run of digits or of other characters at the start of `s`.
+    fn run(s: &str) -> &str {
+        let is_digit = s.starts_with(|c: char| c.is_ascii_digit());
+        let end = s
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(s.len());
+        &s[..end]
+    }
+    let (mut a_rest, mut b_rest) = (a, b);
+    while !a_rest.is_empty() && !b_rest.is_empty() {
+        let (a_run, b_run) = (run(a_rest), run(b_rest));
+        let both_digits = a_run.starts_with(|c: char| c.is_ascii_digit())
+            && b_run.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if both_digits {
+            let (a_digits, b_digits) =
+                (a_run.trim_start_matches('0'), b_run.trim_start_matches('0'));
+            a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits))
+        } else {
+            a_run.cmp(b_run)
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+        (a_rest, b_rest) = (&a_rest[a_run.len()..], &b_rest[b_run.len()..]);
+    }
+    // Equal up to here, so the shorter one comes first, and `01` and `1` are
+    // told apart by their text.
+    a_rest.len().cmp(&b_rest.len()).then_with(|| a.cmp(b))
+}
+
+/// Splits a line from `split_inclusive('\n')` into its content and terminator.
+pub(crate) fn split_line_ending(line: &str) -> (&str, &str) {
+    if let Some(content) = line.strip_suffix("\r\n") {
+        (content, "\r\n")
+    } else if let Some(content) = line.strip_suffix('\n') {
+        (content, "\n")
+    } else {
+        (line, "")
+    }
+}
+
+/// `line` without the whitespace before its line ending, for `trim`.
+fn trim_trailing_whitespace(line: &str) -> String {
+    let (content, line_ending) = split_line_ending(line);
+    [content.trim_end(), line_ending].concat()
+}
+
+/// Returns the most common line terminator in `s`, `\n` if there are none.
+fn detect_newline_style(s: &str) -> &'static str {
+    let bytes = s.as_bytes();
+    let (mut crlf, mut lf) = (0usize, 0usize);
+    for (i, byte) in bytes.iter().enumerate() {
+        if *byte == b'\n' {
+            if i > 0 && bytes[i - 1] == b'\r' {
+                crlf += 1;
+            } else {
+                lf += 1;
+            }
+        }
+    }
+    if crlf > lf {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+pub fn extract_quote(s: &str) -> String {
+    s.chars().scan(false, |escaped, x| {
+        if x == '\\' {
+            *escaped = true;
+            Some(x)
+        } else if x == '"' && escaped.not() {
+            None
+        } else {
+            *escaped = false;
+            Some(x)
+        }
+}).collect()
+}
+
+/// Shortens whitespace at the start and end of `cell` to a single space.
+fn collapse_edges(cell: &str) -> String {
+    let trimmed = cell.trim();
+    let mut collapsed = String::new();
+    if cell.starts_with(char::is_whitespace) {
+        collapsed.push(' ');
+    }
+    collapsed.push_str(trimmed);
+    if !trimmed.is_empty() && cell.ends_with(char::is_whitespace) {
+        collapsed.push(' ');
+    }
+    collapsed
+}
+
+/// Splits `s` around the first `delimiter`, skipping the ones inside string
+/// and char literals if `skip_literals` is set.
+fn split_once<'a>(s: &'a str, delimiter: &str, skip_literals: bool) -> Option<(&'a str, &'a str)> {
+    if skip_literals {
+        split_once_outside_literals(s, delimiter)
+    } else {
+        s.split_once(delimiter)
+    }
+}
+
+/// Splits `s` around the first `delimiter` that isn't inside a string or char
+/// literal. Delimiters that contain a quote themselves are searched for as is.
+fn split_once_outside_literals<'a>(s: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    if delimiter.contains(['"', '\'']) {
+        return s.split_once(delimiter);
+    }
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut index = 0;
+    while let Some(c) = s[index..].chars().next() {
+        let mut len = c.len_utf8();
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if s[index..].starts_with(delimiter) {
+            return Some((&s[..index], &s[index + delimiter.len()..]));
+        } else if c == '"' {
+            in_string = true;
+        } else if c == '\'' {
+            len = char_literal_len(&s[index..]).unwrap_or(len);
+        }
+        index += len;
+    }
+    None
+}
+
+/// The length of the char literal `s` starts with, like `'a'` or `'\n'`.
+/// Lifetimes and apostrophes aren't char literals.
+fn char_literal_len(s: &str) -> Option<usize> {
+    let mut chars = s.char_indices().skip(1);
+    match chars.next()? {
+        (_, '\\') => {
+            // Escapes are at least one character, `'\''` ends at the second quote.
+            chars.next()?;
+            chars.find(|&(_, c)| c == '\'').map(|(i, _)| i + 1)
+        }
+        (_, '\'') => None,
+        _ => match chars.next()? {
+            (i, '\'') => Some(i + 1),
+            _ => None,
+        },
+    }
+}
+
+/// Like [`seperate_str_on_alignments`], but starts over with the first
+/// delimiter after the last one, until the next delimiter isn't found.
+/// At least one delimiter has to be found.
+fn seperate_str_on_repeated_alignments<'a>(
+    s: &'a str,
+    alignment_parts: &'a [String],
+    skip_literals: bool,
+) -> Option<Vec<&'a str>> {
+    let mut parts = Vec::new();
+    let mut rest = s;
+    for delimiter in alignment_parts.iter().cycle() {
+        let Some((cell, after)) = split_once(rest, delimiter, skip_literals) else {
+            break;
+        };
+        parts.push(cell);
+        parts.push(delimiter);
+        rest = after;
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    parts.push(rest);
+    parts.push("\n");
+    Some(parts)
+}
+
+/// Splits `s` into the cells between each of `alignment_parts` in order,
+/// alternating with the delimiters, followed by the text after the last one
+/// and a placeholder for the line ending. Every delimiter has to be found,
+/// outside of string and char literals if `skip_literals` is set.
+fn seperate_str_on_alignments<'a>(
+    s: &'a str,
+    alignment_parts: &'a [String],
+    skip_literals: bool,
+) -> Option<Vec<&'a str>> {
+    let mut parts = Vec::with_capacity(2 * alignment_parts.len() + 2);
+    let mut rest = s;
+    for delimiter in alignment_parts {
+        let (cell, after) = split_once(rest, delimiter, skip_literals)?;
+        parts.push(cell);
+        parts.push(delimiter);
+        rest = after;
+    }
+    parts.push(rest);
+    parts.push("\n");
+    Some(parts)
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::directive::DEFAULT_MARKER;
+
+    fn align_string(s: &str) -> String {
+        AlignOptions::new().align_string(s).unwrap()
+    }
+
+    fn align_string_with_warnings(s: &str, marker: &str, pad_to: Option<usize>, gap: Option<usize>) -> Result<(String, Vec<Warning>), AlignmentError> {
+        AlignOptions::new().marker(marker).pad_to(pad_to).gap(gap).align_string_with_report(s)
+            .map(|(aligned, report)| (aligned, report.warnings))
+    }
+
+    #[test]
+    fn quote_gathering() {
+        assert_eq!(extract_quote(""), "");
+        assert_eq!(extract_quote("\\"), "\\");
+        assert_eq!(extract_quote("\""), "");
+        assert_eq!(extract_quote("\\\""), "\\\"");
+        assert_eq!(extract_quote("=\""), "=");
+        assert_eq!(extract_quote("hello \\\"world\\\"!\""),"hello \\\"world\\\"!");
+    }
+
+    #[test]
+    fn aligning() {
+        assert_eq!(align_string(""), "\n");
+        assert_eq!(align_string("\n"), "\n");
+        assert_eq!(align_string(indoc! {r#"
+            align_by "="aa
+        "#}), indoc! {r#"
+            align_by "="aa
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "="
+            1 = 222
+            111 = 2
+        "#}), indoc! {r#"
+            align_by "="
+            1   = 222
+            111 = 2
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "="
+            1      = 222
+            111 =     2
+        "#}), indoc! {r#"
+            align_by "="
+            1   = 222
+            111 = 2
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "= ;"
+            1 = 222;
+            111 = 2;
+        "#}), indoc! {r#"
+            align_by "= ;"
+            1   = 222;
+            111 = 2  ;
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "="
+            align_by "="
+            1=1
+        "#}), indoc! {r#"
+            align_by "="
+            align_by "="
+            1=1
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "="
+            1=1
+            22=2
+        "#}), indoc! {r#"
+            align_by "="
+            1 =1
+            22=2
+        "#});
+    }
+
+    #[test]
+    fn column_groups() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by "=" then right ";"
+            a = 1;
+            bbb = 222;
+        "#}), indoc! {r#"
+            align_by "=" then right ";"
+            a   =   1;
+            bbb = 222;
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "=" then right "//"
+            a = 1, // one
+            bbb = 222, // two hundred
+        "#}), indoc! {r#"
+            align_by "=" then right "//"
+            a   =   1, // one
+            bbb = 222, // two hundred
+        "#});
+    }
+
+    #[test]
+    fn shadowed_directives() {
+        let (aligned, warnings) = align_string_with_warnings(indoc! {r#"
+            align_by "="
+            align_by ";"
+            1=1;
+            22=2;
+        "#}, DEFAULT_MARKER, None, None).unwrap();
+        assert_eq!(aligned, indoc! {r#"
+            align_by "="
+            align_by ";"
+            1=1 ;
+            22=2;
+        "#});
+        assert_eq!(warnings, vec![Warning {
+            line: 1,
+            message: "this alignment statement is shadowed by the one on line 2 and aligns nothing".to_string(),
+        }]);
+
+        let (_, warnings) = align_string_with_warnings(indoc! {r#"
+            align_by "="
+            1=1
+            align_by "="
+            1=1
+        "#}, DEFAULT_MARKER, None, None).unwrap();
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn invalid_statements() {
+        assert_eq!(align_string_with_warnings(indoc! {r#"
+            a = 1;
+              // align_by"="
+            a = 1;
+        "#}, DEFAULT_MARKER, None, None), Err(AlignmentError::InvalidAlignmentStatement {
+            line: 2,
+            column: 14,
+            kind: InvalidAlignmentStatement::MissingSpace,
+        }));
+
+        assert_eq!(align_string_with_warnings(indoc! {r#"
+            align_by "="
+            a = 1
+            align_by sort
+        "#}, DEFAULT_MARKER, None, None), Err(AlignmentError::InvalidAlignmentStatement {
+            line: 3,
+            column: 14,
+            kind: InvalidAlignmentStatement::UnexpectedEOF,
+        }));
+    }
+
+    #[test]
+    fn sorting_with_comparators() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort:natural "="
+            item10 = 3
+            item2 = 2
+            item3 = 1
+            item1 = 4
+        "#}), indoc! {r#"
+            align_by sort:natural "="
+            item1  = 4
+            item2  = 2
+            item3  = 1
+            item10 = 3
+        "#});
+        assert_eq!(natural_order("1.9.0", "1.10.0"), std::cmp::Ordering::Less);
+        #[cfg(feature = "icu")]
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort:2 locale:sv "="
+            ae = Äpfel
+            z = Zebra
+            a = apfel
+        "#}), indoc! {r#"
+            align_by sort:2 locale:sv "="
+            a  = apfel
+            z  = Zebra
+            ae = Äpfel
+        "#});
+        assert_eq!(natural_order("a", "a1"), std::cmp::Ordering::Less);
+
+        let mut options = AlignOptions::new();
+        options.comparator("length", |a, b| a.len().cmp(&b.len()));
+        assert_eq!(options.align_string(indoc! {r#"
+            // align_by sort:length:2 desc "="
+            a = 333
+            b = 1
+            c = 22
+        "#}).unwrap(), indoc! {r#"
+            // align_by sort:length:2 desc "="
+            a = 333
+            c = 22
+            b = 1
+        "#});
+        assert_eq!(options, options.clone());
+        assert_ne!(options, AlignOptions::new());
+
+        let err = AlignOptions::new().align_string("a = 1\n// align_by sort:length \"=\"\n").unwrap_err();
+        assert_eq!(err, AlignmentError::UnknownComparator { line: 2, column: 13, name: "length".to_string() });
+        assert_eq!(err.to_string(), "2:13: error[A0017]: unknown comparator `length`, run `cargo align --explain A0017` for more information");
+        // `above` blocks are aligned before their statement is reached.
+        assert!(AlignOptions::new().align_string("b = 1\na = 2\n// align_by above 2 sort:length \"=\"\n").is_err());
+    }
+
+    #[test]
+    fn sorting() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort "="
+            2=2
+            1=1
+        "#}), indoc! {r#"
+            align_by sort "="
+            1=1
+            2=2
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort "="
+            22=2
+            1=1
+        "#}), indoc! {r#"
+            align_by sort "="
+            1 =1
+            22=2
+        "#});
+        
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort "="
+            align_by sort "="
+            22=2
+            1=1
+        "#}), indoc! {r#"
+            align_by sort "="
+            align_by sort "="
+            1 =1
+            22=2
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort desc "="
+            1=1
+            3=3
+            2=2
+        "#}), indoc! {r#"
+            align_by sort desc "="
+            3=3
+            2=2
+            1=1
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort ci "="
+            b=2
+            Foo=3
+            B=1
+            a=4
+        "#}), indoc! {r#"
+            align_by sort ci "="
+            a  =4
+            B  =1
+            b  =2
+            Foo=3
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort:2 "= ;"
+            a = 3; x
+            b = 1; z
+            c = 2; y
+        "#}), indoc! {r#"
+            align_by sort:2 "= ;"
+            b = 1; z
+            c = 2; y
+            a = 3; x
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort:3 desc "= ;"
+            a = 3; x
+            b = 1; z
+            c = 2; y
+        "#}), indoc! {r#"
+            align_by sort:3 desc "= ;"
+            b = 1; z
+            c = 2; y
+            a = 3; x
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort dedup "="
+            b = 1
+            a = 2
+            b  =  1
+            a = 3
+        "#}), indoc! {r#"
+            align_by sort dedup "="
+            a = 2
+            a = 3
+            b = 1
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by dedup "="
+            b = 1
+            a = 2
+            b = 1
+        "#}), indoc! {r#"
+            align_by dedup "="
+            b = 1
+            a = 2
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by sort:2,1 "="
+            z = 1
+            serde = 1
+            anyhow = 2
+            indoc = 1
+            z = 1
+        "#}), indoc! {r#"
+            align_by sort:2,1 "="
+            indoc  = 1
+            serde  = 1
+            z      = 1
+            z      = 1
+            anyhow = 2
+        "#});
+    }
+
+    #[test]
+    fn counted_blocks() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by count 2 "="
+            a = 1
+            bb = 2
+            let ccc = 3;
+        "#}), indoc! {r#"
+            align_by count 2 "="
+            a  = 1
+            bb = 2
+            let ccc = 3;
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by count 5 "="
+            a = 1
+            bb = 2
+
+            ccc = 3
+        "#}), indoc! {r#"
+            align_by count 5 "="
+            a   = 1
+            bb  = 2
+
+            ccc = 3
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            // align_by count 3 "="
+            a = 1
+            let x;
+            bb = 2
+            ccc = 3
+        "#}), indoc! {r#"
+            // align_by count 3 "="
+            a  = 1
+            let x;
+            bb = 2
+            ccc = 3
+        "#});
+    }
+
+    #[test]
+    fn skipped_lines() {
+        assert_eq!(align_string(indoc! {r#"
+            // align_by skip "^//" "="
+            a = 1
+            // bb = 2
+            ccc = 3
+            }
+        "#}), indoc! {r#"
+            // align_by skip "^//" "="
+            a   = 1
+            // bb = 2
+            ccc = 3
+            }
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            // align_by sort dedup skip "^#" "="
+            c = 1
+            # keep
+            b = 1
+            c = 1
+            a = 1
+        "#}), indoc! {r#"
+            // align_by sort dedup skip "^#" "="
+            a = 1
+            # keep
+            b = 1
+            c = 1
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            // align_by count 2 skip "^//" "="
+            // x
+            a = 1
+            bb = 2
+        "#}), indoc! {r#"
+            // align_by count 2 skip "^//" "="
+            // x
+            a = 1
+            bb = 2
+        "#});
+    }
+
+    #[test]
+    fn kept_blank_lines() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by keep_blank "="
+            a = 1
+
+            bbb = 2
+            }
+        "#}), indoc! {r#"
+            align_by keep_blank "="
+            a   = 1
+
+            bbb = 2
+            }
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "="
+            a = 1
+
+            bbb = 2
+        "#}), indoc! {r#"
+            align_by "="
+            a = 1
+
+            bbb = 2
+        "#});
+    }
+
+    #[test]
+    fn trimming() {
+        let input = "align_by keep_blank skip \"#\" \"=\"\na = 1\n  \n# x  \nbbb = 2\n";
+        assert_eq!(align_string(input), "align_by keep_blank skip \"#\" \"=\"\na   = 1\n  \n# x  \nbbb = 2\n");
+        let input = "align_by keep_blank skip \"#\" trim \"=\"\r\na = 1\r\n  \r\n# x  \r\nbbb = 2\r\n";
+        assert_eq!(align_string(input), "align_by keep_blank skip \"#\" trim \"=\"\r\na   = 1\r\n\r\n# x\r\nbbb = 2\r\n");
+
+        let mut options = AlignOptions::new();
+        options.trim_trailing(true);
+        assert_eq!(options.align_string("align_by skip \"#\" \"=\"\n# x \n").unwrap(), "align_by skip \"#\" \"=\"\n# x\n");
+        assert_eq!(options.align_string("x \ny\n").unwrap(), "x \ny\n");
+    }
+
+    #[test]
+    fn wrapping() {
+        let aligned = indoc! {r#"
+            // align_by wrap=40 "=" "//"
+            let a   = 1; // the first value, which
+                            has a long comment after
+                            it
+            let bbb = 2; // short
+            }
+        "#};
+        assert_eq!(align_string(indoc! {r#"
+            // align_by wrap=40 "=" "//"
+            let a = 1; // the first value, which has a long comment after it
+            let bbb = 2; // short
+            }
+        "#}), aligned);
+        assert_eq!(align_string(aligned), aligned);
+
+        // Wrapped lines that would split on the delimiters are left long.
+        let long = indoc! {r#"
+            // align_by wrap=15 "=" "//"
+            a = 1 // see b=2//c
+        "#};
+        assert_eq!(align_string(long), long);
+
+        // Lines that aren't indented to the text after the last delimiter end the block.
+        assert_eq!(align_string(indoc! {r#"
+            // align_by raw wrap=20 "="
+                a = one two
+                bbb = three four five six
+                  x
+        "#}), indoc! {r#"
+            // align_by raw wrap=20 "="
+                a   = one two
+                bbb = three four
+                      five six
+                  x
+        "#});
+
+        // Continuation lines of ordinary code are left alone, even if they
+        // line up with the text after the last delimiter.
+        let code = indoc! {r#"
+            // align_by wrap=80 "="
+            let x = foo(
+                    bar);
+            let yy = baz(
+                qux,
+            );
+        "#};
+        assert_eq!(align_string(code), code);
+
+        // Hanging indents can be tabs as far as they reach.
+        let mut options = AlignOptions::new();
+        options.tab_width(Some(4)).indent_with_tabs(true);
+        let tabs = "// align_by wrap=20 \"=\"\nab = one two three four\n";
+        let aligned = options.align_string(tabs).unwrap();
+        assert_eq!(aligned, "// align_by wrap=20 \"=\"\nab = one two three\n\t four\n");
+        assert_eq!(options.align_string(&aligned).unwrap(), aligned);
+    }
+
+    #[test]
+    fn grouped_blocks() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by groups sort "="
+            bb = 1
+            a = 2
+
+            ccc = 3
+            dddd = 4
+            }
+        "#}), indoc! {r#"
+            align_by groups sort "="
+            a  = 2
+            bb = 1
+
+            ccc  = 3
+            dddd = 4
+            }
+        "#});
+    }
+
+    #[test]
+    fn sorting_in_runs() {
+        assert_eq!(align_string(indoc! {r#"
+            // align_by sort runs "^//" "="
+            c = 1
+            a = 2
+            // Colors
+            red = 3
+            blue = 4
+            // Sizes
+            small = 5
+            large = 6
+            }
+        "#}), indoc! {r#"
+            // align_by sort runs "^//" "="
+            a     = 2
+            c     = 1
+            // Colors
+            blue  = 4
+            red   = 3
+            // Sizes
+            large = 6
+            small = 5
+            }
+        "#});
+
+        // Runs are sorted within each group of `groups`.
+        assert_eq!(align_string(indoc! {r#"
+            align_by groups sort desc runs "--" "="
+            a = 1
+            -- x
+            b = 3
+            c = 2
+
+            d = 5
+            e = 4
+        "#}), indoc! {r#"
+            align_by groups sort desc runs "--" "="
+            a = 1
+            -- x
+            c = 2
+            b = 3
+
+            e = 4
+            d = 5
+        "#});
+    }
+
+    #[test]
+    fn named_groups() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by group(fields) "= ;"
+            a = 1;
+            b = 22;
+            }
+            align_by "="
+            cccc = 3
+            }
+            align_by group(fields) "= ;"
+            ccc = 3;
+        "#}), indoc! {r#"
+            align_by group(fields) "= ;"
+            a   = 1 ;
+            b   = 22;
+            }
+            align_by "="
+            cccc = 3
+            }
+            align_by group(fields) "= ;"
+            ccc = 3 ;
+        "#});
+    }
+
+    #[test]
+    fn gaps() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by gap=2 "= //"
+            a = 1 // one
+            bbb=22 // two
+        "#}), indoc! {r#"
+            align_by gap=2 "= //"
+            a    = 1  // one
+            bbb  =22  // two
+        "#});
+
+        assert_eq!(align_string_with_warnings(indoc! {r#"
+            align_by "=" then right "//"
+            a = 1 // one
+            bbb = 22 // two
+        "#}, DEFAULT_MARKER, None, Some(0)).unwrap().0, indoc! {r#"
+            align_by "=" then right "//"
+            a  =  1// one
+            bbb= 22// two
+        "#});
+    }
+
+    #[test]
+    fn column_positions() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by columns "6 12" "= //"
+            a = 1 // one
+            bb = 22 // two
+        "#}), indoc! {r#"
+            align_by columns "6 12" "= //"
+            a     = 1   // one
+            bb    = 22  // two
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by columns "2" "="
+            long = 1
+            a = 2
+        "#}), indoc! {r#"
+            align_by columns "2" "="
+            long = 1
+            a    = 2
+        "#});
+    }
+
+    #[test]
+    fn repeated_delimiters() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by all "|"
+            | a | bb | c |
+            | aaa | b |
+            | x |
+        "#}), indoc! {r#"
+            align_by all "|"
+            | a   | bb | c |
+            | aaa | b  |
+            | x   |
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by all ", ;"
+            a, b; c, dd; e
+            aaa, bbb; c
+        "#}), indoc! {r#"
+            align_by all ", ;"
+            a  , b  ; c, dd; e
+            aaa, bbb; c
+        "#});
+    }
+
+    #[test]
+    fn side_suffixes() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by "=":left ";":right
+            a = 1; // one
+            bbb = 22; // two
+        "#}), indoc! {r#"
+            align_by "=":left ";":right
+            a   =  1; // one
+            bbb = 22; // two
+        "#});
+    }
+
+    #[test]
+    fn centering() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by center all "|"
+            | Name | Value |
+            | a | 1 |
+        "#}), indoc! {r#"
+            align_by center all "|"
+            | Name | Value |
+            |  a   |   1   |
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "=" then center ";"
+            a = 1;
+            bb = 333;
+        "#}), indoc! {r#"
+            align_by "=" then center ";"
+            a  =  1 ;
+            bb = 333;
+        "#});
+    }
+
+    #[test]
+    fn max_widths() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by max=6 "= ;"
+            a = 1;
+            bb = 2;
+            a_very_long_name = 3;
+            ccc = 4;
+        "#}), indoc! {r#"
+            align_by max=6 "= ;"
+            a   = 1;
+            bb  = 2;
+            a_very_long_name = 3;
+            ccc = 4;
+        "#});
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by max=6 "=" then decimal ";"
+            a = 1.5;
+            b = 22.25;
+            c = 123456.7;
+        "#}), indoc! {r#"
+            align_by max=6 "=" then decimal ";"
+            a =  1.5 ;
+            b = 22.25;
+            c = 123456.7;
+        "#});
+    }
+
+    #[test]
+    fn raw_cells() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by raw "= //"
+                a   =  f(x,   y) // keep   this
+                bbb     = 1 //  and this
+        "#}), indoc! {r#"
+            align_by raw "= //"
+                a   = f(x,   y) // keep   this
+                bbb = 1         //  and this
+        "#});
+
+        assert_eq!(collapse_edges("  a  b "), " a  b ");
+        assert_eq!(collapse_edges("   "), " ");
+        assert_eq!(collapse_edges(""), "");
+    }
+
+    #[test]
+    fn separating() {
+        let parts = |delimiters: &[&str]| delimiters.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        assert_eq!(seperate_str_on_alignments("a = b; c", &parts(&["=", ";"]), true).unwrap(), ["a ", "=", " b", ";", " c", "\n"]);
+        assert_eq!(seperate_str_on_alignments("a = b", &[], true).unwrap(), ["a = b", "\n"]);
+        assert_eq!(seperate_str_on_alignments("", &parts(&["="]), true), None);
+        assert_eq!(seperate_str_on_alignments("=", &parts(&["="]), true).unwrap(), ["", "=", "", "\n"]);
+        assert_eq!(seperate_str_on_alignments("a = b", &parts(&["=", "="]), true), None);
+        assert_eq!(seperate_str_on_alignments("a \"=\" b", &parts(&["="]), true), None);
+        assert_eq!(seperate_str_on_alignments("a \"=\" b", &parts(&["="]), false).unwrap(), ["a \"", "=", "\" b", "\n"]);
+        assert_eq!(seperate_str_on_repeated_alignments("a, b, c", &parts(&[","]), true).unwrap(), ["a", ",", " b", ",", " c", "\n"]);
+        assert_eq!(seperate_str_on_repeated_alignments("a", &parts(&[","]), true), None);
+
+        // Long delimiter lists are split in one pass, and a missing delimiter at
+        // the very end still fails the whole line.
+        let line = (0..100_000).map(|i| i.to_string()).collect::<Vec<_>>().join(",");
+        let delimiters = vec![",".to_string(); 99_999];
+        let separated = seperate_str_on_alignments(&line, &delimiters, true).unwrap();
+        assert_eq!(separated.len(), 200_000);
+        assert_eq!(separated[199_998], "99999");
+        assert_eq!(seperate_str_on_alignments(&line, &vec![",".to_string(); 100_000], true), None);
+        let quotes = "\"".repeat(100_001);
+        assert_eq!(seperate_str_on_alignments(&quotes, &delimiters, true), None);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(split_once_outside_literals(r#"a = "b = c""#, "="), Some(("a ", r#" "b = c""#)));
+        assert_eq!(split_once_outside_literals(r#"f("a, \"b", c)"#, ","), Some((r#"f("a, \"b""#, " c)")));
+        assert_eq!(split_once_outside_literals("x(',', '\\'', y)", ","), Some(("x(','", " '\\'', y)")));
+        assert_eq!(split_once_outside_literals("fn f<'a>(x: &'a str, y)", ","), Some(("fn f<'a>(x: &'a str", " y)")));
+        assert_eq!(split_once_outside_literals(r#"say "hi", there"#, "\""), Some(("say ", r#"hi", there"#)));
+        assert_eq!(split_once_outside_literals(r#""a = b""#, "="), None);
+
+        assert_eq!(align_string(indoc! {r#"
+            align_by "= ;"
+            let a = "b=c";
+            let bbb = 2;
+        "#}), indoc! {r#"
+            align_by "= ;"
+            let a   = "b=c";
+            let bbb = 2    ;
+        "#});
+
+        // Without literals, quotes are just text.
+        assert_eq!(AlignOptions::new().skip_literals(false).align_string(indoc! {r#"
+            align_by ":"
+            5" screen: yes
+            keyboard: no
+        "#}).unwrap(), indoc! {r#"
+            align_by ":"
+            5" screen: yes
+            keyboard : no
+        "#});
+    }
+
+    #[test]
+    fn cancelled_blocks() {
+        assert_eq!(align_string_with_warnings(indoc! {r#"
+            align_by "="
+            a = 1
+            bbb = 2
+            // align_by cancel_block
+            align_by "="
+            a = 1
+            bbb = 2
+            align_by table
+            | a | b |
+            | - | - |
+            // align_by cancel_block
+        "#}, DEFAULT_MARKER, None, None).unwrap(), (indoc! {r#"
+            align_by "="
+            a = 1
+            bbb = 2
+            // align_by cancel_block
+            align_by "="
+            a   = 1
+            bbb = 2
+            align_by table
+            | a | b |
+            | - | - |
+            // align_by cancel_block
+        "#}.to_string(), vec![]));
+    }
+
+    #[test]
+    fn blocks_above() {
+        assert_eq!(align_string(indoc! {r#"
+            let a = Foo {
+                a: 1,
+                bbb: 2,
+            }; // align_by above 2 ":"
+            x = 1
+            yyy = 2
+            align_by "="
+            a = 1
+            bb = 2
+            c = 3
+            // align_by above 2 "="
+        "#}), indoc! {r#"
+            let a = Foo {
+            a  : 1,
+            bbb: 2,
+            }; // align_by above 2 ":"
+            x = 1
+            yyy = 2
+            align_by "="
+            a = 1
+            bb = 2
+            c  = 3
+            // align_by above 2 "="
+        "#});
+    }
+
+    #[test]
+    fn reordered_cells() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by reorder "3 1 2" ", ,"
+            a, b, ccc
+            aaaa, b, c
+        "#}), indoc! {r#"
+            align_by reorder "3 1 2" ", ,"
+            ccc, a   , b
+            c  , aaaa, b
+        "#});
+        assert_eq!(align_string(indoc! {r#"
+            align_by all reorder "2 1" ","
+            a, bbb, c
+            dd, e
+        "#}), indoc! {r#"
+            align_by all reorder "2 1" ","
+            bbb, a , c
+            e  , dd
+        "#});
+    }
+
+    #[test]
+    fn strict_checking() {
+        assert_eq!(check_strict(indoc! {r#"
+            // align_by "="
+            // the \align_by marker
+            // align_by stop
+            // the align_by marker
+        "#}, DEFAULT_MARKER, &directive::BUILTIN_MODES), Ok(()));
+        let err = check_strict("a = 1\n  // align_by sotr \"=\"\n", DEFAULT_MARKER, &directive::BUILTIN_MODES).unwrap_err();
+        assert_eq!(err, AlignmentError::UnknownKeyword { line: 2, column: 15, word: "sotr".to_string() });
+        assert_eq!(err.to_string(), "2:15: error[A0016]: unknown keyword `sotr` (did you mean `sort`?), run `cargo align --explain A0016` for the list of keywords");
+        assert_eq!(check_strict("// align_by count lines\n", DEFAULT_MARKER, &directive::BUILTIN_MODES), Err(AlignmentError::InvalidAlignmentStatement {
+            line: 1,
+            column: 19,
+            kind: InvalidAlignmentStatement::InvalidCount,
+        }));
+        assert!(AlignOptions::new().strict(true).align_string("// align_by count lines\n").is_err());
+        assert!(AlignOptions::new().align_string("// align_by count lines\n").is_ok());
+    }
+
+    #[test]
+    fn reporting() {
+        let (_, report) = AlignOptions::new().align_string_with_report(indoc! {r#"
+            // align_by "="
+            a = 1
+            bbb = 2
+
+            // align_by "="
+            c = 3
+            d = 4
+            e = 5
+            // align_by above 1 "="
+            // align_by table
+            | a | bb |
+            |---|----|
+            // align_by "="
+            // align_by "="
+            f = 6
+        "#}).unwrap();
+        assert_eq!(report.statements.iter().map(|(line, _)| *line).collect::<Vec<_>>(), [1, 5, 9, 10, 13, 14]);
+        assert_eq!(report.statements[2].1.above, Some(1));
+        assert_eq!(report.blocks, [
+            Block { statement_line: 1, lines: 2..=3, changed: true },
+            Block { statement_line: 5, lines: 6..=7, changed: false },
+            Block { statement_line: 9, lines: 8..=8, changed: false },
+            Block { statement_line: 10, lines: 11..=12, changed: true },
+            Block { statement_line: 14, lines: 15..=15, changed: false },
+        ]);
+        assert_eq!(report.warnings, [Warning {
+            line: 13,
+            message: "this alignment statement is shadowed by the one on line 14 and aligns nothing".to_string(),
+        }]);
+        // A missing line ending at the end isn't a change of the block.
+        let (_, report) = AlignOptions::new().align_string_with_report("// align_by \"=\"\na = 1").unwrap();
+        assert_eq!(report.blocks, [Block { statement_line: 1, lines: 2..=2, changed: false }]);
+    }
+
+    #[test]
+    fn editing() {
+        fn apply(original: &str, aligned: &str, edits: &[Edit]) -> String {
+            let mut applied = String::new();
+            let mut position = 0;
+            for edit in edits {
+                assert!(position <= edit.original.start);
+                applied.push_str(&original[position..edit.original.start]);
+                applied.push_str(&aligned[edit.aligned.clone()]);
+                position = edit.original.end;
+            }
+            applied + &original[position..]
+        }
+
+        let original = "// align_by \"=\"\na = 1\nbbb = 2\n";
+        let (aligned, report) = AlignOptions::new().align_string_with_report(original).unwrap();
+        assert_eq!(report.edits, [Edit { original: 18..18, aligned: 18..20 }]);
+        assert_eq!(apply(original, &aligned, &report.edits), aligned);
+
+        for original in [
+            "",
+            "a = 1",
+            "// align_by \"=\"\na = 1\nbbb = 2",
+            "\u{FEFF}// align_by \"=\"\r\né = 1\r\nbbb  =  2\r\n\r\n\r\n",
+            "// align_by dedup \"=\"\nb = 1\na  = 1\na = 1\nc = 2\n",
+            "x\n// align_by \"=\"\na = 1  \nbbb = 2  \n   \n",
+            "a = 1\nbbb = 2\n// align_by above 2 \"=\"\n// align_by table\n|a|bb|\n|-|-|\n",
+            "// align_by group(g) \"=\"\na = 1\n\nfn f() {}\n// align_by group(g) \"=\"\nbbbb = 2\n",
+            "// align_by \"=\"\na = 1\nbb = 2\n// align_by cancel_block\n",
+            "a = 1\r\nb = 2\n\n  \n",
+        ] {
+            let (aligned, report) = AlignOptions::new().align_string_with_report(original).unwrap();
+            assert_eq!(apply(original, &aligned, &report.edits), aligned, "{original:?}");
+        }
+    }
+
+    #[test]
+    fn measuring_widths() {
+        let text = indoc! {"
+            // align_by \"=\"
+            é = 1
+            ab = 2
+        "};
+        assert_eq!(AlignOptions::new().align_string(text).unwrap(), text);
+        assert_eq!(AlignOptions::new().unicode(true).align_string(text).unwrap(), indoc! {"
+            // align_by \"=\"
+            é  = 1
+            ab = 2
+        "});
+        // Only raw statements keep the tabs inside a cell.
+        let text = "// align_by raw \"=\"\na\tb = 1\nabcdef = 2\n";
+        assert_eq!(AlignOptions::new().align_string(text).unwrap(), "// align_by raw \"=\"\na\tb    = 1\nabcdef = 2\n");
+        assert_eq!(AlignOptions::new().tab_width(Some(4)).align_string(text).unwrap(), "// align_by raw \"=\"\na\tb  = 1\nabcdef = 2\n");
+        assert_eq!(AlignOptions::new().tab_width(Some(8)).align_string(text).unwrap(), "// align_by raw \"=\"\na\tb = 1\nabcdef    = 2\n");
+    }
+
+    #[test]
+    fn escaped_markers() {
+        assert_eq!(align_string(indoc! {r#"
+            // \align_by stop is literal
+            align_by "="
+            a = 1
+            bbb = 2
+            // \align_by "=" is literal
+            a = 1
+            bbb = 2
+        "#}), indoc! {r#"
+            // \align_by stop is literal
+            align_by "="
+            a   = 1
+            bbb = 2
+            // \align_by "=" is literal
+            a = 1
+            bbb = 2
+        "#});
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        assert_eq!(align_string("\u{FEFF}"), "\u{FEFF}\n");
+        assert_eq!(align_string("\u{FEFF}align_by \"=\"\n1 = 222\n111 = 2\n"), "\u{FEFF}align_by \"=\"\n1   = 222\n111 = 2\n");
+        assert_eq!(align_string("\u{FEFF}1\n"), "\u{FEFF}1\n");
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(align_string("a\r\n"), "a\r\n");
+        assert_eq!(align_string("a\r\nb"), "a\r\nb\r\n");
+        assert_eq!(align_string("a\r\nb\nc\r\n"), "a\r\nb\nc\r\n");
+        assert_eq!(
+            align_string("align_by \"=\"\r\n1 = 222\n111 = 2\r\n"),
+            "align_by \"=\"\r\n1   = 222\n111 = 2\r\n"
+        );
+        assert_eq!(
+            align_string("align_by sort \"=\"\r\n2 = 2\r\n1 = 1"),
+            "align_by sort \"=\"\r\n1 = 1\r\n2 = 2\r\n"
+        );
+        assert_eq!(detect_newline_style(""), "\n");
+        assert_eq!(detect_newline_style("a\r\nb\r\nc\n"), "\r\n");
+        assert_eq!(detect_newline_style("a\r\nb\nc\n"), "\n");
+        // Terminators at the very end count, and a lone `\r` isn't one.
+        assert_eq!(detect_newline_style("\r\n"), "\r\n");
+        assert_eq!(detect_newline_style("a\r\nb\r\nc\n\n"), "\n");
+        assert_eq!(detect_newline_style("a\r\nb\r"), "\r\n");
+        assert_eq!(detect_newline_style("\n\r"), "\n");
+    }
+
+    #[test]
+    fn stop() {
+        assert_eq!(align_string(indoc! {r#"
+            1
+            2
+            3
+            4
+            5
+            align_by stop
+        "#}), indoc! {r#"
+            1
+            2
+            3
+            4
+            5
+            align_by stop
+        "#});
+    }
+
+    #[test]
+    fn custom_marker() {
+        assert_eq!(align_string_with_warnings(indoc! {r#"
+            #align "="
+            a = 1
+            bbb = 2
+
+            align_by "="
+            a = 1
+            bbb = 2
+            #align stop
+            #align "="
+            a = 1
+            bbb = 2
+        "#}, "#align", None, None).unwrap().0, indoc! {r#"
+            #align "="
+            a   = 1
+            bbb = 2
+
+            align_by "="
+            a = 1
+            bbb = 2
+            #align stop
+            #align "="
+            a = 1
+            bbb = 2
+        "#});
+    }
+
+    #[test]
+    fn pad_rounding() {
+        assert_eq!(align_string_with_warnings(indoc! {r#"
+            align_by "=" then right ";"
+            a = 1;
+            bbbb = 22;
+        "#}, DEFAULT_MARKER, Some(4), None).unwrap().0, indoc! {r#"
+            align_by "=" then right ";"
+            a       =  1;
+            bbbb    = 22;
+        "#});
+    }
+
+    #[test]
+    fn decimal_alignment() {
+        assert_eq!(align_string(indoc! {r#"
+            align_by "=" then decimal ";"
+            a = 1.5;
+            bb = 10.25;
+            c = 100;
+            d = -0.125;
+        "#}), indoc! {r#"
+            align_by "=" then decimal ";"
+            a  =   1.5  ;
+            bb =  10.25 ;
+            c  = 100    ;
+            d  =  -0.125;
+        "#});
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(align_string(indoc! {r#"
+            /// align_by table
+            /// | a | b |
+            /// |-|:-:|
+            /// | ccc | d |
+            ///
+            /// | e | f |
+        "#}), indoc! {r#"
+            /// align_by table
+            /// | a   |  b  |
+            /// | --- | :-: |
+            /// | ccc |  d  |
+            ///
+            /// | e | f |
+        "#});
+    }
+
+    #[test]
+    fn limiting() {
+        let long = format!("// align_by \"=\"\na = 1\nb = {}\n", "1".repeat(DEFAULT_MAX_LINE_LENGTH));
+        let err = AlignOptions::new().align_string(&long).unwrap_err();
+        assert_eq!(err, AlignmentError::LineTooLong { line: 3, length: DEFAULT_MAX_LINE_LENGTH + 4, limit: DEFAULT_MAX_LINE_LENGTH });
+        assert_eq!(err.code(), "A0018");
+        assert!(AlignOptions::new().max_line_length(None).align_string(&long).is_ok());
+        // Lines outside of blocks can be as long as they are.
+        let outside = format!("{}\n// align_by \"=\"\na = 1\n\n{}\n", "x".repeat(20_000), "x".repeat(20_000));
+        assert!(AlignOptions::new().align_string(&outside).is_ok());
+        // Neither can the line ending a block, which isn't part of it.
+        let after = format!("// align_by \"=\"\na = 1\n{}\n", "x".repeat(12_000));
+        assert!(AlignOptions::new().align_string(&after).is_ok());
+
+        let mut options = AlignOptions::new();
+        options.max_block_lines(Some(2));
+        assert!(options.align_string("// align_by \"=\"\na = 1\nb = 2\nc\n").is_ok());
+        let err = options.align_string("x\n// align_by \"=\"\na = 1\nb = 2\nc = 3\n").unwrap_err();
+        assert_eq!(err, AlignmentError::BlockTooLong { line: 2, limit: 2 });
+        assert_eq!(err.to_string(), "2:1: error[A0019]: the block has more than 2 lines, run `cargo align --explain A0019` for more information");
+        assert_eq!(options.align_string("// align_by table\n|a|\n|-|\n|b|\n").unwrap_err(), AlignmentError::BlockTooLong { line: 1, limit: 2 });
+        assert_eq!(options.max_line_length(Some(3)).align_string("// align_by table\n|a|\n|bb|\n").unwrap_err().line(), 3);
+    }
+
+    struct Upper;
+
+    impl Mode for Upper {
+        fn transform(&self, lines: &[&str]) -> Vec<String> {
+            lines.iter().map(|line| line.to_uppercase()).collect()
+        }
+    }
+
+    struct Reverse;
+
+    impl Mode for Reverse {
+        fn continues(&self, line: &str) -> bool {
+            line.starts_with('-')
+        }
+
+        fn transform(&self, lines: &[&str]) -> Vec<String> {
+            lines.iter().rev().skip(1).map(|line| line.to_string()).collect()
+        }
+    }
+
+    #[test]
+    fn registering_modes() {
+        let input = "// align_by upper
+a = 1
+b = 2
+
+c = 3
+";
+        assert_eq!(AlignOptions::new().align_string(input).unwrap(), input);
+        let mut options = AlignOptions::new();
+        options.mode("upper", Upper).mode("reverse", Reverse);
+        let (aligned, report) = options.align_string_with_report(input).unwrap();
+        assert_eq!(aligned, "// align_by upper
+A = 1
+B = 2
+
+c = 3
+");
+        assert_eq!(report.statements[0].1.mode(), "upper");
+        assert_eq!(report.blocks, [Block { statement_line: 1, lines: 2..=3, changed: true }]);
+
+        // Blocks end at the next statement, and can change their number of lines.
+        let input = "align_by reverse
+-a
+-b
+-c
+align_by upper
+d";
+        let (aligned, report) = options.align_string_with_report(input).unwrap();
+        assert_eq!(aligned, "align_by reverse
+-b
+-a
+align_by upper
+D
+");
+        assert_eq!(report.edits[0], Edit { original: 18..25, aligned: 18..22 });
+        assert_eq!(options.parse_directive("# align_by reverse").unwrap().unwrap().block_mode.as_deref(), Some("reverse"));
+        assert_eq!(options.parse_directive("# align_by reverse \"=\""), Ok(None));
+
+        // Registered modes aren't unknown keywords, and can replace `table`.
+        assert_eq!(options.strict(true).align_string("// align_by upper
+a").unwrap(), "// align_by upper
+A
+");
+        options.mode("table", Upper);
+        assert_eq!(options.align_string("align_by table
+|a|
+").unwrap(), "align_by table
+|A|
+");
+        assert!(AlignOptions::new().strict(true).align_string("// align_by upper
+a").is_err());
+    }
+}
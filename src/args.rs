@@ -0,0 +1,1094 @@
+use crate::directive::DEFAULT_MARKER;
+use crate::encoding::Encoding;
+use anyhow::Result;
+use cargo_align::align::AlignOptions;
+use cargo_align::align::DEFAULT_MAX_BLOCK_LINES;
+use cargo_align::align::DEFAULT_MAX_LINE_LENGTH;
+use cargo_align::discovery::DEFAULT_FILESIZE_LIMIT;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Extensions of the languages with string and char literals, whose files
+/// don't align on delimiters inside of them.
+const LITERAL_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cjs", "cpp", "cs", "dart", "go", "h", "hpp", "java", "js", "json", "jsx", "kt",
+    "lua", "mjs", "php", "py", "rb", "rs", "scala", "swift", "toml", "ts", "tsx", "yaml", "yml",
+];
+
+/// Whether the file at `file_path` is in a language with string and char literals.
+pub fn has_literals(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| LITERAL_EXTENSIONS.contains(&extension))
+}
+
+/// Printed for `--help`.
+pub const HELP: &str = "\
+Aligns blocks of code marked with alignment statements, ie `// align_by \"=\"`.
+
+Usage: cargo align [OPTIONS] [PATH] [GLOBS]...
+       cargo align <COMMAND> [OPTIONS] [PATH]
+
+PATH is a file or folder to align, the workspace root by default. GLOBS,
+like 'src/**/*.rs', only align the matching files under it.
+
+Commands:
+  rename-marker  Rename the alignment marker in every file, with --from and --to
+  snapshot       Write aligned copies to --out, or compare with --verify-snapshots
+  stats          Count misaligned blocks, over git history with --history and --commits
+  list           Print every alignment statement, as JSON lines with --json
+  revert         Restore the backups written by --backup
+
+Options:
+      --diff                    Print what would change instead of writing files
+      --emit <files|stdout|new-files>
+                                Where aligned content is written to
+  -i, --interactive             Ask before applying each changed block
+      --backup                  Copy files to <file>.orig before overwriting them
+      --preserve-mtime          Keep the modification time of aligned files
+      --readonly <skip|warn|error>
+                                What to do with read-only files that need aligning
+      --file <FILE>             Align this file instead of walking PATH, repeatable
+  -p, --package <NAME>          Only align this workspace member, repeatable
+      --include-non-members     Also align excluded and vendored crates
+      --stdin-filepath <FILE>   Align stdin to stdout as if it was FILE
+      --daemon                  Serve editors over target/cargo-align.sock
+      --log-format <text|json>  How --daemon logs requests
+      --allow-no-vcs            Align outside of version control without asking
+      --incremental             Skip files that were aligned on the last run
+      --fail-fast               Stop at the first file with an error
+      --no-ignore               Don't read any ignore files
+      --no-git-ignore           Don't read .gitignore files
+      --no-follow               Don't walk into symlinks
+      --max-depth <N>           Only walk N folders deep
+      --ext <EXT,...>           Only align files with these extensions
+      --filesize-limit <SIZE>   Skip larger files, `none` for no limit
+      --encoding <ENCODING>     Read and write files in this encoding
+      --rustfmt                 Format Rust files with rustfmt before aligning
+      --no-editorconfig         Ignore .editorconfig files
+      --marker <MARKER>         The word that starts alignment statements
+      --pad-to <N>              Round aligned columns up to a multiple of N
+      --gap <N>                 The least number of spaces before a delimiter
+      --tab-width <N>           Count tabs as reaching the next multiple of N columns
+      --unicode                 Measure cells in characters instead of bytes
+      --trim-trailing           Remove whitespace at the end of every line of a block
+      --max-line-length <N>     Reject blocks with longer lines, `none` for no limit
+      --max-block-lines <N>     Reject blocks with more lines, `none` for no limit
+      --sample <PERCENT>        Only align this percentage of the changed files
+      --seed <N>                Picks which files are in the sample
+      --strict                  Reject markers that don't start a valid statement
+      --expand-macros           Warn about blocks inside macro invocations
+      --warn-formatters         Warn about blocks a formatter may undo
+      --explain <CODE>          Describe an error code
+      --stats                   Print metrics of the run
+      --report <FILE>           Write what happened to each file as JSON or TOML
+      --output-format <human|checkstyle>
+                                Report problems as Checkstyle XML without writing
+      --error-format <human|short|json>
+                                How malformed alignment statements are printed
+      --no-error-on-unaligned   Exit with 0 when --diff finds unaligned files
+      --color <auto|always|never>
+                                When to color output
+  -j, --jobs <N>                The most threads to align files on
+  -q, --quiet                   Don't show progress or the summary
+  -h, --help                    Print this help
+  -V, --version                 Print the version
+";
+
+/// The arguments of the process after the binary name, without the `align`
+/// cargo passes first when it runs `cargo align`.
+pub fn cargo_args(args: impl Iterator<Item = String>) -> impl Iterator<Item = String> {
+    let mut args = args.skip(1).peekable();
+    args.next_if_eq("align");
+    args
+}
+
+/// Where aligned content is written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// Overwrite the original files.
+    Files,
+    /// Print aligned content without writing anything.
+    Stdout,
+    /// Write `foo.rs.aligned` beside the original `foo.rs`.
+    NewFiles,
+}
+
+impl std::str::FromStr for Emit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Emit> {
+        match s {
+            "files" => Ok(Emit::Files),
+            "stdout" => Ok(Emit::Stdout),
+            "new-files" => Ok(Emit::NewFiles),
+            _ => anyhow::bail!(
+                "Unknown emit mode `{s}`, expected one of `files`, `stdout`, `new-files`"
+            ),
+        }
+    }
+}
+
+/// Parses a size limit like `2M` or `512KiB` into bytes, `0` and `none`
+/// disable the limit. `K`, `M`, `G` and `KiB`, `MiB`, `GiB` are powers of
+/// 1024, `KB`, `MB`, `GB` powers of 1000. Suffixes are case insensitive.
+pub fn parse_filesize(value: &str) -> Option<Option<u64>> {
+    if value.eq_ignore_ascii_case("none") {
+        return Some(None);
+    }
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let number = number.parse::<u64>().ok()?;
+    let multiplier: u64 = match suffix.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        _ => return None,
+    };
+    let bytes = number.checked_mul(multiplier)?;
+    Some((bytes > 0).then_some(bytes))
+}
+
+/// Parses a positive limit, `none` disables it.
+fn parse_limit(value: &str) -> Option<Option<usize>> {
+    if value.eq_ignore_ascii_case("none") {
+        return Some(None);
+    }
+    value.parse().ok().filter(|&limit| limit > 0).map(Some)
+}
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Diffs and summaries for people.
+    Human,
+    /// A Checkstyle XML report of the misaligned blocks and invalid
+    /// statements, without changing any files.
+    Checkstyle,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<OutputFormat> {
+        match s {
+            "human" => Ok(OutputFormat::Human),
+            "checkstyle" => Ok(OutputFormat::Checkstyle),
+            _ => {
+                anyhow::bail!("Unknown output format `{s}`, expected one of `human`, `checkstyle`")
+            }
+        }
+    }
+}
+
+/// When output is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// When writing to a terminal and `NO_COLOR` isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl std::str::FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Color> {
+        match s {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => {
+                anyhow::bail!("Unknown color mode `{s}`, expected one of `auto`, `always`, `never`")
+            }
+        }
+    }
+}
+
+/// How `--daemon` logs what it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// A timestamp, the level, and the message on a line.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<LogFormat> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => anyhow::bail!("Unknown log format `{s}`, expected one of `text`, `json`"),
+        }
+    }
+}
+
+/// How malformed alignment statements are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// A sentence with a hint to `--explain`.
+    Human,
+    /// `file:line:column: code: message`, for Vim's quickfix and Emacs'
+    /// compile mode.
+    Short,
+    /// One JSON object per error.
+    Json,
+}
+
+impl std::str::FromStr for ErrorFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ErrorFormat> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "short" => Ok(ErrorFormat::Short),
+            "json" => Ok(ErrorFormat::Json),
+            _ => anyhow::bail!(
+                "Unknown error format `{s}`, expected one of `human`, `short`, `json`"
+            ),
+        }
+    }
+}
+
+/// What happens to read-only files that need aligning. Only matters when
+/// files are overwritten, checks report them as unaligned like any other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadOnly {
+    /// Leave them unaligned and count them in the summary.
+    Skip,
+    /// Leave them unaligned with a warning for each.
+    Warn,
+    /// Fail like any other file that can't be written.
+    Error,
+}
+
+impl std::str::FromStr for ReadOnly {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ReadOnly> {
+        match s {
+            "skip" => Ok(ReadOnly::Skip),
+            "warn" => Ok(ReadOnly::Warn),
+            "error" => Ok(ReadOnly::Error),
+            _ => anyhow::bail!(
+                "Unknown read-only policy `{s}`, expected one of `skip`, `warn`, `error`"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Align,
+    /// Rewrite the directive marker `from` into `to` in every file.
+    RenameMarker {
+        from: String,
+        to: String,
+    },
+    /// Print the long description of an error code.
+    Explain(String),
+    /// Record aligned outputs as golden files in `out`, or compare against them.
+    Snapshot {
+        out: PathBuf,
+        verify: bool,
+    },
+    /// Count misaligned blocks, for each of the last `history` commits if set.
+    Stats {
+        history: Option<usize>,
+    },
+    /// Print every alignment statement, as JSON lines if `json` is set.
+    List {
+        json: bool,
+    },
+    /// Restore the `.orig` backups written by `--backup`.
+    Revert,
+    Help,
+    Version,
+}
+
+pub struct Args {
+    pub command: Command,
+    pub path: Option<PathBuf>,
+    /// Only align files matching one of these globs, relative to the aligned folder.
+    pub globs: Vec<String>,
+    /// Files to align instead of walking a folder, from repeated `--file` flags.
+    pub files: Vec<PathBuf>,
+    /// Only align these members of the workspace, from repeated `--package` flags.
+    pub packages: Vec<String>,
+    /// The directories of `packages`, filled in from `cargo metadata` by `main`.
+    pub package_directories: Vec<PathBuf>,
+    /// Also align crates under the workspace root that aren't members of it.
+    pub include_non_members: bool,
+    /// The directories of the workspace root and its members, filled in from
+    /// `cargo metadata` by `main` when aligning the workspace.
+    pub member_directories: Vec<PathBuf>,
+    /// Align stdin to stdout as if it was the content of this file.
+    pub stdin_filepath: Option<PathBuf>,
+    /// Keep running and align buffers sent over a socket.
+    pub daemon: bool,
+    /// Ask before applying each changed block.
+    pub interactive: bool,
+    /// Copy files to `<file>.orig` before overwriting them.
+    pub backup: bool,
+    /// Give aligned files back the modification time they had before.
+    pub preserve_mtime: bool,
+    pub readonly: ReadOnly,
+    /// Where to write a JSON or TOML report of what happened to each file.
+    pub report: Option<PathBuf>,
+    pub output_format: OutputFormat,
+    pub error_format: ErrorFormat,
+    /// Exit successfully from `--diff` and `--output-format checkstyle` even
+    /// if files aren't aligned.
+    pub no_error_on_unaligned: bool,
+    /// Stop at the first file that couldn't be read or written or has
+    /// malformed alignment statements.
+    pub fail_fast: bool,
+    /// Format Rust files with rustfmt before aligning them.
+    pub rustfmt: bool,
+    /// The directory and edition of every member of the workspace, filled in
+    /// from `cargo metadata` by `main` for `--rustfmt`.
+    pub package_editions: Vec<(PathBuf, String)>,
+    /// Ignore `.editorconfig` files.
+    pub no_editorconfig: bool,
+    pub color: Color,
+    pub log_format: LogFormat,
+    pub allow_no_vcs: bool,
+    pub diff: bool,
+    pub emit: Emit,
+    pub expand_macros: bool,
+    pub warn_formatters: bool,
+    /// Reject markers followed by words that aren't an alignment statement
+    /// instead of treating them as prose.
+    pub strict: bool,
+    /// Print metrics of the run, like the blocks aligned and the slowest files.
+    pub stats: bool,
+    /// Don't show the progress bar or the summary at the end.
+    pub quiet: bool,
+    /// The most threads files are aligned on, one per CPU if unset.
+    pub jobs: Option<usize>,
+    /// Don't read any ignore files.
+    pub no_ignore: bool,
+    /// Don't read `.gitignore` files.
+    pub no_git_ignore: bool,
+    /// Skip symlinks inside the aligned directory instead of walking into them.
+    pub no_follow: bool,
+    /// How many levels below the aligned directory are walked, every level if unset.
+    pub max_depth: Option<usize>,
+    /// Only align files with one of these extensions, every file if empty.
+    pub extensions: Vec<String>,
+    /// Files over this many bytes are skipped, `None` disables the limit.
+    pub filesize_limit: Option<u64>,
+    /// Skip files that a previous run found aligned and haven't changed since.
+    pub incremental: bool,
+    pub encoding: Option<Encoding>,
+    /// The word that starts alignment statements.
+    pub marker: String,
+    /// Rounds aligned column positions up to a multiple of this width.
+    pub pad_to: Option<usize>,
+    /// The least number of spaces between a cell and the delimiter after it,
+    /// unless an alignment statement gives its own `gap=`.
+    pub gap: Option<usize>,
+    /// How many columns a tab reaches to, a single column if unset.
+    pub tab_width: Option<usize>,
+    /// Measure cells in characters instead of bytes.
+    pub unicode: bool,
+    /// Remove whitespace at the end of every line of a block, like `trim`.
+    pub trim_trailing: bool,
+    /// The longest a line of a block can be, in bytes.
+    pub max_line_length: Option<usize>,
+    /// The most lines a block can have.
+    pub max_block_lines: Option<usize>,
+    /// Only align this percentage of the files that would change.
+    pub sample: Option<f64>,
+    /// Picks which files are in the sample.
+    pub seed: u64,
+}
+
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Args> {
+        let mut args = args;
+        let mut subcommand = None;
+        let mut explain = None;
+        let mut out = None;
+        let mut verify_snapshots = false;
+        let mut json = false;
+        let mut history = false;
+        let mut commits = None;
+        let mut from = None;
+        let mut to = None;
+        let mut path = None;
+        let mut globs = Vec::new();
+        let mut files = Vec::new();
+        let mut packages = Vec::new();
+        let mut include_non_members = false;
+        let mut stdin_filepath = None;
+        let mut daemon = false;
+        let mut interactive = false;
+        let mut backup = false;
+        let mut preserve_mtime = false;
+        let mut readonly = ReadOnly::Error;
+        let mut report = None;
+        let mut output_format = OutputFormat::Human;
+        let mut error_format = ErrorFormat::Human;
+        let mut no_error_on_unaligned = false;
+        let mut fail_fast = false;
+        let mut rustfmt = false;
+        let mut no_editorconfig = false;
+        let mut color = Color::Auto;
+        let mut log_format = None;
+        let mut help = false;
+        let mut version = false;
+        let mut allow_no_vcs = false;
+        let mut diff = false;
+        let mut emit = None;
+        let mut expand_macros = false;
+        let mut warn_formatters = false;
+        let mut strict = false;
+        let mut stats = false;
+        let mut quiet = false;
+        let mut jobs = None;
+        let mut no_ignore = false;
+        let mut no_git_ignore = false;
+        let mut no_follow = false;
+        let mut max_depth = None;
+        let mut extensions = Vec::new();
+        let mut filesize_limit = Some(DEFAULT_FILESIZE_LIMIT);
+        let mut incremental = false;
+        let mut encoding = None;
+        let mut marker = None;
+        let mut pad_to = None;
+        let mut gap = None;
+        let mut tab_width = None;
+        let mut unicode = false;
+        let mut trim_trailing = false;
+        let mut max_line_length = Some(DEFAULT_MAX_LINE_LENGTH);
+        let mut max_block_lines = Some(DEFAULT_MAX_BLOCK_LINES);
+        let mut sample = None;
+        let mut seed = 0;
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| anyhow::anyhow!("Flag `{flag}` expects a value"))
+            };
+            match flag {
+                // Cargo passes the `--` of `cargo align -- --diff` along.
+                "--" => {}
+                "--help" | "-h" => help = true,
+                "--version" | "-V" => version = true,
+                "--allow-no-vcs" => allow_no_vcs = true,
+                "--diff" => diff = true,
+                "--emit" => emit = Some(value()?.parse()?),
+                "--expand-macros" => expand_macros = true,
+                "--warn-formatters" => warn_formatters = true,
+                "--strict" => strict = true,
+                "--unicode" => unicode = true,
+                "--trim-trailing" => trim_trailing = true,
+                "--stats" => stats = true,
+                "--quiet" | "-q" => quiet = true,
+                "--no-ignore" => no_ignore = true,
+                "--no-git-ignore" => no_git_ignore = true,
+                "--no-follow" => no_follow = true,
+                // Symlinks are followed by default, these are kept for scripts
+                // written when they weren't.
+                "--follow" | "-L" => no_follow = false,
+                "--incremental" => incremental = true,
+                "--jobs" | "-j" => {
+                    let value = value()?;
+                    match value.parse() {
+                        Ok(count) if count > 0 => jobs = Some(count),
+                        _ => anyhow::bail!(
+                            "`--jobs` expects a positive number of threads, got `{value}`"
+                        ),
+                    }
+                }
+                "--max-depth" => {
+                    let value = value()?;
+                    max_depth = Some(value.parse().map_err(|_| {
+                        anyhow::anyhow!("`--max-depth` expects a number of levels, got `{value}`")
+                    })?);
+                }
+                "--ext" => {
+                    let value = value()?;
+                    for extension in value.split(',') {
+                        let extension = extension.trim().trim_start_matches('.');
+                        if extension.is_empty() {
+                            anyhow::bail!("`--ext` expects a list of extensions, got `{value}`");
+                        }
+                        extensions.push(extension.to_string());
+                    }
+                }
+                "--file" => files.push(PathBuf::from(value()?)),
+                "--package" | "-p" => packages.push(value()?),
+                "--include-non-members" => include_non_members = true,
+                "--stdin-filepath" => stdin_filepath = Some(PathBuf::from(value()?)),
+                "--daemon" => daemon = true,
+                "--interactive" | "-i" => interactive = true,
+                "--backup" => backup = true,
+                "--preserve-mtime" => preserve_mtime = true,
+                "--readonly" => readonly = value()?.parse()?,
+                "--report" => report = Some(PathBuf::from(value()?)),
+                "--output-format" => output_format = value()?.parse()?,
+                "--error-format" => error_format = value()?.parse()?,
+                "--no-error-on-unaligned" => no_error_on_unaligned = true,
+                "--fail-fast" => fail_fast = true,
+                "--rustfmt" => rustfmt = true,
+                "--no-editorconfig" => no_editorconfig = true,
+                "--color" => color = value()?.parse()?,
+                "--log-format" => log_format = Some(value()?.parse()?),
+                "--filesize-limit" => {
+                    let value = value()?;
+                    filesize_limit = parse_filesize(&value).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "`--filesize-limit` expects a size like `512K`, `2M`, or `none`, got `{value}`"
+                        )
+                    })?;
+                }
+                "--encoding" => encoding = Some(value()?.parse()?),
+                "--marker" => marker = Some(value()?),
+                "--sample" => {
+                    let value = value()?;
+                    match value.strip_suffix('%').unwrap_or(&value).parse() {
+                        Ok(percent) if (0.0..=100.0).contains(&percent) => sample = Some(percent),
+                        _ => anyhow::bail!(
+                            "`--sample` expects a percentage between 0% and 100%, got `{value}`"
+                        ),
+                    }
+                }
+                "--seed" => {
+                    let value = value()?;
+                    seed = value
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("`--seed` expects a number, got `{value}`"))?;
+                }
+                "--pad-to" => {
+                    let value = value()?;
+                    match value.parse() {
+                        Ok(width) if width > 0 => pad_to = Some(width),
+                        _ => anyhow::bail!("`--pad-to` expects a positive width, got `{value}`"),
+                    }
+                }
+                "--gap" => {
+                    let value = value()?;
+                    gap = Some(value.parse().map_err(|_| {
+                        anyhow::anyhow!("`--gap` expects a number of spaces, got `{value}`")
+                    })?);
+                }
+                "--tab-width" => {
+                    let value = value()?;
+                    match value.parse() {
+                        Ok(width) if width > 0 => tab_width = Some(width),
+                        _ => anyhow::bail!("`--tab-width` expects a positive width, got `{value}`"),
+                    }
+                }
+                "--max-line-length" => {
+                    let value = value()?;
+                    max_line_length = parse_limit(&value).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "`--max-line-length` expects a positive number of bytes or `none`, got `{value}`"
+                        )
+                    })?;
+                }
+                "--max-block-lines" => {
+                    let value = value()?;
+                    max_block_lines = parse_limit(&value).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "`--max-block-lines` expects a positive number of lines or `none`, got `{value}`"
+                        )
+                    })?;
+                }
+                "--explain" => explain = Some(value()?),
+                "--from" => from = Some(value()?),
+                "--to" => to = Some(value()?),
+                "--out" => out = Some(PathBuf::from(value()?)),
+                "--verify-snapshots" => verify_snapshots = true,
+                "--history" => history = true,
+                "--json" => json = true,
+                "--commits" => {
+                    let value = value()?;
+                    match value.parse() {
+                        Ok(count) if count > 0 => commits = Some(count),
+                        _ => anyhow::bail!("`--commits` expects a positive count, got `{value}`"),
+                    }
+                }
+                flag if flag.starts_with("--") => anyhow::bail!("Unknown flag `{flag}`"),
+                "rename-marker" | "snapshot" | "stats" | "list" | "revert"
+                    if subcommand.is_none() && path.is_none() && globs.is_empty() =>
+                {
+                    subcommand = Some(arg.clone())
+                }
+                _ if crate::glob::is_glob(&arg) => globs.push(arg.clone()),
+                _ if path.is_some() => anyhow::bail!("Expected at most 1 path argument"),
+                _ => path = Some(PathBuf::from(&arg)),
+            }
+        }
+        if diff && emit.is_some() {
+            anyhow::bail!("`--diff` and `--emit` cannot be used together");
+        }
+        if subcommand.as_deref() != Some("rename-marker") && (from.is_some() || to.is_some()) {
+            anyhow::bail!("`--from` and `--to` can only be used with `rename-marker`");
+        }
+        if subcommand.as_deref() != Some("snapshot") && (out.is_some() || verify_snapshots) {
+            anyhow::bail!("`--out` and `--verify-snapshots` can only be used with `snapshot`");
+        }
+        let marker = marker.unwrap_or_else(|| DEFAULT_MARKER.to_string());
+        if marker.is_empty() || marker.contains(|c: char| c.is_whitespace() || c == '"') {
+            anyhow::bail!("`--marker` expects a non empty marker without whitespace or `\"`");
+        }
+        if subcommand.as_deref() != Some("stats") && (history || commits.is_some()) {
+            anyhow::bail!("`--history` and `--commits` can only be used with `stats`");
+        }
+        if subcommand.as_deref() != Some("list") && json {
+            anyhow::bail!("`--json` can only be used with `list`");
+        }
+        if !files.is_empty() && (path.is_some() || !globs.is_empty()) {
+            anyhow::bail!("`--file` cannot be used together with a path or glob argument");
+        }
+        if !packages.is_empty() && (path.is_some() || !files.is_empty()) {
+            anyhow::bail!("`--package` cannot be used together with a path argument or `--file`");
+        }
+        if !files.is_empty() && (subcommand.as_deref() == Some("snapshot") || history) {
+            anyhow::bail!("`--file` cannot be used with `snapshot` or `--history`");
+        }
+        if stdin_filepath.is_some()
+            && (subcommand.is_some() || diff || emit.is_some() || !files.is_empty())
+        {
+            anyhow::bail!(
+                "`--stdin-filepath` cannot be used with subcommands, `--diff`, `--emit`, or `--file`"
+            );
+        }
+        if daemon
+            && (subcommand.is_some()
+                || diff
+                || emit.is_some()
+                || !files.is_empty()
+                || stdin_filepath.is_some())
+        {
+            anyhow::bail!(
+                "`--daemon` cannot be used with subcommands, `--diff`, `--emit`, `--file`, or `--stdin-filepath`"
+            );
+        }
+        if interactive
+            && (subcommand.is_some()
+                || diff
+                || emit == Some(Emit::Stdout)
+                || daemon
+                || stdin_filepath.is_some())
+        {
+            anyhow::bail!(
+                "`--interactive` cannot be used with subcommands, `--diff`, `--emit stdout`, `--daemon`, or `--stdin-filepath`"
+            );
+        }
+        if backup && (subcommand.is_some() || diff || emit.is_some_and(|emit| emit != Emit::Files))
+        {
+            anyhow::bail!("`--backup` can only be used when aligning files in place");
+        }
+        if report.is_some() && subcommand.is_some() {
+            anyhow::bail!("`--report` can only be used when aligning");
+        }
+        if output_format == OutputFormat::Checkstyle
+            && (subcommand.is_some() || diff || emit.is_some() || interactive || backup)
+        {
+            anyhow::bail!("`--output-format checkstyle` only reports problems, it cannot be used with subcommands, `--diff`, `--emit`, `--interactive`, or `--backup`");
+        }
+        if log_format.is_some() && !daemon {
+            anyhow::bail!("`--log-format` can only be used with `--daemon`");
+        }
+        if commits.is_some() && !history {
+            anyhow::bail!("`--commits` can only be used with `--history`");
+        }
+        let command = match subcommand.as_deref() {
+            _ if help => Command::Help,
+            _ if version => Command::Version,
+            _ if explain.is_some() => Command::Explain(explain.unwrap_or_default()),
+            Some("rename-marker") => match (from, to) {
+                (Some(from), Some(to)) if !from.is_empty() && !to.is_empty() => {
+                    Command::RenameMarker { from, to }
+                }
+                _ => anyhow::bail!("`rename-marker` expects non empty `--from` and `--to` markers"),
+            },
+            Some("snapshot") => Command::Snapshot {
+                out: out.unwrap_or_else(|| PathBuf::from("snapshots")),
+                verify: verify_snapshots,
+            },
+            Some("stats") => Command::Stats {
+                history: history.then(|| commits.unwrap_or(10)),
+            },
+            Some("list") => Command::List { json },
+            Some("revert") => Command::Revert,
+            _ => Command::Align,
+        };
+        Ok(Args {
+            command,
+            path,
+            globs,
+            files,
+            packages,
+            package_directories: Vec::new(),
+            include_non_members,
+            member_directories: Vec::new(),
+            stdin_filepath,
+            daemon,
+            interactive,
+            backup,
+            preserve_mtime,
+            readonly,
+            report,
+            output_format,
+            error_format,
+            no_error_on_unaligned,
+            fail_fast,
+            rustfmt,
+            package_editions: Vec::new(),
+            no_editorconfig,
+            color,
+            log_format: log_format.unwrap_or(LogFormat::Text),
+            allow_no_vcs,
+            diff,
+            emit: emit.unwrap_or(Emit::Files),
+            expand_macros,
+            warn_formatters,
+            strict,
+            stats,
+            quiet,
+            jobs,
+            no_ignore,
+            no_git_ignore,
+            no_follow,
+            max_depth,
+            extensions,
+            filesize_limit,
+            incremental,
+            encoding,
+            marker,
+            pad_to,
+            gap,
+            tab_width,
+            unicode,
+            trim_trailing,
+            max_line_length,
+            max_block_lines,
+            sample,
+            seed,
+        })
+    }
+
+    /// The options the engine aligns with, from the flags that change how it aligns.
+    pub fn align_options(&self) -> AlignOptions {
+        let mut options = AlignOptions::new();
+        options
+            .marker(self.marker.as_str())
+            .pad_to(self.pad_to)
+            .gap(self.gap)
+            .tab_width(self.tab_width)
+            .unicode(self.unicode)
+            .trim_trailing(self.trim_trailing)
+            .strict(self.strict)
+            .max_line_length(self.max_line_length)
+            .max_block_lines(self.max_block_lines);
+        options
+    }
+
+    /// [`Args::align_options`] for the file at `file_path`, which only skips
+    /// delimiters inside literals if its language has them.
+    pub fn align_options_for(&self, file_path: &Path) -> AlignOptions {
+        let mut options = self.align_options();
+        options.skip_literals(has_literals(file_path));
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn cargo_subcommand() {
+        let args =
+            |args: &[&str]| cargo_args(args.iter().map(|s| s.to_string())).collect::<Vec<_>>();
+        assert_eq!(args(&["cargo-align", "align", "--diff"]), ["--diff"]);
+        assert_eq!(args(&["cargo-align", "--diff"]), ["--diff"]);
+        assert_eq!(args(&["cargo-align", "src", "align"]), ["src", "align"]);
+        assert!(parse(&["--", "--diff"]).unwrap().diff);
+        assert_eq!(parse(&["--help"]).unwrap().command, Command::Help);
+        assert_eq!(parse(&["stats", "-h"]).unwrap().command, Command::Help);
+        assert_eq!(parse(&["-V"]).unwrap().command, Command::Version);
+    }
+
+    #[test]
+    fn parsing_filesizes() {
+        assert_eq!(parse_filesize("1024"), Some(Some(1024)));
+        assert_eq!(parse_filesize("512K"), Some(Some(512 * 1024)));
+        assert_eq!(parse_filesize("2m"), Some(Some(2 * 1024 * 1024)));
+        assert_eq!(parse_filesize("1GiB"), Some(Some(1 << 30)));
+        assert_eq!(parse_filesize("3MB"), Some(Some(3_000_000)));
+        assert_eq!(parse_filesize("10b"), Some(Some(10)));
+        assert_eq!(parse_filesize("0"), Some(None));
+        assert_eq!(parse_filesize("0M"), Some(None));
+        assert_eq!(parse_filesize("none"), Some(None));
+        assert_eq!(parse_filesize(""), None);
+        assert_eq!(parse_filesize("M"), None);
+        assert_eq!(parse_filesize("1.5M"), None);
+        assert_eq!(parse_filesize("2T"), None);
+        assert_eq!(parse_filesize("-1"), None);
+        assert_eq!(parse_filesize("99999999999G"), None);
+    }
+
+    #[test]
+    fn parsing() {
+        let args = parse(&["src", "--emit", "stdout"]).unwrap();
+        assert_eq!(args.path, Some(PathBuf::from("src")));
+        assert_eq!(args.emit, Emit::Stdout);
+        assert_eq!(parse(&["--emit=new-files"]).unwrap().emit, Emit::NewFiles);
+        assert_eq!(parse(&[]).unwrap().emit, Emit::Files);
+        assert!(parse(&["--emit"]).is_err());
+        assert!(parse(&["--emit", "nowhere"]).is_err());
+        assert!(parse(&["--diff", "--emit", "stdout"]).is_err());
+        assert_eq!(
+            parse(&["--encoding", "latin1"]).unwrap().encoding,
+            Some(Encoding::Latin1)
+        );
+        let args = parse(&["rename-marker", "--from", "align_by", "--to=@align", "src"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::RenameMarker {
+                from: "align_by".to_string(),
+                to: "@align".to_string()
+            }
+        );
+        assert_eq!(args.path, Some(PathBuf::from("src")));
+        assert!(parse(&["rename-marker", "--from", "align_by"]).is_err());
+        assert!(parse(&["--to", "@align"]).is_err());
+        assert_eq!(
+            parse(&["--explain", "A0002"]).unwrap().command,
+            Command::Explain("A0002".to_string())
+        );
+        let args = parse(&["snapshot", "tests", "--out", "golden", "--verify-snapshots"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::Snapshot {
+                out: PathBuf::from("golden"),
+                verify: true
+            }
+        );
+        assert_eq!(args.path, Some(PathBuf::from("tests")));
+        assert!(parse(&["--verify-snapshots"]).is_err());
+        assert_eq!(
+            parse(&["stats"]).unwrap().command,
+            Command::Stats { history: None }
+        );
+        assert_eq!(
+            parse(&["stats", "--history"]).unwrap().command,
+            Command::Stats { history: Some(10) }
+        );
+        assert_eq!(
+            parse(&["stats", "--history", "--commits=3"])
+                .unwrap()
+                .command,
+            Command::Stats { history: Some(3) }
+        );
+        assert!(parse(&["--history"]).is_err());
+        assert_eq!(
+            parse(&["list", "--json"]).unwrap().command,
+            Command::List { json: true }
+        );
+        assert!(parse(&["--json"]).is_err());
+        assert!(parse(&["stats", "--commits", "3"]).is_err());
+        assert_eq!(parse(&[]).unwrap().marker, "align_by");
+        assert_eq!(parse(&["--marker", "#align"]).unwrap().marker, "#align");
+        assert!(parse(&["--marker", "align by"]).is_err());
+        assert!(parse(&["--marker="]).is_err());
+        assert_eq!(parse(&["--pad-to", "4"]).unwrap().pad_to, Some(4));
+        assert!(parse(&["--pad-to", "0"]).is_err());
+        assert_eq!(parse(&["--gap=2"]).unwrap().gap, Some(2));
+        assert!(parse(&["--gap", "-1"]).is_err());
+        assert_eq!(parse(&["--tab-width", "4"]).unwrap().tab_width, Some(4));
+        assert!(parse(&["--tab-width", "0"]).is_err());
+        assert!(parse(&["--unicode"]).unwrap().unicode);
+        assert!(parse(&["--trim-trailing"]).unwrap().trim_trailing);
+        assert_eq!(
+            parse(&[]).unwrap().max_line_length,
+            Some(DEFAULT_MAX_LINE_LENGTH)
+        );
+        assert_eq!(
+            parse(&["--max-line-length", "80"]).unwrap().max_line_length,
+            Some(80)
+        );
+        assert_eq!(
+            parse(&["--max-block-lines=none"]).unwrap().max_block_lines,
+            None
+        );
+        assert!(parse(&["--max-block-lines", "0"]).is_err());
+        assert!(parse(&["--max-line-length", "long"]).is_err());
+        assert_eq!(
+            parse(&["--marker", "#align", "--gap", "2", "--strict"])
+                .unwrap()
+                .align_options(),
+            AlignOptions::new()
+                .marker("#align")
+                .gap(Some(2))
+                .strict(true)
+                .clone()
+        );
+        let args = parse(&[]).unwrap();
+        assert_eq!(
+            args.align_options_for(Path::new("src/lib.rs")),
+            args.align_options()
+        );
+        assert_eq!(
+            args.align_options_for(Path::new("README.md")),
+            *args.align_options().skip_literals(false)
+        );
+        assert_eq!(
+            args.align_options_for(Path::new("Makefile")),
+            *args.align_options().skip_literals(false)
+        );
+        assert!(parse(&["--strict"]).unwrap().strict);
+        assert!(parse(&["--stats"]).unwrap().stats);
+        assert!(parse(&["-q"]).unwrap().quiet);
+        assert_eq!(parse(&["-j", "2"]).unwrap().jobs, Some(2));
+        assert_eq!(parse(&["--jobs=8"]).unwrap().jobs, Some(8));
+        assert!(parse(&["--jobs", "0"]).is_err());
+        assert!(parse(&["--no-ignore"]).unwrap().no_ignore);
+        assert!(parse(&["--no-git-ignore"]).unwrap().no_git_ignore);
+        assert!(parse(&["--no-follow"]).unwrap().no_follow);
+        assert!(!parse(&["--no-follow", "-L"]).unwrap().no_follow);
+        assert_eq!(parse(&["--max-depth", "1"]).unwrap().max_depth, Some(1));
+        assert!(parse(&["--max-depth", "-1"]).is_err());
+        assert_eq!(
+            parse(&["--ext", "rs,.toml", "--ext=md"])
+                .unwrap()
+                .extensions,
+            ["rs", "toml", "md"]
+        );
+        assert!(parse(&["--ext", "rs,"]).is_err());
+        assert_eq!(
+            parse(&[]).unwrap().filesize_limit,
+            Some(DEFAULT_FILESIZE_LIMIT)
+        );
+        assert_eq!(
+            parse(&["--filesize-limit=2M"]).unwrap().filesize_limit,
+            Some(2 << 20)
+        );
+        assert_eq!(
+            parse(&["--filesize-limit", "none"]).unwrap().filesize_limit,
+            None
+        );
+        assert!(parse(&["--filesize-limit", "big"]).is_err());
+        assert!(parse(&["--incremental"]).unwrap().incremental);
+        assert_eq!(parse(&["--sample", "10%"]).unwrap().sample, Some(10.0));
+        assert_eq!(parse(&["--sample=2.5"]).unwrap().sample, Some(2.5));
+        assert!(parse(&["--sample", "101%"]).is_err());
+        assert_eq!(parse(&["--seed", "7"]).unwrap().seed, 7);
+        assert!(parse(&["--seed", "x"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+        let args = parse(&["src/**/*.rs", "benches/*.rs"]).unwrap();
+        assert_eq!(args.globs, ["src/**/*.rs", "benches/*.rs"]);
+        assert_eq!(args.path, None);
+        let args = parse(&["crates", "*/src/*.rs"]).unwrap();
+        assert_eq!(args.path, Some(PathBuf::from("crates")));
+        assert_eq!(args.globs, ["*/src/*.rs"]);
+        let args = parse(&["--file", "a.rs", "--diff", "--file=b.rs"]).unwrap();
+        assert_eq!(args.files, [PathBuf::from("a.rs"), PathBuf::from("b.rs")]);
+        assert!(args.diff);
+        assert!(parse(&["src", "--file", "a.rs"]).is_err());
+        assert!(parse(&["*.rs", "--file", "a.rs"]).is_err());
+        assert!(parse(&["snapshot", "--file", "a.rs"]).is_err());
+        assert_eq!(
+            parse(&["-p", "core", "--package=cli", "*.rs"])
+                .unwrap()
+                .packages,
+            ["core", "cli"]
+        );
+        assert!(parse(&["-p", "core", "src"]).is_err());
+        assert!(
+            parse(&["--include-non-members"])
+                .unwrap()
+                .include_non_members
+        );
+        assert!(parse(&["-p", "core", "--file", "a.rs"]).is_err());
+        assert_eq!(
+            parse(&["--stdin-filepath", "src/a.rs"])
+                .unwrap()
+                .stdin_filepath,
+            Some(PathBuf::from("src/a.rs"))
+        );
+        assert!(parse(&["--stdin-filepath", "src/a.rs", "--diff"]).is_err());
+        assert!(parse(&["--daemon"]).unwrap().daemon);
+        assert!(parse(&["--daemon", "--file", "a.rs"]).is_err());
+        assert!(parse(&["-i", "--emit", "new-files"]).unwrap().interactive);
+        assert!(parse(&["--interactive", "--diff"]).is_err());
+        assert!(parse(&["--backup"]).unwrap().backup);
+        assert!(parse(&["--backup", "--emit", "stdout"]).is_err());
+        assert_eq!(parse(&["revert", "src"]).unwrap().command, Command::Revert);
+        assert_eq!(
+            parse(&["--report", "report.json"]).unwrap().report,
+            Some(PathBuf::from("report.json"))
+        );
+        assert!(parse(&["list", "--report", "report.json"]).is_err());
+        assert_eq!(
+            parse(&["--output-format", "checkstyle"])
+                .unwrap()
+                .output_format,
+            OutputFormat::Checkstyle
+        );
+        assert!(parse(&["--output-format", "xml"]).is_err());
+        assert_eq!(
+            parse(&["--error-format=short"]).unwrap().error_format,
+            ErrorFormat::Short
+        );
+        assert_eq!(
+            parse(&["--error-format", "json"]).unwrap().error_format,
+            ErrorFormat::Json
+        );
+        assert_eq!(parse(&[]).unwrap().error_format, ErrorFormat::Human);
+        assert!(parse(&["--error-format", "long"]).is_err());
+        assert!(
+            parse(&["--diff", "--no-error-on-unaligned"])
+                .unwrap()
+                .no_error_on_unaligned
+        );
+        assert!(parse(&["--fail-fast"]).unwrap().fail_fast);
+        assert!(parse(&["--rustfmt"]).unwrap().rustfmt);
+        assert!(parse(&["--no-editorconfig"]).unwrap().no_editorconfig);
+        assert_eq!(parse(&["--color=never"]).unwrap().color, Color::Never);
+        assert_eq!(parse(&[]).unwrap().color, Color::Auto);
+        assert!(parse(&["--color", "sometimes"]).is_err());
+        assert_eq!(
+            parse(&["--daemon", "--log-format", "json"])
+                .unwrap()
+                .log_format,
+            LogFormat::Json
+        );
+        assert_eq!(parse(&["--daemon"]).unwrap().log_format, LogFormat::Text);
+        assert!(parse(&["--log-format", "json"]).is_err());
+        assert_eq!(parse(&[]).unwrap().readonly, ReadOnly::Error);
+        assert!(parse(&["--preserve-mtime"]).unwrap().preserve_mtime);
+        assert_eq!(
+            parse(&["--readonly=skip"]).unwrap().readonly,
+            ReadOnly::Skip
+        );
+        assert!(parse(&["--readonly", "ignore"]).is_err());
+        assert!(parse(&["--output-format=checkstyle", "--diff"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Aligning from async code on a tokio runtime. Reading and writing are async,
+//! aligning itself is CPU bound and runs on the blocking pool so it doesn't
+//! stall the other tasks of the runtime.
+
+use crate::align::AlignOptions;
+use crate::stream::temporary_path;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::sync::Semaphore;
+
+/// Aligns `content` on the blocking pool. An invalid alignment statement is
+/// an [`io::ErrorKind::InvalidData`] error wrapping the
+/// [`AlignmentError`](crate::align::AlignmentError).
+async fn align_blocking(content: String, options: &AlignOptions) -> io::Result<String> {
+    let options = options.clone();
+    tokio::task::spawn_blocking(move || options.align_string(&content))
+        .await
+        .map_err(io::Error::other)?
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Reads all of `reader` and aligns it with `options`.
+pub async fn align_reader<R: AsyncRead + Unpin>(
+    mut reader: R,
+    options: &AlignOptions,
+) -> io::Result<String> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content).await?;
+    align_blocking(content, options).await
+}
+
+/// Aligns the file at `path` in place, only writing it if aligning changed it.
+/// Returns whether it did. Like [`crate::stream::align_file`] the aligned
+/// content goes to a temporary file next to it first, which replaces it
+/// keeping its permissions.
+pub async fn align_file(path: impl AsRef<Path>, options: &AlignOptions) -> io::Result<bool> {
+    // Symlinks stay symlinks, it's the file they point to that is replaced.
+    let path = tokio::fs::canonicalize(path).await?;
+    let content = tokio::fs::read_to_string(&path).await?;
+    let aligned = align_blocking(content.clone(), options).await?;
+    if aligned == content {
+        return Ok(false);
+    }
+    let temporary_path = temporary_path(&path);
+    let replaced = async {
+        let permissions = tokio::fs::metadata(&path).await?.permissions();
+        tokio::fs::write(&temporary_path, aligned).await?;
+        tokio::fs::set_permissions(&temporary_path, permissions).await?;
+        tokio::fs::rename(&temporary_path, &path).await
+    }
+    .await;
+    if replaced.is_err() {
+        let _ = tokio::fs::remove_file(&temporary_path).await;
+    }
+    replaced.map(|()| true)
+}
+
+/// Aligns every file of `paths` in place, see [`align_file`], as many at the
+/// same time as there are CPUs. The results are in the order of `paths`.
+pub async fn align_files(paths: Vec<PathBuf>, options: &AlignOptions) -> Vec<io::Result<bool>> {
+    let jobs = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let semaphore = Arc::new(Semaphore::new(jobs));
+    let tasks = paths
+        .into_iter()
+        .map(|path| {
+            let options = options.clone();
+            let semaphore = Arc::clone(&semaphore);
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire().await.map_err(io::Error::other)?;
+                align_file(path, &options).await
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut results = Vec::new();
+    for task in tasks {
+        results.push(task.await.unwrap_or_else(|err| Err(io::Error::other(err))));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::AlignmentError;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn aligning_readers() {
+        let aligned = block_on(align_reader(
+            &b"// align_by \"=\"\na = 1\nbbb = 2\n"[..],
+            &AlignOptions::new(),
+        ));
+        assert_eq!(aligned.unwrap(), "// align_by \"=\"\na   = 1\nbbb = 2\n");
+        let err = block_on(align_reader(
+            &b"// align_by\"=\"\n"[..],
+            &AlignOptions::new(),
+        ))
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.get_ref().unwrap().is::<AlignmentError>());
+    }
+
+    #[test]
+    fn aligning_files() {
+        let dir = std::env::temp_dir().join(format!("cargo-align-async-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.rs"), "// align_by \"=\"\na = 1\nbbb = 2\n").unwrap();
+        std::fs::write(dir.join("b.rs"), "a = 1\n").unwrap();
+        let results = block_on(align_files(
+            vec![dir.join("a.rs"), dir.join("b.rs"), dir.join("c.rs")],
+            &AlignOptions::new(),
+        ));
+        assert!(results[0].as_ref().unwrap());
+        assert!(!results[1].as_ref().unwrap());
+        assert!(results[2].is_err());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a.rs")).unwrap(),
+            "// align_by \"=\"\na   = 1\nbbb = 2\n"
+        );
+        #[cfg(unix)]
+        {
+            // Executable scripts stay executable once replaced.
+            use std::os::unix::fs::PermissionsExt;
+            let script = dir.join("d.sh");
+            std::fs::write(&script, "# align_by \"=\"\na = 1\nbbb = 2\n").unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(block_on(align_file(&script, &AlignOptions::new())).unwrap());
+            assert_eq!(
+                std::fs::metadata(&script).unwrap().permissions().mode() & 0o777,
+                0o755
+            );
+            std::fs::remove_file(&script).unwrap();
+        }
+        // No temporary files are left behind.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
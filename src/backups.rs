@@ -0,0 +1,92 @@
+//! Remembers which `.orig` backups `--backup` wrote, so `cargo align revert`
+//! only restores those and leaves other `.orig` files, like the ones merge
+//! tools leave behind, alone.
+//!
+//! The list is a text file next to the `--incremental` cache with the
+//! canonical path of one backed up file per line, whose backup is the path
+//! with `.orig` appended. It is removed once every backup was restored.
+
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub struct Backups {
+    path: PathBuf,
+    /// Canonical paths of the files that have a backup.
+    files: BTreeSet<PathBuf>,
+}
+
+impl Backups {
+    /// Loads the list at `path`, which is empty if it doesn't exist.
+    pub fn load(path: PathBuf) -> Backups {
+        let files = std::fs::read_to_string(&path)
+            .map(|content| content.lines().map(PathBuf::from).collect())
+            .unwrap_or_default();
+        Backups { path, files }
+    }
+
+    /// Where `--backup` copies `file` to before overwriting it.
+    pub fn backup_path(file: &Path) -> PathBuf {
+        let mut backup_path = file.as_os_str().to_owned();
+        backup_path.push(".orig");
+        PathBuf::from(backup_path)
+    }
+
+    /// Copies `file` to its backup and records it.
+    pub fn back_up(&mut self, file: &Path) -> Result<()> {
+        let backup_path = Backups::backup_path(file);
+        std::fs::copy(file, &backup_path).with_context(|| {
+            format!(
+                "Not aligning {} because backing it up to {} failed",
+                file.display(),
+                backup_path.display()
+            )
+        })?;
+        self.files
+            .insert(file.canonicalize().unwrap_or_else(|_| file.to_path_buf()));
+        Ok(())
+    }
+
+    /// Moves the backup of `file` back over it if it has one, returning
+    /// whether it did.
+    pub fn restore(&mut self, file: &Path) -> Result<bool> {
+        let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        if !self.files.contains(&file) {
+            return Ok(false);
+        }
+        let backup_path = Backups::backup_path(&file);
+        std::fs::rename(&backup_path, &file).with_context(|| {
+            format!(
+                "Failed to restore {} from {}",
+                file.display(),
+                backup_path.display()
+            )
+        })?;
+        self.files.remove(&file);
+        Ok(true)
+    }
+
+    /// Writes the list, or removes it if no backups are left.
+    pub fn save(&self) -> Result<()> {
+        if self.files.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    Err(err).with_context(|| format!("Failed to remove {}", self.path.display()))
+                }
+                _ => Ok(()),
+            };
+        }
+        let mut content = String::new();
+        for file in &self.files {
+            content.push_str(&format!("{}\n", file.display()));
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        std::fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
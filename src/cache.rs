@@ -0,0 +1,117 @@
+//! Remembers which files are already aligned so `--incremental` runs can skip
+//! aligning them again.
+//!
+//! The cache is a text file with a header naming the tool version and the
+//! options that change the aligned output, followed by one `<hash> <path>`
+//! line per file. A header that doesn't match the current run discards every
+//! entry, so the cache never outlives the options it was recorded with.
+
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// FNV-1a, stable across platforms and Rust versions unlike `DefaultHasher`.
+pub fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub struct Cache {
+    path: PathBuf,
+    header: String,
+    /// Content hashes of aligned files by their canonical path.
+    entries: HashMap<PathBuf, u64>,
+}
+
+impl Cache {
+    /// Loads the cache at `path`, which is empty if it doesn't exist or was
+    /// recorded with different `options`.
+    pub fn load(path: PathBuf, options: &str) -> Cache {
+        let header = format!(
+            "cargo-align {} {:016x}",
+            env!("CARGO_PKG_VERSION"),
+            hash(options.as_bytes())
+        );
+        let mut entries = HashMap::new();
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            let mut lines = content.lines();
+            if lines.next() == Some(header.as_str()) {
+                entries.extend(lines.filter_map(|line| {
+                    let (hash, file) = line.split_once(' ')?;
+                    Some((PathBuf::from(file), u64::from_str_radix(hash, 16).ok()?))
+                }));
+            }
+        }
+        Cache {
+            path,
+            header,
+            entries,
+        }
+    }
+
+    /// The path `file` is recorded under.
+    pub fn key(file: &Path) -> PathBuf {
+        file.canonicalize().unwrap_or_else(|_| file.to_path_buf())
+    }
+
+    /// Whether `file` was last found aligned with content hashing to `content_hash`.
+    pub fn is_aligned(&self, file: &Path, content_hash: u64) -> bool {
+        self.entries.get(file) == Some(&content_hash)
+    }
+
+    /// Records that `file` is aligned while its content hashes to `content_hash`.
+    pub fn insert(&mut self, file: PathBuf, content_hash: u64) {
+        self.entries.insert(file, content_hash);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort();
+        let mut content = format!("{}\n", self.header);
+        for (file, hash) in entries {
+            content.push_str(&format!("{hash:016x} {}\n", file.display()));
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create cache directory {}", parent.display())
+            })?;
+        }
+        std::fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write cache {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_and_loading() {
+        let dir = std::env::temp_dir().join(format!("cargo-align-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("target/cargo-align-cache");
+
+        let mut cache = Cache::load(path.clone(), "align_by");
+        assert!(!cache.is_aligned(Path::new("/a.rs"), 1));
+        cache.insert(PathBuf::from("/a.rs"), 1);
+        cache.insert(PathBuf::from("/b c.rs"), u64::MAX);
+        cache.save().unwrap();
+
+        let cache = Cache::load(path.clone(), "align_by");
+        assert!(cache.is_aligned(Path::new("/a.rs"), 1));
+        assert!(cache.is_aligned(Path::new("/b c.rs"), u64::MAX));
+        assert!(!cache.is_aligned(Path::new("/a.rs"), 2));
+        assert!(!cache.is_aligned(Path::new("/c.rs"), 1));
+
+        let cache = Cache::load(path, "#align");
+        assert!(!cache.is_aligned(Path::new("/a.rs"), 1));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! Checkstyle XML output for `--output-format checkstyle`, a format that CI
+//! servers and code quality dashboards read natively.
+
+use std::path::PathBuf;
+
+pub struct Diagnostic {
+    /// 1 based line number.
+    pub line: usize,
+    /// 1 based column, in characters.
+    pub column: usize,
+    pub message: String,
+    /// What found the problem, ie `cargo-align.A0001`.
+    pub source: String,
+}
+
+/// Renders the diagnostics of every file as a Checkstyle report.
+pub fn render(files: &[(PathBuf, Vec<Diagnostic>)]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<checkstyle version=\"4.3\">\n");
+    for (path, diagnostics) in files {
+        xml.push_str(&format!(
+            "  <file name=\"{}\">\n",
+            escape(&path.display().to_string())
+        ));
+        for diagnostic in diagnostics {
+            xml.push_str(&format!(
+                "    <error line=\"{}\" column=\"{}\" severity=\"error\" message=\"{}\" source=\"{}\"/>\n",
+                diagnostic.line,
+                diagnostic.column,
+                escape(&diagnostic.message),
+                escape(&diagnostic.source)
+            ));
+        }
+        xml.push_str("  </file>\n");
+    }
+    xml.push_str("</checkstyle>\n");
+    xml
+}
+
+/// Escapes `s` for use in an attribute value. Whitespace other than spaces
+/// would be normalized to spaces by XML parsers, so it is written as
+/// character references, and characters XML 1.0 doesn't allow at all are
+/// replaced with `U+FFFD`.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            '\u{0}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => {
+                escaped.push(char::REPLACEMENT_CHARACTER)
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendering() {
+        let files = vec![(
+            PathBuf::from("src/a&b.rs"),
+            vec![Diagnostic {
+                line: 3,
+                column: 12,
+                message: "unknown keyword `sotr` (did you mean `sort`?)".to_string(),
+                source: "cargo-align.A0016".to_string(),
+            }],
+        )];
+        assert_eq!(render(&files), indoc::indoc! {r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <checkstyle version="4.3">
+              <file name="src/a&amp;b.rs">
+                <error line="3" column="12" severity="error" message="unknown keyword `sotr` (did you mean `sort`?)" source="cargo-align.A0016"/>
+              </file>
+            </checkstyle>
+        "#});
+        assert_eq!(escape("<\"'>\n"), "&lt;&quot;&apos;&gt;&#10;");
+        assert_eq!(escape("a\r\n\tb"), "a&#13;&#10;&#9;b");
+        assert_eq!(escape("a\u{0}b\u{1B}c\u{FFFF}"), "a\u{FFFD}b\u{FFFD}c\u{FFFD}");
+    }
+}
@@ -0,0 +1,46 @@
+//! Ordering text by the rules of a locale for `sort locale:<tag>`, which
+//! needs the `icu` feature. Without it no locale is supported and statements
+//! naming one are rejected instead of silently sorting by code points.
+
+use std::cmp::Ordering;
+
+/// Orders text like a reader of the locale `tag` expects, ie `de` puts `Äpfel`
+/// next to `apfel` and `sv` puts it after `Zebra`. `None` if `tag` isn't a
+/// locale or this build has no collation data.
+#[cfg(feature = "icu")]
+pub fn collator(tag: &str) -> Option<impl Fn(&str, &str) -> Ordering> {
+    let locale = tag.parse::<icu_locid::Locale>().ok()?;
+    let collator =
+        icu_collator::Collator::try_new(&(&locale).into(), icu_collator::CollatorOptions::new())
+            .ok()?;
+    Some(move |a: &str, b: &str| collator.compare(a, b))
+}
+
+#[cfg(not(feature = "icu"))]
+pub fn collator(_tag: &str) -> Option<fn(&str, &str) -> Ordering> {
+    None
+}
+
+/// Whether `sort locale:<tag>` can sort by `tag` in this build.
+pub fn is_supported(tag: &str) -> bool {
+    collator(tag).is_some()
+}
+
+#[cfg(all(test, feature = "icu"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collating() {
+        let sorted = |tag| {
+            let collator = collator(tag).unwrap();
+            let mut words = ["Zebra", "Äpfel", "apfel", "Ähre", "Apfel"];
+            words.sort_by(|a, b| collator(a, b));
+            words
+        };
+        assert_eq!(sorted("de"), ["Ähre", "apfel", "Apfel", "Äpfel", "Zebra"]);
+        assert_eq!(sorted("sv"), ["apfel", "Apfel", "Zebra", "Ähre", "Äpfel"]);
+        assert!(is_supported("de-CH"));
+        assert!(!is_supported("not a locale"));
+    }
+}
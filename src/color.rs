@@ -0,0 +1,99 @@
+//! ANSI colors for `--color`.
+
+use crate::args::Color;
+use std::io::IsTerminal;
+
+const RESET: &str = "\x1b[0m";
+pub const BOLD: &str = "\x1b[1m";
+pub const RED: &str = "\x1b[31m";
+pub const GREEN: &str = "\x1b[32m";
+pub const YELLOW: &str = "\x1b[33m";
+pub const CYAN: &str = "\x1b[36m";
+
+impl Color {
+    /// Whether to color output written to stdout.
+    pub fn stdout(self) -> bool {
+        self.enabled(std::io::stdout().is_terminal())
+    }
+
+    /// Whether to color output written to stderr.
+    pub fn stderr(self) -> bool {
+        self.enabled(std::io::stderr().is_terminal())
+    }
+
+    fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            Color::Always => true,
+            Color::Never => false,
+            // https://no-color.org
+            Color::Auto => is_terminal && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+/// `s` in `style` if `enabled`.
+pub fn paint(enabled: bool, style: &str, s: &str) -> String {
+    if enabled {
+        format!("{style}{s}{RESET}")
+    } else {
+        s.to_string()
+    }
+}
+
+/// Colors each line of a diff by what it does to the file.
+pub fn diff(diff: &str) -> String {
+    diff.split_inclusive('\n')
+        .map(|line| {
+            let content = line.strip_suffix('\n').unwrap_or(line);
+            let style = if content.starts_with("---") || content.starts_with("+++") {
+                BOLD
+            } else if content.starts_with("@@") {
+                CYAN
+            } else if content.starts_with('-') {
+                RED
+            } else if content.starts_with('+') {
+                GREEN
+            } else if content.starts_with('~') {
+                YELLOW
+            } else {
+                return line.to_string();
+            };
+            let newline = &line[content.len()..];
+            format!("{style}{content}{RESET}{newline}")
+        })
+        .collect()
+}
+
+/// Makes the location of a `path:line:column: error[code]: message` line bold
+/// and the error code red, leaving other lines alone.
+pub fn error(line: &str) -> String {
+    let Some((location, rest)) = line.split_once(": error[") else {
+        return line.to_string();
+    };
+    let Some((code, message)) = rest.split_once("]:") else {
+        return line.to_string();
+    };
+    format!("{BOLD}{location}:{RESET} {BOLD}{RED}error[{code}]:{RESET}{message}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coloring() {
+        assert_eq!(paint(false, RED, "1"), "1");
+        assert_eq!(paint(true, RED, "1"), "\x1b[31m1\x1b[0m");
+        assert_eq!(
+            diff("--- f\n+++ f\n@@ -1,2 +1,2 @@\n x\n-a\n+b\n~c[- -]\n"),
+            "\x1b[1m--- f\x1b[0m\n\x1b[1m+++ f\x1b[0m\n\x1b[36m@@ -1,2 +1,2 @@\x1b[0m\n x\n\x1b[31m-a\x1b[0m\n\x1b[32m+b\x1b[0m\n\x1b[33m~c[- -]\x1b[0m\n"
+        );
+        assert_eq!(
+            error("a.rs:1:17: error[A0001]: bad"),
+            "\x1b[1ma.rs:1:17:\x1b[0m \x1b[1m\x1b[31merror[A0001]:\x1b[0m bad"
+        );
+        assert_eq!(error("a.rs:1:17: A0001: bad"), "a.rs:1:17: A0001: bad");
+        assert!(Color::Always.enabled(false));
+        assert!(!Color::Never.enabled(true));
+    }
+}
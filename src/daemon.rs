@@ -0,0 +1,207 @@
+//! A long running process that aligns editor buffers sent over a Unix socket,
+//! so editors don't pay for starting up and running `cargo metadata` on
+//! every save.
+//!
+//! A client connects, writes a request line, then the buffer, and shuts down
+//! its side of the connection:
+//!
+//! ```text
+//! align src/lib.rs
+//! <content of the buffer>
+//! ```
+//!
+//! `align` answers with `ok` followed by the aligned content, `check` with
+//! `aligned` or `misaligned`. Either can be preceded by `warning <message>`
+//! lines, and a request that fails is answered with a single `error <message>`
+//! line instead. Paths are relative to the directory the daemon runs in.
+//!
+//! Which files are aligned is decided once, ignore files are read the first
+//! time a file in their directory is aligned. Up to `--jobs` requests are
+//! answered at the same time, further connections wait for one of them.
+//! Clients get [`REQUEST_TIMEOUT`] to send their request, so one that never
+//! finishes doesn't hold its slot forever.
+
+use crate::align_buffer;
+use crate::args::Args;
+use crate::buffer_discovery;
+use crate::log::log;
+use crate::log::Level;
+use anyhow::Context;
+use anyhow::Result;
+use cargo_align::discovery::Discovery;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long a client has to send its whole request.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The response to a single `request`, for a daemon aligning the files
+/// `discovery` selects.
+fn respond(args: &Args, discovery: &Discovery, request: &str) -> String {
+    let (request_line, content) = request.split_once('\n').unwrap_or((request, ""));
+    let Some((kind @ ("align" | "check"), file_path)) = request_line.split_once(' ') else {
+        return format!("error expected `align <path>` or `check <path>`, got `{request_line}`\n");
+    };
+    let file_path = Path::new(file_path);
+    match align_buffer(args, discovery, file_path, content.to_string()) {
+        Err(err) => format!("error {}\n", err.to_string().replace('\n', " ")),
+        Ok((aligned_content, report)) => {
+            let mut response = String::new();
+            for warning in report.warnings {
+                let _ = writeln!(
+                    response,
+                    "warning {}:{}: {}",
+                    file_path.display(),
+                    warning.line,
+                    warning.message
+                );
+            }
+            match kind {
+                "align" => {
+                    response.push_str("ok\n");
+                    response.push_str(&aligned_content);
+                }
+                _ if aligned_content == content => response.push_str("aligned\n"),
+                _ => response.push_str("misaligned\n"),
+            }
+            response
+        }
+    }
+}
+
+/// Serves requests on a socket in the `target` directory under `root` until
+/// the process is stopped.
+#[cfg(unix)]
+pub fn daemon(args: &Args, root: &Path) -> Result<()> {
+    use std::io::Read;
+    use std::io::Write;
+    use std::os::unix::net::UnixListener;
+    use std::os::unix::net::UnixStream;
+    use std::sync::Condvar;
+    use std::sync::Mutex;
+
+    let socket_path = root.join("target/cargo-align.sock");
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    if UnixStream::connect(&socket_path).is_ok() {
+        anyhow::bail!(
+            "Another daemon is already listening on {}",
+            socket_path.display()
+        );
+    }
+    // A socket left behind by a daemon that was killed refuses new bindings.
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path)
+        .with_context(|| format!("Failed to listen on {}", socket_path.display()))?;
+    log(
+        args.log_format,
+        Level::Info,
+        &format!("Listening on {}", socket_path.display()),
+    );
+    let discovery = &buffer_discovery(args, root);
+    let jobs = args.jobs.unwrap_or_else(crate::parallel::default_jobs);
+    // The number of requests being answered, which `--jobs` caps.
+    let (active, finished) = &(Mutex::new(0), Condvar::new());
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Err(err) => {
+                    log(
+                        args.log_format,
+                        Level::Error,
+                        &format!("Failed to accept a connection: {err}"),
+                    );
+                    continue;
+                }
+                Ok(stream) => stream,
+            };
+            let mut active_requests = finished
+                .wait_while(active.lock().unwrap(), |active| *active >= jobs)
+                .unwrap();
+            *active_requests += 1;
+            drop(active_requests);
+            scope.spawn(move || {
+                let mut request = String::new();
+                let response = match stream
+                    .set_read_timeout(Some(REQUEST_TIMEOUT))
+                    .and_then(|()| stream.read_to_string(&mut request))
+                {
+                    Err(err) => format!("error failed to read the request: {err}\n"),
+                    Ok(_) => respond(args, discovery, &request),
+                };
+                let request_line = request.lines().next().unwrap_or_default();
+                match response.strip_prefix("error ") {
+                    Some(err) => log(
+                        args.log_format,
+                        Level::Error,
+                        &format!("`{request_line}` failed: {}", err.trim_end()),
+                    ),
+                    None => log(
+                        args.log_format,
+                        Level::Info,
+                        &format!("Answered `{request_line}`"),
+                    ),
+                }
+                if let Err(err) = stream.write_all(response.as_bytes()) {
+                    log(
+                        args.log_format,
+                        Level::Error,
+                        &format!("Failed to answer a request: {err}"),
+                    );
+                }
+                *active.lock().unwrap() -= 1;
+                finished.notify_one();
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemon(_: &Args, _: &Path) -> Result<()> {
+    anyhow::bail!("`--daemon` needs Unix sockets, which this platform doesn't have")
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responding() {
+        let args = Args::parse(std::iter::empty()).unwrap();
+        let discovery = &buffer_discovery(&args, Path::new("/workspace"));
+        let misaligned = "// align_by \"=\"\na = 1\nbbb = 2\n";
+        let aligned = "// align_by \"=\"\na   = 1\nbbb = 2\n";
+
+        assert_eq!(respond(&args, discovery, &format!("align /workspace/a.rs\n{misaligned}")), format!("ok\n{aligned}"));
+        assert_eq!(respond(&args, discovery, &format!("check /workspace/a.rs\n{misaligned}")), "misaligned\n");
+        assert_eq!(respond(&args, discovery, &format!("check /workspace/a.rs\n{aligned}")), "aligned\n");
+        // Files outside of the workspace are left alone.
+        assert_eq!(respond(&args, discovery, &format!("align /elsewhere/a.rs\n{misaligned}")), format!("ok\n{misaligned}"));
+        assert!(respond(&args, discovery, "align /workspace/a.rs\n// align_by sort\n").starts_with("error /workspace/a.rs:1:17: error[A0001]"));
+        assert!(respond(&args, discovery, "format a.rs\n").starts_with("error expected"));
+        assert_eq!(
+            respond(&args, discovery, "align /workspace/a.rs\n// align_by \"=\"\n// align_by \"=\"\na = 1\n"),
+            "warning /workspace/a.rs:1: this alignment statement is shadowed by the one on line 2 and aligns nothing\nok\n// align_by \"=\"\n// align_by \"=\"\na = 1\n",
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refusing_to_replace_a_running_daemon() {
+        let root = std::env::temp_dir().join(format!("cargo-align-daemon-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        let socket_path = root.join("target/cargo-align.sock");
+        let _ = std::fs::remove_file(&socket_path);
+        let _listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        let args = Args::parse(std::iter::empty()).unwrap();
+        let err = daemon(&args, &root).unwrap_err();
+        assert!(err.to_string().starts_with("Another daemon is already listening on"));
+        assert!(socket_path.exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
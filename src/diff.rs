@@ -0,0 +1,333 @@
+//! Line diffs between a file and its aligned version.
+//!
+//! Aligning only ever changes whitespace or the order of lines, so lines are
+//! matched on their whitespace-collapsed content. Matched lines whose padding
+//! changed are shown on a single `~` line with the removed padding runs wrapped
+//! in `[-` `-]` and the inserted ones in `{+` `+}`, the rest fall back to
+//! whole-line `-`/`+` output.
+
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, PartialEq)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Padding(&'a str, &'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Renders a diff of `old` against `new`, or returns `None` if they are equal.
+pub fn render_diff(path: &str, old: &str, new: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let diff = diff_lines(&old_lines, &new_lines);
+
+    let mut output = format!("--- {path}\n+++ {path}\n");
+    let changed = diff
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, DiffLine::Same(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT_LINES);
+        let end = (i + CONTEXT_LINES + 1).min(diff.len());
+        match hunks.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    for (start, end) in hunks {
+        let old_start = 1 + diff[..start]
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Added(_)))
+            .count();
+        let new_start = 1 + diff[..start]
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Removed(_)))
+            .count();
+        let hunk = &diff[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Added(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Removed(_)))
+            .count();
+        output.push_str(&format!(
+            "@@ -{old_start},{old_len} +{new_start},{new_len} @@\n"
+        ));
+        for line in hunk {
+            match line {
+                DiffLine::Same(l) => output.push_str(&format!(" {l}\n")),
+                DiffLine::Padding(o, n) => {
+                    output.push_str(&format!("~{}\n", highlight_padding(o, n)))
+                }
+                DiffLine::Removed(l) => output.push_str(&format!("-{l}\n")),
+                DiffLine::Added(l) => output.push_str(&format!("+{l}\n")),
+            }
+        }
+    }
+    Some(output)
+}
+
+/// The number of lines of `old` that aren't in `new` as they are, because
+/// their padding changed or they were moved or removed.
+pub fn changed_lines(old: &str, new: &str) -> usize {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    diff_lines(&old_lines, &new_lines)
+        .iter()
+        .filter(|line| matches!(line, DiffLine::Padding(..) | DiffLine::Removed(_)))
+        .count()
+}
+
+/// Applies the changes between `old` and `new` that `accept` agrees to.
+///
+/// Every run of consecutive changed lines, usually one aligned block, is
+/// passed to `accept` rendered like in a diff along with the 1 based line of
+/// `old` it starts at. Rejected runs keep the lines of `old`.
+pub fn apply_changes(old: &str, new: &str, mut accept: impl FnMut(usize, &str) -> bool) -> String {
+    let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+    let diff = diff_lines(&old_lines, &new_lines);
+    let line_content = |line: &str| line.trim_end_matches(['\n', '\r']).to_string();
+
+    let mut output = String::new();
+    let mut old_line = 1;
+    let mut i = 0;
+    while i < diff.len() {
+        if let DiffLine::Same(line) = diff[i] {
+            output.push_str(line);
+            old_line += 1;
+            i += 1;
+            continue;
+        }
+        let end = diff[i..]
+            .iter()
+            .position(|line| matches!(line, DiffLine::Same(_)))
+            .map_or(diff.len(), |len| i + len);
+        let change = &diff[i..end];
+        let mut rendered = String::new();
+        for line in change {
+            rendered.push_str(&match line {
+                DiffLine::Same(_) => unreachable!(),
+                DiffLine::Padding(o, n) => {
+                    format!(
+                        "~{}\n",
+                        highlight_padding(&line_content(o), &line_content(n))
+                    )
+                }
+                DiffLine::Removed(l) => format!("-{}\n", line_content(l)),
+                DiffLine::Added(l) => format!("+{}\n", line_content(l)),
+            });
+        }
+        let accepted = accept(old_line, &rendered);
+        for line in change {
+            match (line, accepted) {
+                (DiffLine::Padding(_, line), true)
+                | (DiffLine::Padding(line, _), false)
+                | (DiffLine::Added(line), true)
+                | (DiffLine::Removed(line), false) => output.push_str(line),
+                _ => {}
+            }
+        }
+        old_line += change
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Added(_)))
+            .count();
+        i = end;
+    }
+    output
+}
+
+/// The 1 based lines of `old` where each run of changed lines starts.
+pub fn change_starts(old: &str, new: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    apply_changes(old, new, |line, _| {
+        starts.push(line);
+        false
+    });
+    starts
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    s.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let old_keys = old_middle
+        .iter()
+        .map(|l| collapse_whitespace(l))
+        .collect::<Vec<_>>();
+    let new_keys = new_middle
+        .iter()
+        .map(|l| collapse_whitespace(l))
+        .collect::<Vec<_>>();
+
+    // Longest common subsequence table, lcs[i][j] is the length for old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new_keys.len() + 1]; old_keys.len() + 1];
+    for i in (0..old_keys.len()).rev() {
+        for j in (0..new_keys.len()).rev() {
+            lcs[i][j] = if old_keys[i] == new_keys[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = old[..prefix]
+        .iter()
+        .map(|l| DiffLine::Same(l))
+        .collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    while i < old_keys.len() || j < new_keys.len() {
+        if i < old_keys.len() && j < new_keys.len() && old_keys[i] == new_keys[j] {
+            if old_middle[i] == new_middle[j] {
+                diff.push(DiffLine::Same(old_middle[i]));
+            } else {
+                diff.push(DiffLine::Padding(old_middle[i], new_middle[j]));
+            }
+            i += 1;
+            j += 1;
+        } else if j < new_keys.len() && (i == old_keys.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            diff.push(DiffLine::Added(new_middle[j]));
+            j += 1;
+        } else {
+            diff.push(DiffLine::Removed(old_middle[i]));
+            i += 1;
+        }
+    }
+    diff.extend(old[old.len() - suffix..].iter().map(|l| DiffLine::Same(l)));
+    diff
+}
+
+/// Splits `s` into alternating runs of whitespace and non-whitespace, always
+/// starting with a (possibly empty) whitespace run.
+fn whitespace_runs(s: &str) -> Vec<&str> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut in_whitespace = true;
+    for (i, c) in s.char_indices() {
+        if c.is_ascii_whitespace() != in_whitespace {
+            runs.push(&s[start..i]);
+            start = i;
+            in_whitespace = !in_whitespace;
+        }
+    }
+    runs.push(&s[start..]);
+    runs
+}
+
+fn highlight_padding(old: &str, new: &str) -> String {
+    let old_runs = whitespace_runs(old);
+    let new_runs = whitespace_runs(new);
+    if old_runs.len() != new_runs.len() {
+        // Only whitespace at the end of the line differs.
+        let common = old_runs.len().min(new_runs.len());
+        let mut output = old_runs[..common].concat();
+        output.push_str(&format!("[-{}-]", old_runs[common..].concat()));
+        output.push_str(&format!("{{+{}+}}", new_runs[common..].concat()));
+        return output.replace("[--]", "").replace("{++}", "");
+    }
+
+    let mut output = String::new();
+    for (i, (o, n)) in old_runs.iter().zip(new_runs.iter()).enumerate() {
+        if i % 2 == 1 || o == n {
+            output.push_str(n);
+        } else if let Some(inserted) = n.strip_prefix(o) {
+            output.push_str(&format!("{o}{{+{inserted}+}}"));
+        } else if let Some(removed) = o.strip_prefix(n) {
+            output.push_str(&format!("{n}[-{removed}-]"));
+        } else {
+            output.push_str(&format!("[-{o}-]{{+{n}+}}"));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_highlighting() {
+        assert_eq!(highlight_padding("a = 1", "a   = 1"), "a {+  +}= 1");
+        assert_eq!(highlight_padding("a   = 1", "a = 1"), "a [-  -]= 1");
+        assert_eq!(highlight_padding("a\t= 1", "a = 1"), "a[-\t-]{+ +}= 1");
+        assert_eq!(highlight_padding("a =", "a = "), "a ={+ +}");
+    }
+
+    #[test]
+    fn diffing() {
+        assert_eq!(render_diff("f", "a\n", "a\n"), None);
+        assert_eq!(
+            render_diff("f", "x\n1 = 1\n111 = 1\n", "x\n1   = 1\n111 = 1\n").unwrap(),
+            "--- f\n+++ f\n@@ -1,3 +1,3 @@\n x\n~1 {+  +}= 1\n 111 = 1\n"
+        );
+        assert_eq!(
+            render_diff("f", "b\na\n", "a\nb\n").unwrap(),
+            "--- f\n+++ f\n@@ -1,2 +1,2 @@\n+a\n b\n-a\n"
+        );
+    }
+
+    #[test]
+    fn counting_changed_lines() {
+        assert_eq!(changed_lines("a\n", "a\n"), 0);
+        assert_eq!(
+            changed_lines("x\n1 = 1\n111 = 1\n", "x\n1   = 1\n111 = 1\n"),
+            1
+        );
+        assert_eq!(changed_lines("b\na\n", "a\nb\n"), 1);
+        assert_eq!(changed_lines("a\na\n", "a\n"), 1);
+    }
+
+    #[test]
+    fn applying_changes() {
+        let old = "x\r\na = 1\r\nbbb = 2\r\ny\r\nc = 3\r\ndd = 4";
+        let new = "x\r\na   = 1\r\nbbb = 2\r\ny\r\nc  = 3\r\ndd = 4";
+        assert_eq!(apply_changes(old, new, |_, _| true), new);
+        assert_eq!(apply_changes(old, new, |_, _| false), old);
+
+        let mut changes = Vec::new();
+        let applied = apply_changes(old, new, |line, change| {
+            changes.push((line, change.to_string()));
+            line == 5
+        });
+        assert_eq!(applied, "x\r\na = 1\r\nbbb = 2\r\ny\r\nc  = 3\r\ndd = 4");
+        assert_eq!(
+            changes,
+            [
+                (2, "~a {+  +}= 1\n".to_string()),
+                (5, "~c {+ +}= 3\n".to_string())
+            ]
+        );
+
+        let old = "b\na\na\nc\n";
+        let new = "a\nb\nc\n";
+        assert_eq!(apply_changes(old, new, |_, _| true), new);
+        assert_eq!(apply_changes(old, new, |_, _| false), old);
+        assert_eq!(change_starts(old, new), [1, 2]);
+    }
+}
@@ -0,0 +1,1533 @@
+use crate::align::extract_quote;
+
+/// Which side of a cell the padding goes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// Pad after the cell, the default.
+    Left,
+    /// Pad before the cell, anchoring its end to the following delimiter.
+    Right,
+    /// Pad around the cell so the first `.` of every cell lines up, cells
+    /// without one are treated as if it followed their last non space character.
+    Decimal,
+    /// Split the padding between both sides of the cell, with the extra space
+    /// after it when the padding is odd.
+    Center,
+}
+
+impl Alignment {
+    /// Splits `cell` before the `.` cells are lined up on with [`Alignment::Decimal`].
+    pub fn decimal_split(cell: &str) -> usize {
+        cell.find('.').unwrap_or(cell.trim_end().len())
+    }
+
+    /// The word that selects this side in an alignment statement.
+    pub fn name(self) -> &'static str {
+        match self {
+            Alignment::Left => "left",
+            Alignment::Right => "right",
+            Alignment::Decimal => "decimal",
+            Alignment::Center => "center",
+        }
+    }
+}
+
+/// A delimiter to align on, and how the cell before it is padded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delimiter {
+    pub text: String,
+    pub alignment: Alignment,
+}
+
+/// The marker that starts an alignment statement unless configured otherwise.
+pub const DEFAULT_MARKER: &str = "align_by";
+
+/// Comment openers a directive marker may directly follow, longest first so
+/// `<!--` is preferred over `--`.
+pub const COMMENT_OPENERS: [&str; 8] = ["<!--", "///", "//!", "//", "/*", "--", "#", ";"];
+
+/// How the lines of a block are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sort {
+    /// Largest first, from a `desc` modifier.
+    pub descending: bool,
+    /// Ignore case, from a `ci` or `nocase` modifier.
+    pub case_insensitive: bool,
+    /// The 1 based columns to sort by from `sort:N,M,...`, later columns only
+    /// break ties of earlier ones. The whole line is the key if empty.
+    ///
+    /// Column 1 is the cell before the first delimiter, the last column is the
+    /// text after the last delimiter.
+    pub columns: Vec<usize>,
+    /// The name of the comparator that orders the keys instead of comparing
+    /// them as text, from `sort:<name>` or `sort:<name>:N,M,...`.
+    pub comparator: Option<String>,
+    /// The locale whose rules order the keys as text, from `locale:<tag>`.
+    /// Only builds with the `icu` feature support any.
+    pub locale: Option<String>,
+    /// Lines that start a new run, from `runs "<pattern>"`. They stay where
+    /// they are and the lines after each are sorted among themselves, so
+    /// section comments keep their lines below them.
+    pub runs: Option<SkipPattern>,
+}
+
+impl Sort {
+    /// Orders two lines of a block.
+    pub fn compare(&self, a: &str, b: &str) -> std::cmp::Ordering {
+        self.compare_by(a, b, |a, b| a.cmp(b))
+    }
+
+    /// Orders two lines of a block with `order` instead of comparing them as
+    /// text, reversed for `desc`.
+    ///
+    /// Lines that only differ in case are ordered by their original text when
+    /// ignoring case, so the result doesn't depend on the input order.
+    pub fn compare_by(
+        &self,
+        a: &str,
+        b: &str,
+        order: impl Fn(&str, &str) -> std::cmp::Ordering,
+    ) -> std::cmp::Ordering {
+        let ordering = if self.case_insensitive {
+            order(&a.to_lowercase(), &b.to_lowercase()).then_with(|| order(a, b))
+        } else {
+            order(a, b)
+        };
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Lines a block passes over without aligning them, from `skip "<pattern>"`.
+///
+/// The pattern is plain text found anywhere in the line, unless it starts with
+/// `^` which anchors the rest to the start of the line after its indentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkipPattern {
+    text: String,
+    anchored: bool,
+}
+
+impl SkipPattern {
+    fn new(pattern: &str) -> SkipPattern {
+        match pattern.strip_prefix('^') {
+            Some(text) => SkipPattern {
+                text: text.to_string(),
+                anchored: true,
+            },
+            None => SkipPattern {
+                text: pattern.to_string(),
+                anchored: false,
+            },
+        }
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        if self.anchored {
+            line.trim_start().starts_with(&self.text)
+        } else {
+            line.contains(&self.text)
+        }
+    }
+}
+
+/// A parsed `align_by` statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    /// The span of the comment opener the marker follows, if any.
+    pub prefix: Option<Span>,
+    pub sort: Option<Sort>,
+    /// Drop lines that are exact duplicates of an earlier line once aligned,
+    /// from a `dedup` modifier.
+    pub dedup: bool,
+    /// The number of lines the block has, from `count N`.
+    pub count: Option<usize>,
+    /// Align this many lines before the statement instead of the ones after
+    /// it, from `above N`.
+    pub above: Option<usize>,
+    /// Lines inside the block to leave as they are, from `skip "<pattern>"`.
+    pub skip: Option<SkipPattern>,
+    /// Pass blank lines through instead of ending the block, from `keep_blank`.
+    pub keep_blank: bool,
+    /// Blank lines split the block into groups that are aligned and sorted on
+    /// their own, from `groups`.
+    pub groups: bool,
+    /// The name of the group whose blocks share column widths, from `group(<name>)`.
+    pub group: Option<String>,
+    /// The least number of spaces between a cell and the delimiter after it, from `gap=N`.
+    pub gap: Option<usize>,
+    /// The 0 based character column each delimiter starts at, from
+    /// `columns "<positions>"`. Cells too wide for their position push the
+    /// delimiter further right instead.
+    pub columns: Vec<usize>,
+    /// The 1 based cell each cell position takes its text from, from
+    /// `reorder "<cells>"`. Positions past the end of the list keep their text.
+    pub reorder: Vec<usize>,
+    /// Cells wider than this are left unpadded and don't widen their column,
+    /// from `max=N`.
+    pub max_width: Option<usize>,
+    /// Aligned lines wider than this have the text after their last delimiter
+    /// wrapped onto lines indented to where it starts, from `wrap=N`.
+    pub wrap: Option<usize>,
+    /// Keep the indentation and the whitespace inside cells instead of
+    /// collapsing it, from `raw` or `preserve`.
+    pub raw: bool,
+    /// Split lines on every occurrence of the delimiters, starting over with
+    /// the first after the last, from `all`.
+    pub all: bool,
+    /// Remove whitespace at the end of every line of the block, from `trim`.
+    pub trim: bool,
+    /// The keyword of the mode that transforms the block instead of aligning
+    /// it on delimiters, like `table`, `delimiters` is empty.
+    pub block_mode: Option<String>,
+    /// Leave the block ending on this statement as it was, from `cancel_block`.
+    /// Aligns nothing itself, `delimiters` is empty.
+    pub cancel_block: bool,
+    pub delimiters: Vec<Delimiter>,
+}
+
+impl Directive {
+    /// What the statement does, the keyword of its mode like `table`,
+    /// `cancel_block`, `sort` or `normal`.
+    pub fn mode(&self) -> &str {
+        if let Some(keyword) = &self.block_mode {
+            keyword
+        } else if self.cancel_block {
+            "cancel_block"
+        } else if self.sort.is_some() {
+            "sort"
+        } else {
+            "normal"
+        }
+    }
+}
+
+/// The ways an alignment statement can be malformed.
+///
+/// Each kind has a stable code that can be passed to `--explain` for a longer
+/// description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidAlignmentStatement {
+    UnexpectedEOF,
+    MissingSpace,
+    EmptyDelimiters,
+    UnterminatedQuote,
+    UnknownSide,
+    ExpectedQuote,
+    InvalidSortColumn,
+    InvalidCount,
+    EmptySkipPattern,
+    InvalidGroupName,
+    InvalidGap,
+    InvalidColumns,
+    InvalidMaxWidth,
+    InvalidAbove,
+    InvalidReorder,
+    UnknownKeyword,
+    UnknownComparator,
+    LineTooLong,
+    BlockTooLong,
+    UnknownLocale,
+    InvalidWrap,
+}
+
+impl InvalidAlignmentStatement {
+    pub const ALL: [InvalidAlignmentStatement; 21] = [
+        InvalidAlignmentStatement::UnexpectedEOF,
+        InvalidAlignmentStatement::MissingSpace,
+        InvalidAlignmentStatement::EmptyDelimiters,
+        InvalidAlignmentStatement::UnterminatedQuote,
+        InvalidAlignmentStatement::UnknownSide,
+        InvalidAlignmentStatement::ExpectedQuote,
+        InvalidAlignmentStatement::InvalidSortColumn,
+        InvalidAlignmentStatement::InvalidCount,
+        InvalidAlignmentStatement::EmptySkipPattern,
+        InvalidAlignmentStatement::InvalidGroupName,
+        InvalidAlignmentStatement::InvalidGap,
+        InvalidAlignmentStatement::InvalidColumns,
+        InvalidAlignmentStatement::InvalidMaxWidth,
+        InvalidAlignmentStatement::InvalidAbove,
+        InvalidAlignmentStatement::InvalidReorder,
+        InvalidAlignmentStatement::UnknownKeyword,
+        InvalidAlignmentStatement::UnknownComparator,
+        InvalidAlignmentStatement::LineTooLong,
+        InvalidAlignmentStatement::BlockTooLong,
+        InvalidAlignmentStatement::UnknownLocale,
+        InvalidAlignmentStatement::InvalidWrap,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            InvalidAlignmentStatement::UnexpectedEOF => "A0001",
+            InvalidAlignmentStatement::MissingSpace => "A0002",
+            InvalidAlignmentStatement::EmptyDelimiters => "A0003",
+            InvalidAlignmentStatement::UnterminatedQuote => "A0004",
+            InvalidAlignmentStatement::UnknownSide => "A0005",
+            InvalidAlignmentStatement::ExpectedQuote => "A0006",
+            InvalidAlignmentStatement::InvalidSortColumn => "A0007",
+            InvalidAlignmentStatement::InvalidCount => "A0008",
+            InvalidAlignmentStatement::EmptySkipPattern => "A0009",
+            InvalidAlignmentStatement::InvalidGroupName => "A0010",
+            InvalidAlignmentStatement::InvalidGap => "A0011",
+            InvalidAlignmentStatement::InvalidColumns => "A0012",
+            InvalidAlignmentStatement::InvalidMaxWidth => "A0013",
+            InvalidAlignmentStatement::InvalidAbove => "A0014",
+            InvalidAlignmentStatement::InvalidReorder => "A0015",
+            InvalidAlignmentStatement::UnknownKeyword => "A0016",
+            InvalidAlignmentStatement::UnknownComparator => "A0017",
+            InvalidAlignmentStatement::LineTooLong => "A0018",
+            InvalidAlignmentStatement::BlockTooLong => "A0019",
+            InvalidAlignmentStatement::UnknownLocale => "A0020",
+            InvalidAlignmentStatement::InvalidWrap => "A0021",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<InvalidAlignmentStatement> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.code().eq_ignore_ascii_case(code))
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            InvalidAlignmentStatement::UnexpectedEOF => {
+                "the alignment statement ended before its delimiters"
+            }
+            InvalidAlignmentStatement::MissingSpace => "expected a space after the marker",
+            InvalidAlignmentStatement::EmptyDelimiters => "the delimiter list is empty",
+            InvalidAlignmentStatement::UnterminatedQuote => "the delimiter list is never closed",
+            InvalidAlignmentStatement::UnknownSide => {
+                "expected `left`, `right`, `center` or `decimal` as the side"
+            }
+            InvalidAlignmentStatement::ExpectedQuote => "expected a quoted delimiter list",
+            InvalidAlignmentStatement::InvalidSortColumn => "the sort column doesn't exist",
+            InvalidAlignmentStatement::InvalidCount => "expected a number of lines after `count`",
+            InvalidAlignmentStatement::EmptySkipPattern => "the pattern matches every line",
+            InvalidAlignmentStatement::InvalidGroupName => "expected a group name in parentheses",
+            InvalidAlignmentStatement::InvalidGap => "expected a number of spaces after `gap=`",
+            InvalidAlignmentStatement::InvalidColumns => "expected a list of column positions",
+            InvalidAlignmentStatement::InvalidMaxWidth => "expected a positive width after `max=`",
+            InvalidAlignmentStatement::InvalidAbove => "expected a number of lines after `above`",
+            InvalidAlignmentStatement::InvalidReorder => {
+                "expected each existing cell at most once in the new order"
+            }
+            InvalidAlignmentStatement::UnknownKeyword => "unknown keyword",
+            InvalidAlignmentStatement::UnknownComparator => "unknown comparator",
+            InvalidAlignmentStatement::LineTooLong => "a line of the block is too long",
+            InvalidAlignmentStatement::BlockTooLong => "the block has too many lines",
+            InvalidAlignmentStatement::UnknownLocale => "unknown or unsupported locale",
+            InvalidAlignmentStatement::InvalidWrap => "expected a positive width after `wrap=`",
+        }
+    }
+
+    /// The long form description printed by `--explain`.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            InvalidAlignmentStatement::UnexpectedEOF => concat!(
+                "The line ended before the alignment statement gave a list of delimiters.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by sort\n",
+                "\n",
+                "Every alignment statement needs a quoted list of delimiters after its keywords:\n",
+                "\n",
+                "    // align_by sort \"=\"\n",
+            ),
+            InvalidAlignmentStatement::MissingSpace => concat!(
+                "The marker was directly followed by the delimiter list.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by\"=\"\n",
+                "\n",
+                "The marker and the rest of the statement are separated by whitespace:\n",
+                "\n",
+                "    // align_by \"=\"\n",
+            ),
+            InvalidAlignmentStatement::EmptyDelimiters => concat!(
+                "The delimiter list didn't contain any delimiters, so there is nothing to align on.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by \" \"\n",
+                "\n",
+                "Delimiters are separated by spaces, so a list of only spaces is empty.\n",
+                "Give at least one delimiter:\n",
+                "\n",
+                "    // align_by \"=\"\n",
+            ),
+            InvalidAlignmentStatement::UnterminatedQuote => concat!(
+                "The delimiter list was opened with `\"` but never closed.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by \"= ;\n",
+                "\n",
+                "Close the list with another `\"`, a `\"` inside the list is escaped with `\\`:\n",
+                "\n",
+                "    // align_by \"= ;\"\n",
+            ),
+            InvalidAlignmentStatement::UnknownSide => concat!(
+                "A `then` group or `:` suffix didn't say which side its cells are padded on.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by \"=\" then \";\"\n",
+                "\n",
+                "`then` is followed by either `left`, `right`, `center` or `decimal`, and so is the `:`\n",
+                "directly after a delimiter list:\n",
+                "\n",
+                "    // align_by \"=\" then right \";\"\n",
+                "    // align_by \"=\" \";\":right\n",
+            ),
+            InvalidAlignmentStatement::ExpectedQuote => concat!(
+                "A `then` group wasn't followed by its delimiter list.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by \"=\" then right ;\n",
+                "\n",
+                "Delimiters are always quoted:\n",
+                "\n",
+                "    // align_by \"=\" then right \";\"\n",
+            ),
+            InvalidAlignmentStatement::InvalidSortColumn => concat!(
+                "`sort:` was followed by something other than a comma separated list of column numbers\n",
+                "or the name of a comparator.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by sort:3 \"=\"\n",
+                "\n",
+                "Columns are numbered from 1, column 1 is the text before the first delimiter\n",
+                "and the last column is the text after the last delimiter, so a statement with\n",
+                "one delimiter has two columns:\n",
+                "\n",
+                "    // align_by sort:2 \"=\"\n",
+                "\n",
+                "A comparator name starts with a letter and can be followed by the columns to\n",
+                "sort by, ie `sort:natural:2`.\n",
+            ),
+            InvalidAlignmentStatement::InvalidCount => concat!(
+                "`count` wasn't followed by the number of lines the block is limited to.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by count \"=\"\n",
+                "\n",
+                "The number is separated from `count` by whitespace and is at least 1:\n",
+                "\n",
+                "    // align_by count 2 \"=\"\n",
+            ),
+            InvalidAlignmentStatement::EmptySkipPattern => concat!(
+                "The pattern after `skip` or `runs` was empty, or only `^`, so it would match\n",
+                "every line.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by skip \"^\" \"=\"\n",
+                "\n",
+                "Give the text that lines to skip contain, or start with when it follows `^`:\n",
+                "\n",
+                "    // align_by skip \"^//\" \"=\"\n",
+            ),
+            InvalidAlignmentStatement::InvalidGroupName => concat!(
+                "`group(` wasn't followed by a name and a closing `)`.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                // Split so this line isn't an invalid statement itself.
+                "    // align_by group", "() \"=\"\n",
+                "\n",
+                "Blocks with the same name share their column widths, the name can't contain\n",
+                "whitespace or `\"`:\n",
+                "\n",
+                "    // align_by group(fields) \"=\"\n",
+            ),
+            InvalidAlignmentStatement::InvalidGap => concat!(
+                "`gap=` wasn't followed by the number of spaces to put before each delimiter.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                // Split so this line isn't an invalid statement itself.
+                "    // align_by gap", "=two \"//\"\n",
+                "\n",
+                "The number is written with digits and can be 0 for no space at all:\n",
+                "\n",
+                "    // align_by gap=2 \"//\"\n",
+            ),
+            InvalidAlignmentStatement::InvalidColumns => concat!(
+                "The quoted list after `columns` wasn't a list of column positions.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by columns \"=\" \"//\"\n",
+                "\n",
+                "The list holds the 0 based column each delimiter should start at, separated\n",
+                "by spaces, followed by the delimiters themselves:\n",
+                "\n",
+                "    // align_by columns \"10 40\" \"= //\"\n",
+            ),
+            InvalidAlignmentStatement::InvalidMaxWidth => concat!(
+                "`max=` wasn't followed by the widest a cell can be and still be aligned.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                // Split so this line isn't an invalid statement itself.
+                "    // align_by max", "=0 \"=\"\n",
+                "\n",
+                "The width is at least 1, wider cells are left as they are:\n",
+                "\n",
+                "    // align_by max=40 \"=\"\n",
+            ),
+            InvalidAlignmentStatement::InvalidAbove => concat!(
+                "`above` wasn't followed by the number of lines before the statement to align.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by above \"=\"\n",
+                "\n",
+                "The number is separated from `above` by whitespace and is at least 1:\n",
+                "\n",
+                "    // align_by above 2 \"=\"\n",
+            ),
+            InvalidAlignmentStatement::InvalidReorder => concat!(
+                "The quoted list after `reorder` wasn't a new order of the cells.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by reorder \"1 1 4\" \",\"\n",
+                "\n",
+                "The list holds the 1 based cells in the order they should be written, each\n",
+                "at most once. A statement with two delimiters has three cells:\n",
+                "\n",
+                "    // align_by reorder \"3 1 2\" \",\"\n",
+            ),
+            InvalidAlignmentStatement::UnknownKeyword => concat!(
+                "With `--strict` every marker starts an alignment statement, and a word in it\n",
+                "wasn't one of the keywords.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by sotr \"=\"\n",
+                "\n",
+                "The keywords are `dedup`, `count`, `above`, `skip`, `keep_blank`, `groups`,\n",
+                "`group(<name>)`, `gap=`, `max=`, `wrap=`, `columns`, `reorder`, `all`, `trim`,\n",
+                "`center`, `raw`, `preserve`, `sort`, `asc`, `desc`, `ci`, `nocase`, `locale:`,\n",
+                "`runs`, `table` and `cancel_block`.\n",
+                "Prose that mentions the marker can escape it with a `\\`, ie `\\align_by`.\n",
+            ),
+            InvalidAlignmentStatement::UnknownComparator => concat!(
+                "`sort:` named a comparator that isn't registered.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by sort:semver \"=\"\n",
+                "\n",
+                "`natural` is always there, it compares runs of digits by their value so\n",
+                "`item2` comes before `item10` and `1.9.0` before `1.10.0`:\n",
+                "\n",
+                "    // align_by sort:natural \"=\"\n",
+                "\n",
+                "Programs using the library can register more with `AlignOptions::comparator`.\n",
+            ),
+            InvalidAlignmentStatement::LineTooLong => concat!(
+                "A line of a block is longer than the limit, 10000 bytes by default. Lines\n",
+                "that long are usually minified or generated content that ended up under\n",
+                "an alignment statement by accident.\n",
+                "\n",
+                "Erroneous example, with `--max-line-length 20`:\n",
+                "\n",
+                "    // align_by \"=\"\n",
+                "    let table = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];\n",
+                "\n",
+                "Move the statement away from the long lines, or raise the limit with\n",
+                "`--max-line-length <N>`, `none` turns it off.\n",
+            ),
+            InvalidAlignmentStatement::BlockTooLong => concat!(
+                "A block has more lines than the limit, 100000 by default. Blocks that\n",
+                "long are usually generated content that ended up under an alignment\n",
+                "statement by accident.\n",
+                "\n",
+                "Erroneous example, with `--max-block-lines 2`:\n",
+                "\n",
+                "    // align_by \"=\"\n",
+                "    a = 1\n",
+                "    b = 2\n",
+                "    c = 3\n",
+                "\n",
+                "Split the block with more statements or `count N`, or raise the limit with\n",
+                "`--max-block-lines <N>`, `none` turns it off.\n",
+            ),
+            InvalidAlignmentStatement::UnknownLocale => concat!(
+                "`locale:` named something that isn't a locale, or cargo-align was built\n",
+                "without the `icu` feature that sorting by a locale needs.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                "    // align_by sort locale:de.UTF-8 \"=\"\n",
+                "\n",
+                "Locales are BCP 47 tags like `de`, `sv` or `zh-Hant`:\n",
+                "\n",
+                "    // align_by sort locale:de \"=\"\n",
+                "\n",
+                "Install with `cargo install cargo-align --features icu` to sort by locales.\n",
+            ),
+            InvalidAlignmentStatement::InvalidWrap => concat!(
+                "`wrap=` wasn't followed by the widest an aligned line can be before the\n",
+                "text after its last delimiter is wrapped.\n",
+                "\n",
+                "Erroneous example:\n",
+                "\n",
+                // Split so this line isn't an invalid statement itself.
+                "    // align_by wrap", "=0 \"=\" \"//\"\n",
+                "\n",
+                "The width is at least 1, usually the line width of the project:\n",
+                "\n",
+                "    // align_by wrap=100 \"=\" \"//\"\n",
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for InvalidAlignmentStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.code(), self.message())
+    }
+}
+
+/// A byte range within a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// The 1 based column, in characters, the span starts at in `line`.
+    pub fn column(self, line: &str) -> usize {
+        line[..self.start].chars().count() + 1
+    }
+}
+
+/// An invalid alignment statement, with the span of the part at fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: InvalidAlignmentStatement,
+    pub span: Span,
+}
+
+impl InvalidAlignmentStatement {
+    fn at(self, span: Span) -> ParseError {
+        ParseError { kind: self, span }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Quote(String),
+}
+
+/// Lazily splits a line into whitespace separated words and quoted strings,
+/// so anything after the last token a caller asks for is never looked at.
+/// Every token carries its span within the whole line.
+///
+/// Trailing words made only of comment decoration, like the `*/` closing a
+/// block comment or the right border of a boxed comment, end the line instead.
+struct Tokens<'a> {
+    line: &'a str,
+    position: usize,
+}
+
+/// Tokenizes `line` starting at byte offset `position`.
+fn tokenize(line: &str, position: usize) -> Tokens<'_> {
+    Tokens { line, position }
+}
+
+impl Tokens<'_> {
+    /// The span just past the last non whitespace character, where a missing
+    /// token would have been.
+    fn end_span(&self) -> Span {
+        let end = self.line.trim_end().len();
+        Span::new(end, end)
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<(Token<'a>, Span), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.line[self.position..];
+        let start = self.position + (rest.len() - rest.trim_start().len());
+        let rest = &self.line[start..];
+        if !rest.starts_with('"') && rest.chars().all(|c| c.is_whitespace() || is_decoration(c)) {
+            self.line = &self.line[..start];
+        }
+        if start >= self.line.len() {
+            self.position = self.line.len();
+            return None;
+        }
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let content = extract_quote(quoted);
+            if quoted.len() == content.len() {
+                self.position = self.line.len();
+                let span = Span::new(start, self.line.len());
+                return Some(Err(InvalidAlignmentStatement::UnterminatedQuote.at(span)));
+            }
+            self.position = start + content.len() + 2;
+            Some(Ok((Token::Quote(content), Span::new(start, self.position))))
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || c == '"')
+                .unwrap_or(rest.len());
+            self.position = start + len;
+            Some(Ok((
+                Token::Word(&rest[..len]),
+                Span::new(start, self.position),
+            )))
+        }
+    }
+}
+
+fn delimiters(quote: &str, alignment: Alignment) -> impl Iterator<Item = Delimiter> + '_ {
+    quote.split_ascii_whitespace().map(move |text| Delimiter {
+        text: text.to_string(),
+        alignment,
+    })
+}
+
+/// The side named by `then <side>` or a `:<side>` suffix.
+fn side(word: &str) -> Option<Alignment> {
+    match word {
+        "left" => Some(Alignment::Left),
+        "right" => Some(Alignment::Right),
+        "decimal" => Some(Alignment::Decimal),
+        "center" => Some(Alignment::Center),
+        _ => None,
+    }
+}
+
+/// Characters comments are opened, closed and decorated with, like the borders
+/// of a boxed comment.
+fn is_decoration(c: char) -> bool {
+    "*/#;-!<>|+%".contains(c)
+}
+
+/// The span of the comment opener `before` ends with, along with any
+/// decoration following it like the `*` in `//*` or the `|` in `# |`, ignoring
+/// whitespace. A lone `*` at the start of the line continuing a block comment
+/// counts as an opener too.
+pub fn comment_prefix(before: &str) -> Option<Span> {
+    let trimmed = before.trim_end();
+    let undecorated = trimmed.trim_end_matches(|c: char| c.is_whitespace() || is_decoration(c));
+    let decoration = &trimmed[undecorated.len()..];
+    let opener_start = decoration.char_indices().map(|(i, _)| i).find(|&i| {
+        COMMENT_OPENERS
+            .iter()
+            .any(|opener| decoration[i..].starts_with(opener))
+    });
+    match opener_start {
+        Some(i) => Some(Span::new(undecorated.len() + i, trimmed.len())),
+        None if undecorated.is_empty() && decoration.trim_start().starts_with('*') => {
+            let start = trimmed.len() - decoration.trim_start().len();
+            Some(Span::new(start, trimmed.len()))
+        }
+        None => None,
+    }
+}
+
+/// The index of the first `marker` in `line` that isn't escaped by a `\`
+/// directly before it, like `\align_by` in text that only mentions the marker.
+fn find_marker(line: &str, marker: &str) -> Option<usize> {
+    line.match_indices(marker)
+        .map(|(index, _)| index)
+        .find(|&index| !line[..index].ends_with('\\'))
+}
+
+/// Whether `line` has an unescaped `<marker> stop`, which makes the rest of the
+/// file be skipped.
+pub fn is_stop(line: &str, marker: &str) -> bool {
+    let stop = [marker, " stop"].concat();
+    find_marker(line, &stop).is_some()
+}
+
+/// Whether `line` is a `<marker> cancel_block` statement, which leaves the
+/// block right before it as it was.
+pub fn is_cancel_block(line: &str, marker: &str) -> bool {
+    matches!(
+        parse_directive(line, marker),
+        Ok(Some(Directive {
+            cancel_block: true,
+            ..
+        }))
+    )
+}
+
+/// Parses what follows `sort:`, the name of a comparator, comma separated
+/// column numbers like `2,1`, or both separated by a `:`.
+fn parse_sort(spec: &str) -> Option<Sort> {
+    if let Some(columns) = parse_sort_columns(spec) {
+        return Some(Sort {
+            columns,
+            ..Sort::default()
+        });
+    }
+    let (name, columns) = match spec.split_once(':') {
+        Some((name, columns)) => (name, parse_sort_columns(columns)?),
+        None => (spec, Vec::new()),
+    };
+    let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    is_name.then(|| Sort {
+        columns,
+        comparator: Some(name.to_string()),
+        ..Sort::default()
+    })
+}
+
+/// Parses the comma separated column numbers after `sort:`, like `2,1`.
+fn parse_sort_columns(spec: &str) -> Option<Vec<usize>> {
+    spec.split(',')
+        .map(|column| column.parse().ok().filter(|&column| column > 0))
+        .collect()
+}
+
+/// Parses the space separated cell numbers of `reorder "<cells>"`, which have
+/// to be positive and distinct.
+fn parse_reorder(spec: &str) -> Option<Vec<usize>> {
+    let cells = spec
+        .split_ascii_whitespace()
+        .map(|cell| cell.parse().ok().filter(|&cell| cell > 0))
+        .collect::<Option<Vec<usize>>>()?;
+    let distinct = cells
+        .iter()
+        .enumerate()
+        .all(|(index, cell)| !cells[..index].contains(cell));
+    (!cells.is_empty() && distinct).then_some(cells)
+}
+
+/// The words a statement can start with, for suggestions on typos.
+const KEYWORDS: [&str; 26] = [
+    "dedup",
+    "count",
+    "above",
+    "skip",
+    "keep_blank",
+    "groups",
+    "group(",
+    "gap=",
+    "max=",
+    "wrap=",
+    "columns",
+    "reorder",
+    "all",
+    "trim",
+    "center",
+    "raw",
+    "preserve",
+    "sort",
+    "asc",
+    "desc",
+    "ci",
+    "nocase",
+    "locale:",
+    "runs",
+    "table",
+    "cancel_block",
+];
+
+/// The keyword `word` is most likely a typo of, if any is close enough.
+pub fn suggest_keyword(word: &str) -> Option<&'static str> {
+    KEYWORDS
+        .into_iter()
+        .map(|keyword| (edit_distance(word, keyword), keyword))
+        .filter(|&(distance, _)| distance <= 2 && distance < word.chars().count())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, keyword)| keyword)
+}
+
+/// The number of inserted, removed or replaced characters between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != b);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Parses the directive started by `marker` on `line`, if it has one.
+///
+/// The grammar is `<marker> [dedup] [count N] [above N] [skip "<pattern>"] [keep_blank] [groups] [group(<name>)] [gap=N] [max=N] [wrap=N] [columns "<positions>"] [reorder "<cells>"] [all] [trim] [center] [raw|preserve] [sort[:N,...] [asc|desc] [ci|nocase] [locale:<tag>] [runs "<pattern>"]] "<delimiters>"[:<side>] [[then <side>] "<delimiters>"[:<side>]]...`
+/// or `<marker> table` or `<marker> cancel_block`, where `<side>` is `left`, `right`, `center` or `decimal`, the modifiers before the
+/// delimiters can come in any order and
+/// anything after the last recognized part is ignored. Trailing comment
+/// decoration is not part of the statement, so `/* align_by sort */` is missing
+/// its delimiters rather than having a `*/` word in their place. Lines where the marker is
+/// followed by an unknown word are not directives, so prose mentioning the
+/// marker is left alone.
+pub fn parse_directive(line: &str, marker: &str) -> Result<Option<Directive>, ParseError> {
+    parse(line, marker, false, &BUILTIN_MODES)
+}
+
+/// The keywords of the modes every statement can use.
+pub const BUILTIN_MODES: [&str; 1] = ["table"];
+
+/// Like [`parse_directive`], but `<marker> <keyword>` is a statement for each
+/// of the mode keywords `modes` instead of only for the built in ones.
+pub fn parse_directive_with_modes(
+    line: &str,
+    marker: &str,
+    modes: &[&str],
+) -> Result<Option<Directive>, ParseError> {
+    parse(line, marker, false, modes)
+}
+
+/// Like [`parse_directive`], but a marker followed by words that aren't a
+/// statement is an error instead of prose, for `--strict`.
+pub fn parse_directive_strict(line: &str, marker: &str) -> Result<Option<Directive>, ParseError> {
+    parse(line, marker, true, &BUILTIN_MODES)
+}
+
+pub(crate) fn parse(
+    line: &str,
+    marker: &str,
+    strict: bool,
+    modes: &[&str],
+) -> Result<Option<Directive>, ParseError> {
+    // Where a statement turns out to be prose, which only `strict` rejects.
+    let prose = |error: ParseError| if strict { Err(error) } else { Ok(None) };
+    let Some(index) = find_marker(line, marker) else {
+        return Ok(None);
+    };
+    let after_marker = index + marker.len();
+    let rest = &line[after_marker..];
+    // A marker wrapped in quotes, like `"align_by"`, is a string literal and not a statement.
+    if rest.starts_with('"') && !line[..index].ends_with('"') {
+        let span = Span::new(after_marker, after_marker + 1);
+        return Err(InvalidAlignmentStatement::MissingSpace.at(span));
+    }
+    if !rest.starts_with(char::is_whitespace) {
+        return Ok(None);
+    }
+
+    let prefix = comment_prefix(&line[..index]);
+    let mut tokens = tokenize(line, after_marker);
+    let mut sort: Option<Sort> = None;
+    let mut sort_column_span = None;
+    let mut locale_span = None;
+    let mut dedup = false;
+    let mut count = None;
+    let mut above = None;
+    let mut skip = None;
+    let mut keep_blank = false;
+    let mut groups = false;
+    let mut group = None;
+    let mut gap = None;
+    let mut max_width = None;
+    let mut wrap = None;
+    let mut raw = false;
+    let mut columns = Vec::new();
+    let mut reorder = Vec::new();
+    let mut reorder_span = None;
+    let mut all = false;
+    let mut trim = false;
+    // The side of delimiter lists without a `then <side>` or `:<side>`.
+    let mut default_side = Alignment::Left;
+    // Whether any word was recognized yet, `table` has to come first.
+    let mut has_modifiers = false;
+    let first_quote = loop {
+        match (tokens.next().transpose()?, &mut sort) {
+            (None, _) if !has_modifiers => return Ok(None),
+            (None, _) => return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span())),
+            (Some((Token::Word("dedup"), _)), _) if !dedup => dedup = true,
+            (Some((Token::Word("keep_blank"), _)), _) if !keep_blank => keep_blank = true,
+            (Some((Token::Word("groups"), _)), _) if !groups => groups = true,
+            (Some((Token::Word("all"), _)), _) if !all => all = true,
+            (Some((Token::Word("trim"), _)), _) if !trim => trim = true,
+            (Some((Token::Word("raw" | "preserve"), _)), _) if !raw => raw = true,
+            (Some((Token::Word("center"), _)), _) if default_side == Alignment::Left => {
+                default_side = Alignment::Center
+            }
+            (Some((Token::Word(word), span)), _)
+                if group.is_none() && word.starts_with("group(") =>
+            {
+                let name = word["group(".len()..]
+                    .strip_suffix(')')
+                    .filter(|name| !name.is_empty())
+                    .ok_or(InvalidAlignmentStatement::InvalidGroupName.at(span))?;
+                group = Some(name.to_string());
+            }
+            (Some((Token::Word("count"), _)), _) if count.is_none() => {
+                count = match tokens.next().transpose()? {
+                    // Prose like "the align_by count of a file" isn't a statement.
+                    Some((Token::Word(number), span)) => match number.parse() {
+                        Ok(0) => return Err(InvalidAlignmentStatement::InvalidCount.at(span)),
+                        Ok(lines) => Some(lines),
+                        Err(_) => return prose(InvalidAlignmentStatement::InvalidCount.at(span)),
+                    },
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((_, span)) => return Err(InvalidAlignmentStatement::InvalidCount.at(span)),
+                }
+            }
+            (Some((Token::Word("above"), _)), _) if above.is_none() => {
+                above = match tokens.next().transpose()? {
+                    // Prose like "the align_by above this line" isn't a statement.
+                    Some((Token::Word(number), span)) => match number.parse() {
+                        Ok(0) => return Err(InvalidAlignmentStatement::InvalidAbove.at(span)),
+                        Ok(lines) => Some(lines),
+                        Err(_) => return prose(InvalidAlignmentStatement::InvalidAbove.at(span)),
+                    },
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((_, span)) => return Err(InvalidAlignmentStatement::InvalidAbove.at(span)),
+                }
+            }
+            (Some((Token::Word("skip"), _)), _) if skip.is_none() => {
+                skip = match tokens.next().transpose()? {
+                    Some((Token::Quote(pattern), span)) => match SkipPattern::new(&pattern) {
+                        SkipPattern { text, .. } if text.is_empty() => {
+                            return Err(InvalidAlignmentStatement::EmptySkipPattern.at(span))
+                        }
+                        pattern => Some(pattern),
+                    },
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((Token::Word(_), span)) => {
+                        return prose(InvalidAlignmentStatement::ExpectedQuote.at(span))
+                    }
+                }
+            }
+            (Some((Token::Word(word), span)), _) if gap.is_none() && word.starts_with("gap=") => {
+                gap = Some(
+                    word["gap=".len()..]
+                        .parse()
+                        .map_err(|_| InvalidAlignmentStatement::InvalidGap.at(span))?,
+                );
+            }
+            (Some((Token::Word(word), span)), _)
+                if max_width.is_none() && word.starts_with("max=") =>
+            {
+                max_width = Some(
+                    word["max=".len()..]
+                        .parse()
+                        .ok()
+                        .filter(|&width| width > 0)
+                        .ok_or(InvalidAlignmentStatement::InvalidMaxWidth.at(span))?,
+                );
+            }
+            (Some((Token::Word(word), span)), _) if wrap.is_none() && word.starts_with("wrap=") => {
+                wrap = Some(
+                    word["wrap=".len()..]
+                        .parse()
+                        .ok()
+                        .filter(|&width| width > 0)
+                        .ok_or(InvalidAlignmentStatement::InvalidWrap.at(span))?,
+                );
+            }
+            (Some((Token::Word("columns"), _)), _) if columns.is_empty() => {
+                columns = match tokens.next().transpose()? {
+                    Some((Token::Quote(positions), span)) => positions
+                        .split_ascii_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<Vec<_>, _>>()
+                        .ok()
+                        .filter(|positions| !positions.is_empty())
+                        .ok_or(InvalidAlignmentStatement::InvalidColumns.at(span))?,
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((Token::Word(_), span)) => {
+                        return prose(InvalidAlignmentStatement::InvalidColumns.at(span))
+                    }
+                }
+            }
+            (Some((Token::Word("reorder"), _)), _) if reorder.is_empty() => {
+                reorder = match tokens.next().transpose()? {
+                    Some((Token::Quote(cells), span)) => {
+                        reorder_span = Some(span);
+                        parse_reorder(&cells)
+                            .ok_or(InvalidAlignmentStatement::InvalidReorder.at(span))?
+                    }
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((Token::Word(_), span)) => {
+                        return prose(InvalidAlignmentStatement::InvalidReorder.at(span))
+                    }
+                }
+            }
+            (Some((Token::Word("sort"), _)), None) => sort = Some(Sort::default()),
+            (Some((Token::Word(word), span)), None) if word.starts_with("sort:") => {
+                sort = Some(
+                    parse_sort(&word["sort:".len()..])
+                        .ok_or(InvalidAlignmentStatement::InvalidSortColumn.at(span))?,
+                );
+                sort_column_span = Some(span);
+            }
+            (Some((Token::Word("asc"), _)), Some(sort)) => sort.descending = false,
+            (Some((Token::Word("desc"), _)), Some(sort)) => sort.descending = true,
+            (Some((Token::Word("ci" | "nocase"), _)), Some(sort)) => sort.case_insensitive = true,
+            (Some((Token::Word("runs"), _)), Some(sort)) if sort.runs.is_none() => {
+                sort.runs = match tokens.next().transpose()? {
+                    Some((Token::Quote(pattern), span)) => match SkipPattern::new(&pattern) {
+                        SkipPattern { text, .. } if text.is_empty() => {
+                            return Err(InvalidAlignmentStatement::EmptySkipPattern.at(span))
+                        }
+                        pattern => Some(pattern),
+                    },
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((Token::Word(_), span)) => {
+                        return prose(InvalidAlignmentStatement::ExpectedQuote.at(span))
+                    }
+                }
+            }
+            (Some((Token::Word(word), span)), Some(sort)) if word.starts_with("locale:") => {
+                sort.locale = Some(word["locale:".len()..].to_string());
+                locale_span = Some(span);
+            }
+            (Some((Token::Word(word), _)), _)
+                if !has_modifiers && (word == "cancel_block" || modes.contains(&word)) =>
+            {
+                if let Some(next) = tokens.next() {
+                    return prose(next.map_or_else(
+                        |err| err,
+                        |(_, span)| InvalidAlignmentStatement::UnknownKeyword.at(span),
+                    ));
+                }
+                return Ok(Some(Directive {
+                    prefix,
+                    sort,
+                    dedup,
+                    count,
+                    above,
+                    skip,
+                    keep_blank,
+                    groups,
+                    group,
+                    gap,
+                    columns,
+                    reorder,
+                    max_width,
+                    wrap,
+                    raw,
+                    all,
+                    trim,
+                    block_mode: (word != "cancel_block").then(|| word.to_string()),
+                    cancel_block: word == "cancel_block",
+                    delimiters: Vec::new(),
+                }));
+            }
+            (Some((Token::Word(_), span)), _) => {
+                return prose(InvalidAlignmentStatement::UnknownKeyword.at(span))
+            }
+            (Some((Token::Quote(quote), span)), _) => break (quote, span),
+        }
+        has_modifiers = true;
+    };
+    let mut parsed = delimiters(&first_quote.0, default_side).collect::<Vec<_>>();
+    if parsed.is_empty() {
+        return Err(InvalidAlignmentStatement::EmptyDelimiters.at(first_quote.1));
+    }
+
+    // The delimiters of the last quoted list, which a `:side` suffix applies to.
+    let mut last_group = 0..parsed.len();
+    loop {
+        let (alignment, (quote, quote_span)) = match tokens.next().transpose()? {
+            Some((Token::Word(word), span)) if word.starts_with(':') && !last_group.is_empty() => {
+                let alignment =
+                    side(&word[1..]).ok_or(InvalidAlignmentStatement::UnknownSide.at(span))?;
+                for delimiter in &mut parsed[last_group] {
+                    delimiter.alignment = alignment;
+                }
+                last_group = 0..0;
+                continue;
+            }
+            Some((Token::Word("then"), _)) => {
+                let alignment = match tokens.next().transpose()? {
+                    Some((Token::Word(word), span)) => {
+                        side(word).ok_or(InvalidAlignmentStatement::UnknownSide.at(span))?
+                    }
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((_, span)) => return Err(InvalidAlignmentStatement::UnknownSide.at(span)),
+                };
+                match tokens.next().transpose()? {
+                    Some((Token::Quote(quote), span)) => (alignment, (quote, span)),
+                    None => {
+                        return Err(InvalidAlignmentStatement::UnexpectedEOF.at(tokens.end_span()))
+                    }
+                    Some((_, span)) => {
+                        return Err(InvalidAlignmentStatement::ExpectedQuote.at(span))
+                    }
+                }
+            }
+            Some((Token::Quote(quote), span)) => (default_side, (quote, span)),
+            _ => break,
+        };
+        let group = delimiters(&quote, alignment).collect::<Vec<_>>();
+        if group.is_empty() {
+            return Err(InvalidAlignmentStatement::EmptyDelimiters.at(quote_span));
+        }
+        last_group = parsed.len()..parsed.len() + group.len();
+        parsed.extend(group);
+    }
+
+    // With `all` the number of columns depends on the line.
+    if let (Some(sort), Some(span), false) = (&sort, sort_column_span, all) {
+        if sort.columns.iter().any(|&column| column > parsed.len() + 1) {
+            return Err(InvalidAlignmentStatement::InvalidSortColumn.at(span));
+        }
+    }
+    if let (Some(tag), Some(span)) = (
+        sort.as_ref().and_then(|sort| sort.locale.as_deref()),
+        locale_span,
+    ) {
+        if !crate::collation::is_supported(tag) {
+            return Err(InvalidAlignmentStatement::UnknownLocale.at(span));
+        }
+    }
+    if let (Some(span), false) = (reorder_span, all) {
+        if reorder.iter().any(|&cell| cell > parsed.len() + 1) {
+            return Err(InvalidAlignmentStatement::InvalidReorder.at(span));
+        }
+    }
+
+    Ok(Some(Directive {
+        prefix,
+        sort,
+        dedup,
+        count,
+        above,
+        skip,
+        keep_blank,
+        groups,
+        group,
+        gap,
+        columns,
+        reorder,
+        max_width,
+        wrap,
+        raw,
+        all,
+        trim,
+        block_mode: None,
+        cancel_block: false,
+        delimiters: parsed,
+    }))
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use super::*;
+
+    fn delimiter(text: &str, alignment: Alignment) -> Delimiter {
+        Delimiter { text: text.to_string(), alignment }
+    }
+
+    fn tokens(s: &str) -> Result<Vec<Token<'_>>, InvalidAlignmentStatement> {
+        tokenize(s, 0).map(|token| token.map(|(token, _)| token).map_err(|err| err.kind)).collect()
+    }
+
+    fn error(line: &str) -> (InvalidAlignmentStatement, usize, usize) {
+        let err = parse_directive(line, DEFAULT_MARKER).unwrap_err();
+        (err.kind, err.span.start, err.span.end)
+    }
+
+    #[test]
+    fn tokenizing() {
+        assert_eq!(tokens(r#" sort "= ;" then"#), Ok(vec![Token::Word("sort"), Token::Quote("= ;".to_string()), Token::Word("then")]));
+        assert_eq!(tokens(r#""a"b"#), Ok(vec![Token::Quote("a".to_string()), Token::Word("b")]));
+        assert_eq!(tokens(r#""unterminated"#), Err(InvalidAlignmentStatement::UnterminatedQuote));
+        let spans = tokenize(r#"x  sort "= ;" then"#, 1).map(|token| token.unwrap().1).collect::<Vec<_>>();
+        assert_eq!(spans, vec![Span::new(3, 7), Span::new(8, 13), Span::new(14, 18)]);
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse_directive("let a = 1;", DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"align_by typo "=""#, DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"align_by typo "="#, DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive("the align_by marker", DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"line.find("align_by")"#, DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"// align_by "= ;""#, DEFAULT_MARKER), Ok(Some(Directive {
+            prefix: Some(Span::new(0, 2)),
+            sort: None,
+            dedup: false,
+            count: None,
+            above: None,
+            skip: None,
+            keep_blank: false,
+            groups: false,
+            group: None,
+            gap: None,
+            columns: vec![],
+            reorder: vec![],
+            max_width: None,
+            wrap: None,
+            raw: false,
+            all: false,
+            trim: false,
+            block_mode: None,
+            cancel_block: false,
+            delimiters: vec![delimiter("=", Alignment::Left), delimiter(";", Alignment::Left)],
+        })));
+        assert_eq!(parse_directive(r#"<!-- align_by sort "|" -->"#, DEFAULT_MARKER), Ok(Some(Directive {
+            prefix: Some(Span::new(0, 4)),
+            sort: Some(Sort::default()),
+            dedup: false,
+            count: None,
+            above: None,
+            skip: None,
+            keep_blank: false,
+            groups: false,
+            group: None,
+            gap: None,
+            columns: vec![],
+            reorder: vec![],
+            max_width: None,
+            wrap: None,
+            raw: false,
+            all: false,
+            trim: false,
+            block_mode: None,
+            cancel_block: false,
+            delimiters: vec![delimiter("|", Alignment::Left)],
+        })));
+        assert_eq!(parse_directive(r#"align_by "=" then right "// ""#, DEFAULT_MARKER), Ok(Some(Directive {
+            prefix: None,
+            sort: None,
+            dedup: false,
+            count: None,
+            above: None,
+            skip: None,
+            keep_blank: false,
+            groups: false,
+            group: None,
+            gap: None,
+            columns: vec![],
+            reorder: vec![],
+            max_width: None,
+            wrap: None,
+            raw: false,
+            all: false,
+            trim: false,
+            block_mode: None,
+            cancel_block: false,
+            delimiters: vec![delimiter("=", Alignment::Left), delimiter("//", Alignment::Right)],
+        })));
+        assert_eq!(parse_directive(r#"align_by sort desc "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { descending: true, ..Sort::default() }));
+        assert_eq!(parse_directive(r#"align_by sort desc asc "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort::default()));
+        assert_eq!(parse_directive(r#"align_by sort nocase desc "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { descending: true, case_insensitive: true, columns: vec![], comparator: None, locale: None, runs: None }));
+        assert_eq!(parse_directive(r#"align_by sort:3 desc "= ;""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { descending: true, case_insensitive: false, columns: vec![3], comparator: None, locale: None, runs: None }));
+        assert_eq!(parse_directive(r#"align_by sort:natural "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { comparator: Some("natural".to_string()), ..Sort::default() }));
+        assert_eq!(parse_directive(r#"align_by sort:semver-2:2,1 "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { comparator: Some("semver-2".to_string()), columns: vec![2, 1], ..Sort::default() }));
+        assert_eq!(parse_directive(r#"align_by sort:semver:3 "=""#, DEFAULT_MARKER).unwrap_err().kind, InvalidAlignmentStatement::InvalidSortColumn);
+        assert_eq!(parse_directive(r#"align_by sort:2x "=""#, DEFAULT_MARKER).unwrap_err().kind, InvalidAlignmentStatement::InvalidSortColumn);
+        assert_eq!(parse_directive(r#"align_by sort:2,1 "=""#, DEFAULT_MARKER).unwrap().unwrap().sort.unwrap().columns, vec![2, 1]);
+        assert!(parse_directive(r#"align_by trim "=""#, DEFAULT_MARKER).unwrap().unwrap().trim);
+        assert_eq!(parse_directive(r#"align_by sort runs "^//" "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { runs: Some(SkipPattern::new("^//")), ..Sort::default() }));
+        assert_eq!(parse_directive(r#"align_by sort runs "^" "=""#, DEFAULT_MARKER).unwrap_err().kind, InvalidAlignmentStatement::EmptySkipPattern);
+        assert_eq!(parse_directive(r#"align_by runs "--" "=""#, DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"align_by sort locale:de_DE! "=""#, DEFAULT_MARKER).unwrap_err().kind, InvalidAlignmentStatement::UnknownLocale);
+        if cfg!(feature = "icu") {
+            assert_eq!(parse_directive(r#"align_by sort desc locale:de "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { descending: true, locale: Some("de".to_string()), ..Sort::default() }));
+        } else {
+            assert_eq!(parse_directive(r#"align_by sort locale:de "=""#, DEFAULT_MARKER).unwrap_err().kind, InvalidAlignmentStatement::UnknownLocale);
+        }
+        assert_eq!(parse_directive(r#"align_by desc "=""#, DEFAULT_MARKER), Ok(None));
+        assert!(parse_directive(r#"align_by dedup "=""#, DEFAULT_MARKER).unwrap().unwrap().dedup);
+        assert_eq!(parse_directive(r#"align_by sort desc dedup "=""#, DEFAULT_MARKER).unwrap().unwrap().sort, Some(Sort { descending: true, ..Sort::default() }));
+        assert_eq!(parse_directive(r#"align_by dedup dedup "=""#, DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive("align_by dedup table", DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"align_by count 5 sort "=""#, DEFAULT_MARKER).unwrap().unwrap().count, Some(5));
+        assert_eq!(parse_directive("the align_by count of a file", DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"}; // align_by above 3 "=""#, DEFAULT_MARKER).unwrap().unwrap().above, Some(3));
+        assert_eq!(parse_directive("see the align_by above this line", DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"align_by reorder "3 1 2" ", ;""#, DEFAULT_MARKER).unwrap().unwrap().reorder, vec![3, 1, 2]);
+        assert_eq!(parse_directive(r#"align_by all reorder "3 1" ",""#, DEFAULT_MARKER).unwrap().unwrap().reorder, vec![3, 1]);
+        assert_eq!(parse_directive(r#"align_by skip "^//" "=""#, DEFAULT_MARKER).unwrap().unwrap().skip, Some(SkipPattern::new("^//")));
+        assert!(SkipPattern::new("^//").matches("    // comment"));
+        assert!(!SkipPattern::new("^//").matches("a = 1; // comment"));
+        assert!(SkipPattern::new("//").matches("a = 1; // comment"));
+        assert_eq!(parse_directive("align_by skip this", DEFAULT_MARKER), Ok(None));
+        assert!(parse_directive(r#"align_by keep_blank sort "=""#, DEFAULT_MARKER).unwrap().unwrap().keep_blank);
+        assert_eq!(parse_directive("align_by sort table", DEFAULT_MARKER), Ok(None));
+        assert!(parse_directive(r#"align_by groups "=""#, DEFAULT_MARKER).unwrap().unwrap().groups);
+        assert_eq!(parse_directive(r#"align_by group(fields) "=""#, DEFAULT_MARKER).unwrap().unwrap().group, Some("fields".to_string()));
+        assert_eq!(parse_directive(r#"align_by gap=0 "=""#, DEFAULT_MARKER).unwrap().unwrap().gap, Some(0));
+        assert_eq!(parse_directive(r#"align_by columns "10 40" "= //""#, DEFAULT_MARKER).unwrap().unwrap().columns, vec![10, 40]);
+        assert_eq!(parse_directive("align_by columns of a table", DEFAULT_MARKER), Ok(None));
+        assert!(parse_directive(r#"align_by all sort:5 "|""#, DEFAULT_MARKER).unwrap().unwrap().all);
+        assert_eq!(parse_directive(r#"align_by max=40 "=""#, DEFAULT_MARKER).unwrap().unwrap().max_width, Some(40));
+        assert_eq!(parse_directive(r#"align_by wrap=100 "=""#, DEFAULT_MARKER).unwrap().unwrap().wrap, Some(100));
+        assert_eq!(parse_directive(r#"align_by wrap=0 "=""#, DEFAULT_MARKER).unwrap_err().kind, InvalidAlignmentStatement::InvalidWrap);
+        assert!(parse_directive(r#"align_by preserve "=""#, DEFAULT_MARKER).unwrap().unwrap().raw);
+        assert_eq!(parse_directive(r#"write \align_by "=" above a block"#, DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive(r#"\align_by "=" // align_by "=""#, DEFAULT_MARKER).unwrap().unwrap().prefix, Some(Span::new(14, 16)));
+        assert!(is_stop("// align_by stop", DEFAULT_MARKER));
+        assert!(!is_stop(r"// \align_by stop", DEFAULT_MARKER));
+        assert_eq!(parse_directive(r#"align_by "=":left ";" :right "//""#, DEFAULT_MARKER).unwrap().unwrap().delimiters, vec![
+            delimiter("=", Alignment::Left), delimiter(";", Alignment::Right), delimiter("//", Alignment::Left),
+        ]);
+        assert_eq!(parse_directive(r#"align_by center "=" ";":left"#, DEFAULT_MARKER).unwrap().unwrap().delimiters, vec![
+            delimiter("=", Alignment::Center), delimiter(";", Alignment::Left),
+        ]);
+        assert_eq!(parse_directive(r#"align_by "= ;":decimal :left"#, DEFAULT_MARKER).unwrap().unwrap().delimiters, vec![
+            delimiter("=", Alignment::Decimal), delimiter(";", Alignment::Decimal),
+        ]);
+        assert_eq!(parse_directive("/// align_by table", DEFAULT_MARKER).unwrap().unwrap().mode(), "table");
+        assert_eq!(parse_directive("// align_by csv", DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive_with_modes("// align_by csv", DEFAULT_MARKER, &["csv"]).unwrap().unwrap().block_mode.as_deref(), Some("csv"));
+        assert_eq!(parse_directive_with_modes("// align_by dedup csv", DEFAULT_MARKER, &["csv"]), Ok(None));
+        assert_eq!(parse_directive("align_by table of contents", DEFAULT_MARKER), Ok(None));
+        assert!(is_cancel_block("// align_by cancel_block", DEFAULT_MARKER));
+        assert!(!is_cancel_block(r#"align_by cancel_block "=""#, DEFAULT_MARKER));
+        assert!(!is_cancel_block(r#"align_by "=""#, DEFAULT_MARKER));
+        assert_eq!(parse_directive(r#"align_by "=" then decimal ",""#, DEFAULT_MARKER).unwrap().unwrap().delimiters[1], delimiter(",", Alignment::Decimal));
+        assert_eq!(parse_directive(r#"// align_by "=""#, "@align"), Ok(None));
+        assert_eq!(parse_directive(r#"// @align sort "=""#, "@align"), Ok(Some(Directive {
+            prefix: Some(Span::new(0, 2)),
+            sort: Some(Sort::default()),
+            dedup: false,
+            count: None,
+            above: None,
+            skip: None,
+            keep_blank: false,
+            groups: false,
+            group: None,
+            gap: None,
+            columns: vec![],
+            reorder: vec![],
+            max_width: None,
+            wrap: None,
+            raw: false,
+            all: false,
+            trim: false,
+            block_mode: None,
+            cancel_block: false,
+            delimiters: vec![delimiter("=", Alignment::Left)],
+        })));
+    }
+
+    #[test]
+    fn comment_prefixes() {
+        assert_eq!(comment_prefix("  // "), Some(Span::new(2, 4)));
+        assert_eq!(comment_prefix("<!--"), Some(Span::new(0, 4)));
+        assert_eq!(comment_prefix(" * "), Some(Span::new(1, 2)));
+        assert_eq!(comment_prefix("let x = "), None);
+        assert_eq!(comment_prefix("a - "), None);
+        assert_eq!(comment_prefix("a * "), None);
+        assert_eq!(comment_prefix("x! // "), Some(Span::new(3, 5)));
+        assert_eq!(comment_prefix(";; "), Some(Span::new(0, 2)));
+        assert_eq!(comment_prefix("//*"), Some(Span::new(0, 3)));
+        assert_eq!(comment_prefix("  # | "), Some(Span::new(2, 5)));
+        assert_eq!(comment_prefix(" *| "), Some(Span::new(1, 3)));
+        assert_eq!(parse_directive(r#";; align_by "=" ;;"#, DEFAULT_MARKER).unwrap().unwrap().delimiters, vec![delimiter("=", Alignment::Left)]);
+        assert_eq!(parse_directive(r#"/* align_by "=" */"#, DEFAULT_MARKER).unwrap().unwrap().prefix, Some(Span::new(0, 2)));
+        assert_eq!(parse_directive(r#"/* align_by */"#, DEFAULT_MARKER), Ok(None));
+    }
+
+    #[test]
+    fn invalid_statements() {
+        use InvalidAlignmentStatement::*;
+        //                                    0         1         2         3
+        //                                    0123456789012345678901234567890123456
+        assert_eq!(error(r#"// align_by sort   "#),                 (UnexpectedEOF, 16, 16));
+        assert_eq!(error(r#"// align_by dedup"#),                   (UnexpectedEOF, 17, 17));
+        assert_eq!(error(r#"// align_by count"#),                   (UnexpectedEOF, 17, 17));
+        assert_eq!(error(r#"// align_by count "=""#),               (InvalidCount, 18, 21));
+        assert_eq!(error(r#"// align_by count 0 "=""#),             (InvalidCount, 18, 19));
+        assert_eq!(error(r#"// align_by above "=""#),               (InvalidAbove, 18, 21));
+        assert_eq!(error(r#"// align_by above 0 "=""#),             (InvalidAbove, 18, 19));
+        assert_eq!(error(r#"// align_by reorder "2 2" ",""#),       (InvalidReorder, 20, 25));
+        assert_eq!(error(r#"// align_by reorder "3 1" ",""#),       (InvalidReorder, 20, 25));
+        assert_eq!(error(r#"// align_by skip"#),                    (UnexpectedEOF, 16, 16));
+        assert_eq!(error(r#"// align_by skip "^""#),                (EmptySkipPattern, 17, 20));
+        assert_eq!(error(r#"// align_by skip "" "=""#),             (EmptySkipPattern, 17, 19));
+        assert_eq!(error(r#"// align_by group() "=""#),             (InvalidGroupName, 12, 19));
+        assert_eq!(error(r#"// align_by group(a "=""#),             (InvalidGroupName, 12, 19));
+        assert_eq!(error(r#"// align_by gap=-1 "=""#),              (InvalidGap, 12, 18));
+        assert_eq!(error(r#"// align_by columns "=" "=""#),         (InvalidColumns, 20, 23));
+        assert_eq!(error(r#"// align_by columns "" "=""#),          (InvalidColumns, 20, 22));
+        assert_eq!(error(r#"// align_by max=0 "=""#),               (InvalidMaxWidth, 12, 17));
+        assert_eq!(error(r#"// align_by"=""#),                      (MissingSpace, 11, 12));
+        assert_eq!(error(r#"// align_by "=" then left " ""#),       (EmptyDelimiters, 26, 29));
+        assert_eq!(error(r#"// align_by " ""#),                     (EmptyDelimiters, 12, 15));
+        assert_eq!(error(r#"// align_by  "= ;"#),                   (UnterminatedQuote, 13, 17));
+        assert_eq!(error(r#"// align_by "=" then sideways "//""#),  (UnknownSide, 21, 29));
+        assert_eq!(error(r#"// align_by "=" then right ="#),        (ExpectedQuote, 27, 28));
+        assert_eq!(error(r#"// align_by "=" then right"#),          (UnexpectedEOF, 26, 26));
+        assert_eq!(error(r#"// align_by "=" then"#),                (UnexpectedEOF, 20, 20));
+        assert_eq!(error(r#"/* align_by sort */"#),                 (UnexpectedEOF, 16, 16));
+        assert_eq!(error(r#"<!-- align_by "=" then -->"#),          (UnexpectedEOF, 22, 22));
+        assert_eq!(error(r#"//* align_by "=" then right      *"#), (UnexpectedEOF, 27, 27));
+        assert_eq!(error(r#"# | align_by "=" then sideways |"#),    (UnknownSide, 22, 30));
+        assert_eq!(error(r#"// align_by "=":up"#),                  (UnknownSide, 15, 18));
+        assert_eq!(error(r#"// align_by "=" """#),                  (EmptyDelimiters, 16, 18));
+        assert_eq!(error(r#"// align_by sort:3 "=""#),              (InvalidSortColumn, 12, 18));
+        assert_eq!(error(r#"// align_by sort:4 "= ;""#),            (InvalidSortColumn, 12, 18));
+        assert_eq!(error(r#"// align_by sort:0 "=""#),              (InvalidSortColumn, 12, 18));
+        assert_eq!(error(r#"// align_by sort:_ "=""#),              (InvalidSortColumn, 12, 18));
+        assert_eq!(error(r#"// align_by sort:1,3 "=""#),            (InvalidSortColumn, 12, 20));
+        assert_eq!(error(r#"// align_by sort:1, "=""#),             (InvalidSortColumn, 12, 19));
+        assert_eq!(Span::new(4, 5).column("é = é"), 4);
+    }
+
+    #[test]
+    fn strict_statements() {
+        let strict_error = |line| {
+            let err = parse_directive_strict(line, DEFAULT_MARKER).unwrap_err();
+            (err.kind, err.span.start, err.span.end)
+        };
+        use InvalidAlignmentStatement::*;
+        assert_eq!(strict_error(r#"// align_by sotr "=""#),                (UnknownKeyword, 12, 16));
+        assert_eq!(strict_error(r#"// align_by sort desc typo "=""#),      (UnknownKeyword, 22, 26));
+        assert_eq!(strict_error("// align_by table of contents"),         (UnknownKeyword, 18, 20));
+        assert_eq!(strict_error("// align_by count lines"),               (InvalidCount, 18, 23));
+        assert_eq!(strict_error("// align_by skip comments"),             (ExpectedQuote, 17, 25));
+        assert!(parse_directive_strict(r#"// align_by sort "=""#, DEFAULT_MARKER).unwrap().is_some());
+        assert_eq!(parse_directive_strict(r"// \align_by sotr", DEFAULT_MARKER), Ok(None));
+        assert_eq!(parse_directive_strict("let align_by_name = 1;", DEFAULT_MARKER), Ok(None));
+
+        assert_eq!(suggest_keyword("sotr"), Some("sort"));
+        assert_eq!(suggest_keyword("dedupe"), Some("dedup"));
+        assert_eq!(suggest_keyword("gap"), Some("gap="));
+        assert_eq!(suggest_keyword("x"), None);
+        assert_eq!(suggest_keyword("whatever"), None);
+    }
+
+    #[test]
+    fn codes() {
+        for kind in InvalidAlignmentStatement::ALL {
+            assert_eq!(InvalidAlignmentStatement::from_code(kind.code()), Some(kind));
+            assert!(kind.explanation().contains("Erroneous example"));
+        }
+        assert_eq!(InvalidAlignmentStatement::from_code("a0002"), Some(InvalidAlignmentStatement::MissingSpace));
+        assert_eq!(InvalidAlignmentStatement::from_code("A9999"), None);
+    }
+}
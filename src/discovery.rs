@@ -0,0 +1,473 @@
+//! Selection of the files to align.
+//!
+//! Every entry point that walks the file system goes through [`Discovery`] so
+//! they all agree on which files are considered.
+
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Files over this size are skipped by default.
+pub const DEFAULT_FILESIZE_LIMIT: u64 = 1 << 20;
+
+type Filter = Box<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// Builder describing which files to align.
+pub struct Discovery {
+    roots: Vec<PathBuf>,
+    ignore_file_names: Vec<String>,
+    /// Whether ignore files are read at all.
+    read_ignore_files: bool,
+    filesize_limit: Option<u64>,
+    follow_links: bool,
+    max_depth: Option<usize>,
+    filters: Vec<Filter>,
+    /// The ignored names [`Discovery::includes`] read by directory, so asking
+    /// about many files, like a daemon does, reads each ignore file once.
+    ignored_names_by_dir: Mutex<HashMap<PathBuf, Vec<String>>>,
+}
+
+/// The result of walking the roots of a [`Discovery`].
+#[derive(Debug, Default)]
+pub struct FileSet {
+    /// Files to align, in walk order.
+    pub files: Vec<PathBuf>,
+    /// Problems encountered while walking, none of them stop the walk.
+    pub warnings: Vec<String>,
+}
+
+impl Discovery {
+    /// Starts a discovery rooted at `root`, respecting `.gitignore` files and
+    /// skipping files over [`DEFAULT_FILESIZE_LIMIT`]. Hidden files and
+    /// directories like `.github` are walked, only `.git` is always skipped.
+    /// Symlinks inside the roots are walked into.
+    pub fn new(root: impl Into<PathBuf>) -> Discovery {
+        Discovery {
+            roots: vec![root.into()],
+            ignore_file_names: vec![".gitignore".to_string()],
+            read_ignore_files: true,
+            filesize_limit: Some(DEFAULT_FILESIZE_LIMIT),
+            follow_links: true,
+            max_depth: None,
+            filters: Vec::new(),
+            ignored_names_by_dir: Mutex::default(),
+        }
+    }
+
+    pub fn add_root(&mut self, root: impl Into<PathBuf>) -> &mut Discovery {
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Also reads ignore rules from files named `name`, in addition to `.gitignore`.
+    pub fn add_ignore_file_name(&mut self, name: impl Into<String>) -> &mut Discovery {
+        self.ignore_file_names.push(name.into());
+        self
+    }
+
+    /// Whether `.gitignore` files are read, other ignore files are unaffected.
+    pub fn git_ignore(&mut self, enabled: bool) -> &mut Discovery {
+        self.ignore_file_names.retain(|name| name != ".gitignore");
+        if enabled {
+            self.ignore_file_names.push(".gitignore".to_string());
+        }
+        self
+    }
+
+    /// Whether any ignore files are read, disabling it walks every file.
+    pub fn ignore_files(&mut self, enabled: bool) -> &mut Discovery {
+        self.read_ignore_files = enabled;
+        self
+    }
+
+    /// Skips files larger than `limit` bytes, `None` disables the limit.
+    pub fn filesize_limit(&mut self, limit: Option<u64>) -> &mut Discovery {
+        self.filesize_limit = limit;
+        self
+    }
+
+    /// Whether symlinks inside the roots are walked into. Directories that link
+    /// back to one of their parents are skipped with a warning.
+    pub fn follow_links(&mut self, enabled: bool) -> &mut Discovery {
+        self.follow_links = enabled;
+        self
+    }
+
+    /// Only walks `depth` levels below the roots, `Some(1)` only keeps the
+    /// files directly inside them. `None` walks every level.
+    pub fn max_depth(&mut self, depth: Option<usize>) -> &mut Discovery {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Only keeps files for which `filter` returns true.
+    pub fn filter(
+        &mut self,
+        filter: impl Fn(&Path) -> bool + Send + Sync + 'static,
+    ) -> &mut Discovery {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn build(&self) -> FileSet {
+        let mut file_set = FileSet::default();
+        for root in &self.roots {
+            self.walk(root.clone(), 0, &mut Vec::new(), &mut file_set);
+        }
+        file_set
+    }
+
+    /// Walks `path`, `depth` levels below its root. `ancestors` holds the
+    /// canonical paths of the directories currently being walked.
+    fn walk(
+        &self,
+        path: PathBuf,
+        depth: usize,
+        ancestors: &mut Vec<PathBuf>,
+        file_set: &mut FileSet,
+    ) {
+        if self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return;
+        }
+        // Roots are always followed, like the files passed to a command.
+        if depth > 0
+            && !self.follow_links
+            && std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_symlink())
+        {
+            return;
+        }
+
+        let path_metadata = match std::fs::metadata(&path)
+            .with_context(|| format!("Failed to get metadata of path {}", path.display()))
+        {
+            Err(err) => {
+                file_set.warnings.push(err.to_string());
+                return;
+            }
+            Ok(meta) => meta,
+        };
+
+        if path_metadata.is_file() {
+            if !self.filters.iter().all(|filter| filter(&path)) {
+                return;
+            }
+            match self.filesize_limit {
+                Some(limit) if path_metadata.len() > limit => {
+                    file_set.warnings.push(format!(
+                        "Skipping file {} because it is over the {limit} byte size limit.",
+                        path.display(),
+                    ));
+                }
+                _ => file_set.files.push(path),
+            }
+            return;
+        }
+
+        if path.file_name() == Some(std::ffi::OsStr::new(".git")) {
+            return;
+        }
+
+        let canonical_path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if ancestors.contains(&canonical_path) {
+            file_set.warnings.push(format!(
+                "Skipping directory {} because it links back to {}.",
+                path.display(),
+                canonical_path.display(),
+            ));
+            return;
+        }
+
+        let dir_contents = match std::fs::read_dir(&path)
+            .with_context(|| format!("Failed to read contents of path {}", path.display()))
+        {
+            Err(err) => {
+                file_set.warnings.push(err.to_string());
+                return;
+            }
+            Ok(read_dir) => read_dir,
+        }
+        .filter_map(|x| {
+            match x.with_context(|| {
+                format!(
+                    "Failure occured on reading one item from the contents of path {}",
+                    path.display()
+                )
+            }) {
+                Err(err) => {
+                    file_set.warnings.push(err.to_string());
+                    None
+                }
+                Ok(dir_entry) => Some(dir_entry),
+            }
+        })
+        .collect::<Vec<_>>();
+
+        let ignored_names = self.ignored_names(&path, &mut file_set.warnings);
+        let mut dir_contents = dir_contents
+            .into_iter()
+            .filter(|d| {
+                ignored_names
+                    .iter()
+                    .all(|name| d.file_name() != name.as_str())
+            })
+            .collect::<Vec<_>>();
+        // `read_dir` order is platform dependent.
+        dir_contents.sort_by_key(|d| d.file_name());
+
+        ancestors.push(canonical_path);
+        for dir_entry in dir_contents {
+            self.walk(dir_entry.path(), depth + 1, ancestors, file_set);
+        }
+        ancestors.pop();
+    }
+
+    /// The names of the entries of `dir` that its ignore files exclude.
+    fn ignored_names(&self, dir: &Path, warnings: &mut Vec<String>) -> Vec<String> {
+        let mut ignored_names = Vec::new();
+        if !self.read_ignore_files {
+            return ignored_names;
+        }
+        for name in &self.ignore_file_names {
+            let ignore_file = dir.join(name);
+            if !ignore_file.exists() {
+                continue;
+            }
+            match std::fs::read_to_string(&ignore_file).with_context(|| {
+                format!(
+                    "Failed to read content of ignore file at path {}",
+                    ignore_file.display()
+                )
+            }) {
+                Err(err) => warnings.push(err.to_string()),
+                Ok(ignore_content) => ignored_names.extend(parse_ignored_names(&ignore_content)),
+            }
+        }
+        ignored_names
+    }
+
+    /// Whether walking the roots would select the file at `path`, without
+    /// walking them. The file doesn't have to exist, which suits editor
+    /// buffers that were never saved. Ignore files are read the first time
+    /// they are needed, later changes to them aren't seen.
+    pub fn includes(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| {
+            let Ok(relative_path) = path.strip_prefix(root) else {
+                return false;
+            };
+            let names = relative_path.iter().collect::<Vec<_>>();
+            if self
+                .max_depth
+                .is_some_and(|max_depth| names.len() > max_depth)
+            {
+                return false;
+            }
+            let mut dir = root.clone();
+            for name in names {
+                let mut ignored_names_by_dir = self.ignored_names_by_dir.lock().unwrap();
+                let ignored_names = ignored_names_by_dir
+                    .entry(dir.clone())
+                    .or_insert_with(|| self.ignored_names(&dir, &mut Vec::new()));
+                if name == ".git" || ignored_names.iter().any(|ignored| name == ignored.as_str()) {
+                    return false;
+                }
+                dir.push(name);
+                if !self.follow_links
+                    && std::fs::symlink_metadata(&dir)
+                        .is_ok_and(|meta| meta.file_type().is_symlink())
+                {
+                    return false;
+                }
+            }
+            self.filters.iter().all(|filter| filter(path))
+        })
+    }
+}
+
+/// Only rules anchored to the ignore file's directory that name a direct
+/// child, like `/target`, are supported.
+fn parse_ignored_names(ignore_content: &str) -> Vec<String> {
+    ignore_content
+        .lines()
+        .filter_map(|line| {
+            if line.len() > 1 && line.starts_with('/') && !line[1..].contains('/') {
+                Some(line[1..].to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fresh directory under the system temp directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-align-discovery-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn relative_files(root: &Path, file_set: &FileSet) -> Vec<String> {
+        file_set
+            .files
+            .iter()
+            .map(|f| {
+                f.strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ignore_rules() {
+        assert_eq!(
+            parse_ignored_names("/target\ntarget/\n/a/b\n/\n*.rs\n/Cargo.lock"),
+            vec!["target", "Cargo.lock"]
+        );
+    }
+
+    #[test]
+    fn walking() {
+        let root = temp_dir("walking");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::create_dir_all(root.join("vendor")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".gitignore"), "/target\n").unwrap();
+        std::fs::write(root.join(".alignignore"), "/vendor\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+        std::fs::write(root.join("src/big.rs"), "0123456789").unwrap();
+        std::fs::write(root.join("target/out.rs"), "").unwrap();
+        std::fs::write(root.join("vendor/lib.rs"), "").unwrap();
+        std::fs::write(root.join(".git/HEAD"), "").unwrap();
+        std::fs::create_dir_all(root.join(".github")).unwrap();
+        std::fs::write(root.join(".github/ci.yml"), "").unwrap();
+
+        let file_set = Discovery::new(&root).build();
+        assert_eq!(
+            relative_files(&root, &file_set),
+            vec![
+                ".alignignore",
+                ".github/ci.yml",
+                ".gitignore",
+                "src/big.rs",
+                "src/main.rs",
+                "vendor/lib.rs"
+            ]
+        );
+
+        let file_set = Discovery::new(&root)
+            .add_ignore_file_name(".alignignore")
+            .filesize_limit(Some(5))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+            .build();
+        assert_eq!(relative_files(&root, &file_set), vec!["src/main.rs"]);
+        assert_eq!(file_set.warnings.len(), 1);
+
+        let file_set = Discovery::new(&root)
+            .add_ignore_file_name(".alignignore")
+            .git_ignore(false)
+            .build();
+        assert_eq!(
+            relative_files(&root, &file_set),
+            vec![
+                ".alignignore",
+                ".github/ci.yml",
+                ".gitignore",
+                "src/big.rs",
+                "src/main.rs",
+                "target/out.rs"
+            ]
+        );
+
+        let file_set = Discovery::new(&root)
+            .add_ignore_file_name(".alignignore")
+            .ignore_files(false)
+            .build();
+        assert_eq!(file_set.files.len(), 7);
+
+        let file_set = Discovery::new(&root).max_depth(Some(1)).build();
+        assert_eq!(
+            relative_files(&root, &file_set),
+            vec![".alignignore", ".gitignore"]
+        );
+        let file_set = Discovery::new(root.join("src/main.rs"))
+            .max_depth(Some(0))
+            .build();
+        assert_eq!(file_set.files, vec![root.join("src/main.rs")]);
+
+        let file_set = Discovery::new(root.join("src"))
+            .add_root(root.join("vendor"))
+            .build();
+        assert_eq!(
+            relative_files(&root, &file_set),
+            vec!["src/big.rs", "src/main.rs", "vendor/lib.rs"]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn including() {
+        let root = temp_dir("including");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join(".gitignore"), "/target\n").unwrap();
+
+        let discovery = Discovery::new(&root);
+        assert!(discovery.includes(&root.join("src/main.rs")));
+        assert!(discovery.includes(&root.join("new/unsaved.rs")));
+        assert!(!discovery.includes(&root.join("target/out.rs")));
+        assert!(!discovery.includes(&root.join(".git/HEAD")));
+        assert!(!discovery.includes(Path::new("/elsewhere/main.rs")));
+        assert!(!Discovery::new(&root)
+            .max_depth(Some(1))
+            .includes(&root.join("src/main.rs")));
+        assert!(!Discovery::new(&root)
+            .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+            .includes(&root.join("src/main.rs")));
+        assert!(Discovery::new(&root)
+            .git_ignore(false)
+            .includes(&root.join("target/out.rs")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        let root = temp_dir("symlinks");
+        let outside = temp_dir("symlinks-outside");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+        std::fs::write(outside.join("lib.rs"), "").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("vendor")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("src/loop")).unwrap();
+
+        let file_set = Discovery::new(&root).follow_links(false).build();
+        assert_eq!(relative_files(&root, &file_set), vec!["src/main.rs"]);
+        assert!(file_set.warnings.is_empty());
+
+        let file_set = Discovery::new(&root).build();
+        assert_eq!(
+            relative_files(&root, &file_set),
+            vec!["src/main.rs", "vendor/lib.rs"]
+        );
+        assert_eq!(file_set.warnings.len(), 1);
+
+        let file_set = Discovery::new(root.join("vendor")).build();
+        assert_eq!(file_set.files, vec![root.join("vendor/lib.rs")]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+//! The line ending and indentation properties of `.editorconfig` files.
+//!
+//! Aligning only generates a line ending when the last line of a file has
+//! none, which `end_of_line` and `insert_final_newline` decide. `tab_width`,
+//! or `indent_size` without it, is how wide tabs are measured, and
+//! `indent_style` whether the hanging indents of `wrap=N` are written with
+//! tabs. The padding between cells is always spaces, since tabs there
+//! wouldn't line up.
+
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Properties {
+    /// The line ending to use, `\n` or `\r\n`.
+    pub end_of_line: Option<&'static str>,
+    pub insert_final_newline: Option<bool>,
+    /// Whether indentation is written with tabs, from `indent_style`.
+    pub indent_with_tabs: Option<bool>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+}
+
+impl Properties {
+    /// The properties for the file at `path`, from the `.editorconfig` files
+    /// in its directory and the ones above it up to one with `root = true`.
+    pub fn for_file(path: &Path) -> Properties {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut configs = Vec::new();
+        for directory in absolute.ancestors().skip(1) {
+            let Ok(content) = std::fs::read_to_string(directory.join(".editorconfig")) else {
+                continue;
+            };
+            let is_root = parse(&content).0;
+            configs.push((directory, content));
+            if is_root {
+                break;
+            }
+        }
+        let mut properties = Properties::default();
+        // Closer files take precedence, so they are applied last.
+        for (directory, content) in configs.iter().rev() {
+            let Ok(relative_path) = absolute.strip_prefix(directory) else {
+                continue;
+            };
+            let relative_path = relative_path.to_string_lossy().replace('\\', "/");
+            properties.apply(content, &relative_path);
+        }
+        properties
+    }
+
+    /// Applies the sections of the `.editorconfig` `content` that match
+    /// `relative_path`, relative to the directory of the file.
+    fn apply(&mut self, content: &str, relative_path: &str) {
+        for (pattern, pairs) in parse(content).1 {
+            if !section_matches(&pattern, relative_path) {
+                continue;
+            }
+            for (key, value) in pairs {
+                match (key.as_str(), value.as_str()) {
+                    ("end_of_line", "lf") => self.end_of_line = Some("\n"),
+                    ("end_of_line", "crlf") => self.end_of_line = Some("\r\n"),
+                    ("end_of_line", _) => self.end_of_line = None,
+                    ("insert_final_newline", "true") => self.insert_final_newline = Some(true),
+                    ("insert_final_newline", "false") => self.insert_final_newline = Some(false),
+                    ("insert_final_newline", _) => self.insert_final_newline = None,
+                    ("indent_style", "tab") => self.indent_with_tabs = Some(true),
+                    ("indent_style", "space") => self.indent_with_tabs = Some(false),
+                    ("indent_style", _) => self.indent_with_tabs = None,
+                    // `indent_size = tab` means the tab width, which is the fallback anyway.
+                    ("indent_size", size) => self.indent_size = size_or_none(size),
+                    ("tab_width", width) => self.tab_width = size_or_none(width),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// How wide a tab is, `indent_size` stands in for a missing `tab_width`.
+    pub fn tab_width(&self) -> Option<usize> {
+        self.tab_width.or(self.indent_size)
+    }
+
+    /// Changes the line ending aligning gave `aligned` if `original` had none
+    /// after its last line.
+    pub fn apply_to(&self, original: &str, aligned: String) -> String {
+        if original[original.trim_end().len()..].contains('\n') {
+            return aligned;
+        }
+        let Some(content) = aligned
+            .strip_suffix("\r\n")
+            .or_else(|| aligned.strip_suffix('\n'))
+        else {
+            return aligned;
+        };
+        let generated = &aligned[content.len()..];
+        match (self.insert_final_newline, self.end_of_line) {
+            (Some(false), _) => content.to_string(),
+            (_, Some(end_of_line)) if generated != end_of_line => [content, end_of_line].concat(),
+            _ => aligned,
+        }
+    }
+}
+
+/// A size property's value, where `0` and `unset` mean it isn't set.
+fn size_or_none(value: &str) -> Option<usize> {
+    value.parse().ok().filter(|&size| size > 0)
+}
+
+type Section = (String, Vec<(String, String)>);
+
+/// Whether the file has `root = true` in its preamble, and its sections.
+fn parse(content: &str) -> (bool, Vec<Section>) {
+    let mut is_root = false;
+    let mut sections: Vec<Section> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(pattern) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((pattern.to_string(), Vec::new()));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        match sections.last_mut() {
+            None if key == "root" => is_root = value == "true",
+            None => {}
+            Some((_, pairs)) => pairs.push((key, value)),
+        }
+    }
+    (is_root, sections)
+}
+
+/// Whether the section `pattern` matches `relative_path`. Patterns without a
+/// `/` match file names in any directory, `{a,b}` matches either `a` or `b`.
+fn section_matches(pattern: &str, relative_path: &str) -> bool {
+    expand_braces(pattern).iter().any(|pattern| {
+        if pattern.contains('/') {
+            let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+            crate::glob::matches(pattern, relative_path)
+        } else {
+            crate::glob::matches(&format!("**/{pattern}"), relative_path)
+        }
+    })
+}
+
+/// The patterns `pattern` stands for with every `{a,b}` alternation expanded.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let Some(close) = pattern[open..].find('}').map(|close| open + close) else {
+        return vec![pattern.to_string()];
+    };
+    let (before, after) = (&pattern[..open], &pattern[close + 1..]);
+    pattern[open + 1..close]
+        .split(',')
+        .flat_map(|alternative| expand_braces(&[before, alternative, after].concat()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_sections() {
+        assert!(section_matches("*", "src/a.rs"));
+        assert!(section_matches("*.rs", "src/a.rs"));
+        assert!(section_matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(!section_matches("*.{rs,toml}", "README.md"));
+        assert!(section_matches("/src/*.rs", "src/a.rs"));
+        assert!(!section_matches("/src/*.rs", "tests/src/a.rs"));
+        assert!(section_matches("src/**/*.rs", "src/a/b.rs"));
+    }
+
+    #[test]
+    fn applying_properties() {
+        let mut properties = Properties::default();
+        properties.apply(
+            "root = true\n\n[*]\nend_of_line = lf\n\n[*.bat]\nend_of_line = CRLF\ninsert_final_newline = false\n",
+            "scripts/build.bat",
+        );
+        assert_eq!(
+            properties,
+            Properties {
+                end_of_line: Some("\r\n"),
+                insert_final_newline: Some(false),
+                ..Properties::default()
+            }
+        );
+        assert!(parse("root = true\n[*]\n").0);
+
+        assert_eq!(properties.apply_to("a", "a\n".to_string()), "a");
+        assert_eq!(properties.apply_to("a\n", "a\n".to_string()), "a\n");
+        let crlf = Properties {
+            end_of_line: Some("\r\n"),
+            ..Properties::default()
+        };
+        assert_eq!(crlf.apply_to("a\nb", "a\nb\n".to_string()), "a\nb\r\n");
+        assert_eq!(
+            Properties::default().apply_to("a", "a\n".to_string()),
+            "a\n"
+        );
+        assert_eq!(crlf.apply_to("", String::new()), "");
+
+        let mut properties = Properties::default();
+        properties.apply("[*]\nindent_style = tab\nindent_size = 4\n", "a.rs");
+        assert_eq!(properties.indent_with_tabs, Some(true));
+        assert_eq!(properties.tab_width(), Some(4));
+        properties.apply("[*.rs]\nindent_size = tab\ntab_width = 8\n", "a.rs");
+        assert_eq!(properties.tab_width(), Some(8));
+        properties.apply("[*]\nindent_style = space\ntab_width = unset\n", "a.rs");
+        assert_eq!(properties.indent_with_tabs, Some(false));
+        assert_eq!(properties.tab_width(), None);
+    }
+
+    #[test]
+    fn finding_files() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-align-editorconfig-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("a/b")).unwrap();
+        std::fs::write(
+            dir.join(".editorconfig"),
+            "root = true\n[*]\nend_of_line = crlf\ninsert_final_newline = true\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("a/.editorconfig"), "[*.rs]\nend_of_line = lf\n").unwrap();
+        assert_eq!(
+            Properties::for_file(&dir.join("a/b/c.rs")),
+            Properties {
+                end_of_line: Some("\n"),
+                insert_final_newline: Some(true),
+                ..Properties::default()
+            }
+        );
+        assert_eq!(
+            Properties::for_file(&dir.join("a/c.md")).end_of_line,
+            Some("\r\n")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::Result;
+
+/// Text encodings files can be read and written back in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Encoding> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => anyhow::bail!(
+                "Unknown encoding `{s}`, expected one of `utf-8`, `utf-16le`, `utf-16be`, `latin1`"
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        })
+    }
+}
+
+impl Encoding {
+    /// Guesses the encoding of `bytes`, preferring a byte order mark, then
+    /// utf-16 if most of every other byte is zero, then utf-8, falling back to
+    /// latin1. Returns `None` for data that looks binary, which latin1 would
+    /// otherwise happily decode.
+    pub fn detect(bytes: &[u8]) -> Option<Encoding> {
+        if bytes.starts_with(&[0xFF, 0xFE]) {
+            return Some(Encoding::Utf16Le);
+        }
+        if bytes.starts_with(&[0xFE, 0xFF]) {
+            return Some(Encoding::Utf16Be);
+        }
+        if bytes.len().is_multiple_of(2) {
+            let zeros_at = |offset: usize| {
+                bytes
+                    .iter()
+                    .skip(offset)
+                    .step_by(2)
+                    .filter(|b| **b == 0)
+                    .count()
+            };
+            let half = bytes.len() / 2;
+            if zeros_at(1) * 2 > half && zeros_at(0) * 2 <= half {
+                return Some(Encoding::Utf16Le);
+            }
+            if zeros_at(0) * 2 > half && zeros_at(1) * 2 <= half {
+                return Some(Encoding::Utf16Be);
+            }
+        }
+        if bytes.contains(&0) {
+            return None;
+        }
+        if std::str::from_utf8(bytes).is_ok() {
+            return Some(Encoding::Utf8);
+        }
+        // Control characters other than whitespace don't show up in latin1 text.
+        let is_text = bytes
+            .iter()
+            .all(|b| *b >= 0x20 || matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B));
+        is_text.then_some(Encoding::Latin1)
+    }
+
+    /// Whether `bytes` look like binary data instead of text in `encoding`, or
+    /// in the detected encoding if `None`. Like ripgrep, a zero byte means
+    /// binary, except in utf-16 where text is full of them.
+    pub fn is_binary(bytes: &[u8], encoding: Option<Encoding>) -> bool {
+        match encoding.or_else(|| Encoding::detect(bytes)) {
+            None => true,
+            Some(Encoding::Utf16Le | Encoding::Utf16Be) => false,
+            Some(_) => bytes.contains(&0),
+        }
+    }
+
+    /// Decodes `bytes`, keeping any byte order mark as a leading `\u{FEFF}`
+    /// so [`Encoding::encode`] writes it back.
+    pub fn decode(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            Encoding::Utf8 => Ok(std::str::from_utf8(bytes)?.to_string()),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    anyhow::bail!("Odd number of bytes in {self} data");
+                }
+                let units = bytes
+                    .chunks_exact(2)
+                    .map(|pair| match self {
+                        Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                        _ => u16::from_be_bytes([pair[0], pair[1]]),
+                    })
+                    .collect::<Vec<_>>();
+                Ok(String::from_utf16(&units)?)
+            }
+            Encoding::Latin1 => Ok(bytes.iter().map(|b| char::from(*b)).collect()),
+        }
+    }
+
+    pub fn encode(self, s: &str) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Utf8 => Ok(s.as_bytes().to_vec()),
+            Encoding::Utf16Le => Ok(s.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Encoding::Utf16Be => Ok(s.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Encoding::Latin1 => s
+                .chars()
+                .map(|c| {
+                    u8::try_from(c).map_err(|_| anyhow::anyhow!("`{c}` can't be encoded as latin1"))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detection() {
+        assert_eq!(Encoding::detect(b"a = 1"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::detect(b"caf\xe9"), Some(Encoding::Latin1));
+        assert_eq!(Encoding::detect(b"\xff\xfea\0"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::detect(b"\xfe\xff\0a"), Some(Encoding::Utf16Be));
+        assert_eq!(Encoding::detect(b"a\0=\0"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::detect(b"a\0=\0\xe9\0"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::detect(b"\0a\0=\0\xe9"), Some(Encoding::Utf16Be));
+        assert_eq!(Encoding::detect(b"\0\0\0\0"), None);
+        assert_eq!(Encoding::detect(b"ELF\0\x01\x02"), None);
+        assert_eq!(Encoding::detect(b"\x89PNG\x1a\x05"), None);
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!Encoding::is_binary(b"a = 1", None));
+        assert!(!Encoding::is_binary(b"a\0=\0", None));
+        assert!(Encoding::is_binary(b"ELF\0\x01\x02", None));
+        assert!(Encoding::is_binary(b"a = 1\0", Some(Encoding::Utf8)));
+        assert!(Encoding::is_binary(b"caf\xe9\0", Some(Encoding::Latin1)));
+        assert!(!Encoding::is_binary(b"\0a\0=", Some(Encoding::Utf16Be)));
+    }
+
+    #[test]
+    fn round_trip() {
+        for (bytes, encoding) in [
+            (&b"caf\xe9 = 1"[..], Encoding::Latin1),
+            (&b"\xff\xfea\0=\0\xe9\0"[..], Encoding::Utf16Le),
+            (&b"\xfe\xff\0a\0=\0\xe9"[..], Encoding::Utf16Be),
+            (&b"caf\xc3\xa9"[..], Encoding::Utf8),
+        ] {
+            let decoded = encoding.decode(bytes).unwrap();
+            assert_eq!(encoding.encode(&decoded).unwrap(), bytes);
+        }
+        assert_eq!(Encoding::Latin1.decode(b"caf\xe9").unwrap(), "café");
+        assert!(Encoding::Latin1.encode("€").is_err());
+        assert!(Encoding::Utf8.decode(b"\xe9").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! A C interface to the alignment engine, so editors and language servers can
+//! embed it instead of running `cargo align`. Built into the `cdylib` with
+//! `cargo build --release --no-default-features --features ffi`, declared in
+//! `include/cargo_align.h`.
+
+use crate::align::AlignOptions;
+use crate::align::AlignmentError;
+use crate::directive;
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::CStr;
+use std::ffi::CString;
+
+/// The text was aligned.
+pub const CARGO_ALIGN_OK: c_int = 0;
+/// The text has an invalid alignment statement, described by the error.
+pub const CARGO_ALIGN_INVALID: c_int = 1;
+/// A pointer was null or the text wasn't valid UTF-8.
+pub const CARGO_ALIGN_BAD_ARGUMENT: c_int = 2;
+
+/// An invalid alignment statement.
+#[repr(C)]
+pub struct CargoAlignError {
+    /// 1 based line number.
+    pub line: usize,
+    /// 1 based column, in characters.
+    pub column: usize,
+    /// The code `cargo align --explain` takes, like `A0001`, NUL terminated.
+    pub code: [c_char; 6],
+    /// What is wrong, freed with [`cargo_align_free_string`].
+    pub message: *mut c_char,
+}
+
+/// Aligns `input` with the `align_by` marker, see
+/// [`cargo_align_format_with_marker`].
+///
+/// # Safety
+///
+/// Same as [`cargo_align_format_with_marker`].
+#[no_mangle]
+pub unsafe extern "C" fn cargo_align_format(
+    input: *const c_char,
+    output: *mut *mut c_char,
+    err: *mut CargoAlignError,
+) -> c_int {
+    cargo_align_format_with_marker(input, std::ptr::null(), output, err)
+}
+
+/// Aligns the NUL terminated UTF-8 `input`, with `marker` starting the
+/// alignment statements, or `align_by` if it is null.
+///
+/// Returns [`CARGO_ALIGN_OK`] and stores the aligned text in `output`, to be
+/// freed with [`cargo_align_free_string`]. Returns [`CARGO_ALIGN_INVALID`] and
+/// fills `err` if `input` has an invalid alignment statement, and
+/// [`CARGO_ALIGN_BAD_ARGUMENT`] without touching either otherwise.
+///
+/// # Safety
+///
+/// `input` and `marker`, unless it is null, must point to NUL terminated
+/// strings. `output` and `err` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cargo_align_format_with_marker(
+    input: *const c_char,
+    marker: *const c_char,
+    output: *mut *mut c_char,
+    err: *mut CargoAlignError,
+) -> c_int {
+    if input.is_null() || output.is_null() || err.is_null() {
+        return CARGO_ALIGN_BAD_ARGUMENT;
+    }
+    let Ok(input) = CStr::from_ptr(input).to_str() else {
+        return CARGO_ALIGN_BAD_ARGUMENT;
+    };
+    let marker = if marker.is_null() {
+        directive::DEFAULT_MARKER
+    } else {
+        match CStr::from_ptr(marker).to_str() {
+            Ok(marker) => marker,
+            Err(_) => return CARGO_ALIGN_BAD_ARGUMENT,
+        }
+    };
+    match AlignOptions::new().marker(marker).align_string(input) {
+        Ok(aligned) => {
+            // The input has no NUL, so neither does the aligned text.
+            let Ok(aligned) = CString::new(aligned) else {
+                return CARGO_ALIGN_BAD_ARGUMENT;
+            };
+            *output = aligned.into_raw();
+            CARGO_ALIGN_OK
+        }
+        Err(alignment_error) => {
+            *err = c_error(&alignment_error);
+            CARGO_ALIGN_INVALID
+        }
+    }
+}
+
+fn c_error(err: &AlignmentError) -> CargoAlignError {
+    let mut code = [0; 6];
+    for (c, byte) in code.iter_mut().zip(err.code().bytes()) {
+        *c = byte as c_char;
+    }
+    CargoAlignError {
+        line: err.line(),
+        column: err.column(),
+        code,
+        message: CString::new(err.message()).unwrap_or_default().into_raw(),
+    }
+}
+
+/// Frees a string returned by this library. Does nothing if `s` is null.
+///
+/// # Safety
+///
+/// `s` must be null or a string from this library that wasn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cargo_align_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(input: &str) -> (c_int, String, CargoAlignError) {
+        let input = CString::new(input).unwrap();
+        let mut output = std::ptr::null_mut();
+        let mut err = CargoAlignError {
+            line: 0,
+            column: 0,
+            code: [0; 6],
+            message: std::ptr::null_mut(),
+        };
+        let status = unsafe { cargo_align_format(input.as_ptr(), &mut output, &mut err) };
+        let mut aligned = String::new();
+        if !output.is_null() {
+            aligned = unsafe { CStr::from_ptr(output) }
+                .to_str()
+                .unwrap()
+                .to_string();
+            unsafe { cargo_align_free_string(output) };
+        }
+        (status, aligned, err)
+    }
+
+    #[test]
+    fn formatting() {
+        let (status, aligned, _) = format("// align_by \"=\"\na = 1\nbbb = 2\n");
+        assert_eq!(status, CARGO_ALIGN_OK);
+        assert_eq!(aligned, "// align_by \"=\"\na   = 1\nbbb = 2\n");
+
+        let (status, aligned, err) = format("a = 1;\n  // align_by\"=\"\n");
+        assert_eq!(status, CARGO_ALIGN_INVALID);
+        assert_eq!(aligned, "");
+        assert_eq!((err.line, err.column), (2, 14));
+        let code = unsafe { CStr::from_ptr(err.code.as_ptr()) };
+        assert_eq!(code.to_str().unwrap(), "A0002");
+        let message = unsafe { CStr::from_ptr(err.message) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { cargo_align_free_string(err.message) };
+        assert_eq!(message, "expected a space after the marker");
+
+        let status = unsafe {
+            cargo_align_format(std::ptr::null(), std::ptr::null_mut(), std::ptr::null_mut())
+        };
+        assert_eq!(status, CARGO_ALIGN_BAD_ARGUMENT);
+    }
+}
@@ -0,0 +1,284 @@
+//! Detection of alignment blocks that another formatter will likely undo.
+//!
+//! Formatters like rustfmt or prettier normalize the whitespace an alignment
+//! block relies on, unless the block is in a region they were told to skip.
+//! This only scans for the skip markers of each formatter, it doesn't check
+//! whether the formatter is actually used.
+
+use crate::directive;
+use crate::directive::parse_directive;
+use anyhow::Context;
+use anyhow::Result;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+
+/// A formatter and the marker that makes it skip a region.
+struct Formatter {
+    name: &'static str,
+    skip_marker: &'static str,
+    extensions: &'static [&'static str],
+    protected_lines: fn(&[&str]) -> Vec<bool>,
+}
+
+const FORMATTERS: [Formatter; 3] = [
+    Formatter {
+        name: "rustfmt",
+        skip_marker: "#[rustfmt::skip]",
+        extensions: &["rs"],
+        protected_lines: rustfmt_protected_lines,
+    },
+    Formatter {
+        name: "prettier",
+        skip_marker: "prettier-ignore",
+        extensions: &[
+            "md", "markdown", "js", "jsx", "mjs", "ts", "tsx", "css", "scss", "less", "html",
+            "vue", "yaml", "yml", "json",
+        ],
+        protected_lines: prettier_protected_lines,
+    },
+    Formatter {
+        name: "black",
+        skip_marker: "# fmt: off",
+        extensions: &["py", "pyi"],
+        protected_lines: black_protected_lines,
+    },
+];
+
+/// Returns the 1 based line number of every alignment directive started by
+/// `marker` that a formatter for `extension` would format, along with a
+/// message naming the formatter and its skip marker.
+pub fn unprotected_directives(source: &str, extension: &str, marker: &str) -> Vec<(usize, String)> {
+    let Some(formatter) = FORMATTERS
+        .iter()
+        .find(|formatter| formatter.extensions.contains(&extension))
+    else {
+        return Vec::new();
+    };
+    let message = format!(
+        "the alignment block is not in a region marked with `{}`, so {} will likely undo the alignment",
+        formatter.skip_marker, formatter.name
+    );
+    let lines = source.lines().collect::<Vec<_>>();
+    let protected = (formatter.protected_lines)(&lines);
+    lines
+        .iter()
+        .enumerate()
+        .take_while(|(_, line)| !directive::is_stop(line, marker))
+        .filter(|(i, line)| !protected[*i] && matches!(parse_directive(line, marker), Ok(Some(_))))
+        .map(|(i, _)| (i + 1, message.clone()))
+        .collect()
+}
+
+/// `source` of the file at `file_path` formatted by rustfmt, for `--rustfmt` to
+/// format before aligning so the alignment is applied on top of rustfmt's
+/// output instead of fighting it.
+///
+/// rustfmt runs next to the file so it finds the `rustfmt.toml` that applies
+/// to it, and formats for `edition` if given, like `cargo fmt` passes the
+/// edition of the package.
+pub fn rustfmt(source: &str, file_path: &Path, edition: Option<&str>) -> Result<String> {
+    let mut command = Command::new("rustfmt");
+    command.args(["--emit", "stdout"]);
+    if let Some(edition) = edition {
+        command.args(["--edition", edition]);
+    }
+    if let Some(directory) = file_path.parent().filter(|directory| directory.is_dir()) {
+        command.current_dir(directory);
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run `rustfmt`, is it installed?")?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .context("Failed to open stdin of `rustfmt`.")?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .context("Failed to open stdout of `rustfmt`.")?;
+    // Writing everything first could block on a full stdout pipe.
+    let formatted = std::thread::scope(|scope| {
+        let reader = scope.spawn(move || {
+            let mut formatted = String::new();
+            stdout.read_to_string(&mut formatted).map(|_| formatted)
+        });
+        let written = stdin.write_all(source.as_bytes());
+        drop(stdin);
+        written.and(
+            reader
+                .join()
+                .expect("reading the output of rustfmt panicked"),
+        )
+    })
+    .context("Failed to pipe the file through `rustfmt`.")?;
+    let output = child
+        .wait_with_output()
+        .context("Failed to run `rustfmt`.")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`rustfmt` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(formatted)
+}
+
+/// Lines inside an item or statement marked `#[rustfmt::skip]`, or after a
+/// `#![rustfmt::skip]` until its enclosing block closes.
+///
+/// Braces are counted without regard for strings or comments, which is good
+/// enough for the usual placement of the attribute on a `mod`, `fn` or `impl`.
+fn rustfmt_protected_lines(lines: &[&str]) -> Vec<bool> {
+    let mut protected = Vec::with_capacity(lines.len());
+    let mut depth = 0usize;
+    // The depth a skipped region ends at, and whether its item has opened a brace yet.
+    let mut region: Option<(usize, bool)> = None;
+    for line in lines {
+        if region.is_none() {
+            if line.contains("#![rustfmt::skip]") {
+                region = Some((depth.saturating_sub(1), true));
+            } else if line.contains("#[rustfmt::skip]") {
+                region = Some((depth, false));
+            }
+        }
+        protected.push(region.is_some());
+        for c in line.chars() {
+            match c {
+                '{' => {
+                    depth += 1;
+                    if let Some((_, opened)) = &mut region {
+                        *opened = true;
+                    }
+                }
+                '}' => depth = depth.saturating_sub(1),
+                ';' if region.is_some_and(|(end, opened)| !opened && depth == end) => region = None,
+                _ => {}
+            }
+        }
+        if region.is_some_and(|(end, opened)| opened && depth <= end) {
+            region = None;
+        }
+    }
+    protected
+}
+
+/// Lines right after a `prettier-ignore` comment, or between
+/// `prettier-ignore-start` and `prettier-ignore-end`.
+fn prettier_protected_lines(lines: &[&str]) -> Vec<bool> {
+    let mut protected = Vec::with_capacity(lines.len());
+    let mut in_region = false;
+    let mut previous_ignores = false;
+    for line in lines {
+        if line.contains("prettier-ignore-start") {
+            in_region = true;
+        } else if line.contains("prettier-ignore-end") {
+            in_region = false;
+        }
+        let ignores_next = line.contains("prettier-ignore") && !line.contains("prettier-ignore-");
+        protected.push(in_region || previous_ignores || ignores_next);
+        if !line.trim().is_empty() {
+            previous_ignores = ignores_next;
+        }
+    }
+    protected
+}
+
+/// Lines between `# fmt: off` and `# fmt: on`.
+fn black_protected_lines(lines: &[&str]) -> Vec<bool> {
+    let mut protected = Vec::with_capacity(lines.len());
+    let mut in_region = false;
+    for line in lines {
+        if line.contains("# fmt: off") {
+            in_region = true;
+        } else if line.contains("# fmt: on") {
+            in_region = false;
+        }
+        protected.push(in_region);
+    }
+    protected
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::directive::DEFAULT_MARKER;
+
+    fn unprotected_lines(source: &str, extension: &str) -> Vec<usize> {
+        unprotected_directives(source, extension, DEFAULT_MARKER).into_iter().map(|(line, _)| line).collect()
+    }
+
+    #[test]
+    fn finding_unprotected_directives() {
+        assert_eq!(unprotected_lines(indoc! {r#"
+            // align_by "="
+            let a = 1;
+            #[rustfmt::skip]
+            fn f() {
+                // align_by "="
+                let a = 1;
+                if a {
+                    // align_by "="
+                }
+            }
+            // align_by "="
+            #[rustfmt::skip]
+            let b = 2;
+            // align_by "="
+            mod m {
+                #![rustfmt::skip]
+                // align_by "="
+            }
+            // align_by "="
+        "#}, "rs"), vec![1, 11, 14, 19]);
+
+        assert_eq!(unprotected_lines(indoc! {r#"
+            <!-- align_by "|" -->
+            <!-- prettier-ignore -->
+            <!-- align_by "|" -->
+
+            <!-- prettier-ignore-start -->
+            <!-- align_by "|" -->
+            <!-- prettier-ignore-end -->
+            <!-- align_by "|" -->
+        "#}, "md"), vec![1, 8]);
+
+        assert_eq!(unprotected_lines(indoc! {r#"
+            # align_by "="
+            # fmt: off
+            # align_by "="
+            # fmt: on
+        "#}, "py"), vec![1]);
+
+        assert_eq!(unprotected_lines("// align_by \"=\"\n", "c"), Vec::<usize>::new());
+        assert_eq!(unprotected_lines("// align_by stop\n// align_by \"=\"\n", "rs"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn running_rustfmt() {
+        if Command::new("rustfmt").arg("--version").output().is_err() {
+            return;
+        }
+        let file_path = Path::new("a.rs");
+        assert_eq!(rustfmt("fn  f( ) {let a=1;}", file_path, None).unwrap(), "fn f() {\n    let a = 1;\n}\n");
+        assert!(rustfmt("fn {", file_path, None).is_err());
+        // `async fn` needs 2018 or later.
+        assert!(rustfmt("async fn f() {}", file_path, Some("2015")).is_err());
+        assert!(rustfmt("async fn f() {}", file_path, Some("2018")).is_ok());
+
+        // A `rustfmt.toml` next to the file applies.
+        let dir = std::env::temp_dir().join(format!("cargo-align-rustfmt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rustfmt.toml"), "tab_spaces = 2\n").unwrap();
+        assert_eq!(rustfmt("fn f() {let a=1;}", &dir.join("a.rs"), Some("2021")).unwrap(), "fn f() {\n  let a = 1;\n}\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
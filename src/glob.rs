@@ -0,0 +1,121 @@
+//! Shell style glob patterns for selecting files, ie `src/**/*.rs`.
+//!
+//! `*` matches any run of characters inside one path component, `?` one
+//! character, `[abc]`, `[a-z]`, and `[!abc]` one character of a set, and a
+//! `**` component any number of components, including none.
+
+/// Whether `arg` is meant as a glob rather than a path. Paths that exist,
+/// like `app/[id].tsx`, are paths even with glob characters in them.
+pub fn is_glob(arg: &str) -> bool {
+    arg.contains(['*', '?', '[']) && !std::path::Path::new(arg).exists()
+}
+
+/// Whether the `/` separated relative `path` matches `pattern`.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches("./");
+    let pattern = pattern.split('/').collect::<Vec<_>>();
+    let path = path.split('/').collect::<Vec<_>>();
+    matches_components(&pattern, &path)
+}
+
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_components(rest, &path[skip..])),
+        Some((component, rest)) => match path.split_first() {
+            Some((name, path)) => {
+                let component = component.chars().collect::<Vec<_>>();
+                let name = name.chars().collect::<Vec<_>>();
+                matches_component(&component, &name) && matches_components(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+fn matches_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_component(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_component(rest, &name[1..]),
+        Some(('[', rest)) => {
+            // A `]` right after the `[` is part of the set.
+            let Some(end) = rest.iter().skip(1).position(|&c| c == ']') else {
+                // An unclosed `[` is a literal character.
+                return name.first() == Some(&'[') && matches_component(rest, &name[1..]);
+            };
+            let Some((&c, name)) = name.split_first() else {
+                return false;
+            };
+            let end = end + 1;
+            let (negated, set) = match &rest[..end] {
+                ['!', set @ ..] => (true, set),
+                set => (false, set),
+            };
+            in_set(set, c) != negated && matches_component(&rest[end + 1..], name)
+        }
+        Some((&literal, rest)) => {
+            name.first() == Some(&literal) && matches_component(rest, &name[1..])
+        }
+    }
+}
+
+/// Whether `c` is in a set of characters and ranges like `a-z_`.
+fn in_set(set: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            if (set[i]..=set[i + 2]).contains(&c) {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detecting_globs() {
+        assert!(is_glob("src/*.rs"));
+        assert!(is_glob("src/?.rs"));
+        assert!(is_glob("src/[ab].rs"));
+        assert!(!is_glob("src/main.rs"));
+
+        let dir = std::env::temp_dir().join(format!("cargo-align-glob-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("app")).unwrap();
+        std::fs::write(dir.join("app/[id].tsx"), "").unwrap();
+        assert!(!is_glob(dir.join("app/[id].tsx").to_str().unwrap()));
+        assert!(is_glob(dir.join("app/[ab].tsx").to_str().unwrap()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matching() {
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/nested/main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("./src/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(matches("**/*.md", "README.md"));
+        assert!(matches("src/**", "src/a/b"));
+        assert!(!matches("src/**/*.rs", "benches/main.rs"));
+        assert!(matches("ma?n.rs", "main.rs"));
+        assert!(!matches("ma?n.rs", "maain.rs"));
+        assert!(matches("[a-c]x.rs", "bx.rs"));
+        assert!(!matches("[!a-c]x.rs", "bx.rs"));
+        assert!(matches("[!a-c]x.rs", "dx.rs"));
+        assert!(matches("[-]x", "-x"));
+        assert!(matches("[x", "[x"));
+        assert!(matches("[]]x", "]x"));
+    }
+}
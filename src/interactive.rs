@@ -0,0 +1,121 @@
+//! Asking before each changed block is applied, for `--interactive`.
+
+use anyhow::Context;
+use anyhow::Result;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+
+const HELP: &str = "y - apply this change
+n - don't apply this change
+a - apply this change and all later changes in the file
+q - quit, don't apply this change or any later ones
+";
+
+/// The answers given so far, read from `input`.
+pub struct Review<R> {
+    input: R,
+    /// Set once `q` was answered, nothing is applied after that.
+    quit: bool,
+    /// Whether the changes are shown in color.
+    color: bool,
+}
+
+impl<R: BufRead> Review<R> {
+    pub fn new(input: R, color: bool) -> Review<R> {
+        Review {
+            input,
+            quit: false,
+            color,
+        }
+    }
+
+    /// The content of `path` with the changes from `old` to `new` that were
+    /// agreed to applied.
+    pub fn review(&mut self, path: &Path, old: &str, new: &str) -> Result<String> {
+        if self.quit {
+            return Ok(old.to_string());
+        }
+        let mut apply_all = false;
+        let mut error = None;
+        let reviewed = crate::diff::apply_changes(old, new, |line, change| {
+            if self.quit || error.is_some() {
+                return false;
+            }
+            if apply_all {
+                return true;
+            }
+            if self.color {
+                eprint!("{}:{line}:\n{}", path.display(), crate::color::diff(change));
+            } else {
+                eprint!("{}:{line}:\n{change}", path.display());
+            }
+            loop {
+                match self.ask() {
+                    Err(err) => {
+                        error = Some(err);
+                        self.quit = true;
+                        return false;
+                    }
+                    Ok(answer) => match answer.as_str() {
+                        "y" => return true,
+                        "n" => return false,
+                        "a" => {
+                            apply_all = true;
+                            return true;
+                        }
+                        "q" => {
+                            self.quit = true;
+                            return false;
+                        }
+                        _ => eprint!("{HELP}"),
+                    },
+                }
+            }
+        });
+        match error {
+            Some(err) => Err(err),
+            None => Ok(reviewed),
+        }
+    }
+
+    fn ask(&mut self) -> Result<String> {
+        eprint!("Apply this change [y,n,a,q,?]? ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        let read = self
+            .input
+            .read_line(&mut answer)
+            .context("Failed to read answer from stdin.")?;
+        if read == 0 {
+            anyhow::bail!("Stdin closed before all changes were reviewed.");
+        }
+        Ok(answer.trim().to_lowercase())
+    }
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reviewing() {
+        let path = Path::new("a.rs");
+        let old = "// align_by \"=\"\na = 1\nbb = 2\n\n// align_by \"=\"\nc = 3\ndd = 4\n";
+        let new = "// align_by \"=\"\na  = 1\nbb = 2\n\n// align_by \"=\"\nc  = 3\ndd = 4\n";
+
+        let mut review = Review::new("n\ny\n".as_bytes(), false);
+        assert_eq!(review.review(path, old, new).unwrap(), "// align_by \"=\"\na = 1\nbb = 2\n\n// align_by \"=\"\nc  = 3\ndd = 4\n");
+
+        let mut review = Review::new("?\na\n".as_bytes(), false);
+        assert_eq!(review.review(path, old, new).unwrap(), new);
+
+        let mut review = Review::new("q\n".as_bytes(), false);
+        assert_eq!(review.review(path, old, new).unwrap(), old);
+        assert_eq!(review.review(path, old, new).unwrap(), old);
+
+        let mut review = Review::new("y\n".as_bytes(), false);
+        assert!(review.review(path, old, new).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Just enough of a JSON parser to read the output of `cargo metadata`.
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// The value of `key` if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+pub fn parse(s: &str) -> Result<Json> {
+    let mut parser = Parser {
+        chars: s.chars().collect(),
+        position: 0,
+    };
+    let value = parser.value()?;
+    parser.whitespace();
+    if parser.position < parser.chars.len() {
+        anyhow::bail!(
+            "Unexpected `{}` after the JSON value",
+            parser.chars[parser.position]
+        );
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn whitespace(&mut self) {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char> {
+        let c = *self
+            .chars
+            .get(self.position)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected end of JSON"))?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.whitespace();
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => anyhow::bail!("Expected `{expected}` in JSON, found `{c}`"),
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Result<Json> {
+        for expected in literal.chars() {
+            if self.next()? != expected {
+                anyhow::bail!("Expected `{literal}` in JSON");
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.whitespace();
+        match self.chars.get(self.position) {
+            None => anyhow::bail!("Unexpected end of JSON"),
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                self.whitespace();
+                if self.chars.get(self.position) == Some(&']') {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        ']' => return Ok(Json::Array(items)),
+                        c => anyhow::bail!("Expected `,` or `]` in JSON, found `{c}`"),
+                    }
+                }
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut entries = Vec::new();
+                self.whitespace();
+                if self.chars.get(self.position) == Some(&'}') {
+                    self.position += 1;
+                    return Ok(Json::Object(entries));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    entries.push((key, self.value()?));
+                    self.whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        '}' => return Ok(Json::Object(entries)),
+                        c => anyhow::bail!("Expected `,` or `}}` in JSON, found `{c}`"),
+                    }
+                }
+            }
+            Some(_) => {
+                let start = self.position;
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(|c| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+                {
+                    self.position += 1;
+                }
+                let number = self.chars[start..self.position].iter().collect::<String>();
+                match number.parse() {
+                    Ok(number) => Ok(Json::Number(number)),
+                    Err(_) => {
+                        anyhow::bail!("Invalid JSON value starting at `{}`", self.chars[start])
+                    }
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if self.next()? != '"' {
+            anyhow::bail!("Expected a string in JSON");
+        }
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let mut code = self.hex4()?;
+                        // Characters outside the BMP are escaped as surrogate pairs.
+                        if (0xD800..0xDC00).contains(&code)
+                            && self.chars.get(self.position) == Some(&'\\')
+                        {
+                            self.position += 1;
+                            if self.next()? != 'u' {
+                                anyhow::bail!("Expected a low surrogate in JSON");
+                            }
+                            let low = self.hex4()?;
+                            code = 0x10000
+                                + ((code - 0xD800) << 10)
+                                + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        s.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.next()?;
+            code = code * 16
+                + digit
+                    .to_digit(16)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid `\\u` escape in JSON"))?;
+        }
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let json = parse(
+            r#" {"a": [1, -2.5e1, true, false, null], "b": {}, "c": "x\"\\\né😀", "d": []} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            json.get("a").unwrap().as_array(),
+            [
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Bool(false),
+                Json::Null,
+            ]
+        );
+        assert_eq!(json.get("b"), Some(&Json::Object(Vec::new())));
+        assert_eq!(json.get("c").and_then(Json::as_str), Some("x\"\\\né😀"));
+        assert_eq!(json.get("d").unwrap().as_array(), []);
+        assert_eq!(json.get("e"), None);
+        assert_eq!(
+            parse(r#""\u00e9\ud83d\ude00""#).unwrap(),
+            Json::String("é😀".to_string())
+        );
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("[] x").is_err());
+        assert!(parse("nul").is_err());
+    }
+}
@@ -0,0 +1,22 @@
+//! Library parts of `cargo-align`, shared by the command line tool.
+//!
+//! The alignment engine in [`align`] and [`directive`] works on strings only,
+//! the filesystem parts are behind the default `cli` feature.
+
+pub mod align;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+mod collation;
+pub mod directive;
+#[cfg(feature = "cli")]
+pub mod discovery;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod stream;
+pub mod table;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,133 @@
+//! An inventory of the alignment statements under a path, for `cargo align list`.
+
+use crate::args::Args;
+use crate::directive;
+use crate::directive::Directive;
+use crate::discover_files;
+use crate::read_file;
+use crate::AlignmentError;
+use crate::BinaryFile;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+/// One alignment statement of a file.
+#[derive(Debug, PartialEq, Serialize)]
+struct Entry {
+    /// 1 based line number.
+    line: usize,
+    #[serde(flatten)]
+    directive: Directive,
+}
+
+/// An [`Entry`] with its file, as `--json` prints it.
+#[derive(Serialize)]
+struct FileEntry<'a> {
+    file: &'a str,
+    #[serde(flatten)]
+    entry: &'a Entry,
+}
+
+/// The alignment statements of `content` before the stop marker.
+fn entries(content: &str, marker: &str) -> Result<Vec<Entry>, AlignmentError> {
+    let mut entries = Vec::new();
+    for (line_index, line) in content.lines().enumerate() {
+        if directive::is_stop(line, marker) {
+            break;
+        }
+        match directive::parse_directive(line, marker) {
+            Ok(Some(directive)) => entries.push(Entry {
+                line: line_index + 1,
+                directive,
+            }),
+            Ok(None) => {}
+            Err(err) => {
+                return Err(AlignmentError::InvalidAlignmentStatement {
+                    line: line_index + 1,
+                    column: err.span.column(line),
+                    kind: err.kind,
+                })
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// `file:line: mode` followed by each delimiter with its side, ie `"=":left`.
+fn format_entry(path: &str, entry: &Entry) -> String {
+    let mut formatted = format!("{path}:{}: {}", entry.line, entry.directive.mode());
+    for delimiter in &entry.directive.delimiters {
+        formatted.push_str(&format!(
+            " \"{}\":{}",
+            delimiter.text,
+            delimiter.alignment.name()
+        ));
+    }
+    formatted
+}
+
+/// One JSON object per line, so the output can be streamed and grepped.
+fn format_entry_json(path: &str, entry: &Entry) -> serde_json::Result<String> {
+    serde_json::to_string(&FileEntry { file: path, entry })
+}
+
+/// Prints every alignment statement of the files under `path`.
+pub fn list(args: &Args, path: &Path, json: bool) -> Result<()> {
+    for file_path in discover_files(path.to_path_buf(), args) {
+        let content = match read_file(&file_path, args.encoding) {
+            Err(err) if err.is::<BinaryFile>() => continue,
+            Err(err) => {
+                eprintln!("{err}");
+                continue;
+            }
+            Ok((content, _)) => content,
+        };
+        let display_path = file_path.display().to_string();
+        match entries(&content, &args.marker) {
+            Err(err) => eprintln!("{display_path}:{err}"),
+            Ok(entries) => {
+                for entry in entries {
+                    if json {
+                        println!("{}", format_entry_json(&display_path, &entry)?);
+                    } else {
+                        println!("{}", format_entry(&display_path, &entry));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::directive::DEFAULT_MARKER;
+
+    #[test]
+    fn listing() {
+        let entries = entries(indoc! {r#"
+            // align_by "=" then right ";"
+            a = 1;
+            // the align_by marker
+            /// align_by table
+            // align_by sort "\""
+            // align_by stop
+            // align_by "="
+        "#}, DEFAULT_MARKER).unwrap();
+        let formatted = entries.iter().map(|entry| format_entry("a.rs", entry)).collect::<Vec<_>>();
+        assert_eq!(formatted, [
+            r#"a.rs:1: normal "=":left ";":right"#,
+            "a.rs:4: table",
+            r#"a.rs:5: sort "\"":left"#,
+        ]);
+        assert_eq!(
+            format_entry_json("a\\b.rs", &entries[2]).unwrap(),
+            r#"{"file":"a\\b.rs","line":5,"mode":"sort","delimiters":[{"text":"\\\"","side":"left"}]}"#
+        );
+        assert!(super::entries("// align_by sort\n", DEFAULT_MARKER).is_err());
+    }
+}
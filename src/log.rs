@@ -0,0 +1,102 @@
+//! Timestamped log lines for `--daemon`, as text or as JSON lines with
+//! `--log-format json` for log collectors.
+
+use crate::args::LogFormat;
+use serde::Serialize;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// A line of `--log-format json`.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    timestamp: &'a str,
+    level: &'static str,
+    message: &'a str,
+}
+
+/// Writes `message` to stderr.
+pub fn log(format: LogFormat, level: Level, message: &str) {
+    eprintln!("{}", line(format, SystemTime::now(), level, message));
+}
+
+fn line(format: LogFormat, time: SystemTime, level: Level, message: &str) -> String {
+    let timestamp = timestamp(time);
+    match format {
+        LogFormat::Text => format!("{timestamp} {:<5} {message}", level.name().to_uppercase()),
+        LogFormat::Json => serde_json::to_string(&JsonLine {
+            timestamp: &timestamp,
+            level: level.name(),
+            message,
+        })
+        .expect("log lines only hold strings"),
+    }
+}
+
+/// `time` in RFC 3339 in UTC, with milliseconds.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+    // Days to a civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn formatting() {
+        assert_eq!(
+            timestamp(SystemTime::UNIX_EPOCH),
+            "1970-01-01T00:00:00.000Z"
+        );
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(timestamp(time), "2024-02-29T12:34:56.789Z");
+        assert_eq!(
+            line(LogFormat::Text, time, Level::Info, "Listening"),
+            "2024-02-29T12:34:56.789Z INFO  Listening"
+        );
+        assert_eq!(
+            line(LogFormat::Json, time, Level::Error, "Failed \"a\""),
+            r#"{"timestamp":"2024-02-29T12:34:56.789Z","level":"error","message":"Failed \"a\""}"#
+        );
+    }
+}
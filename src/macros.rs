@@ -0,0 +1,177 @@
+//! Detection of alignment blocks that live inside Rust macro invocations.
+//!
+//! Macros re-emit their input as tokens, so any whitespace an alignment block
+//! relies on is gone after expansion and the alignment only exists in the
+//! source. Rather than running `cargo expand` this does a lexical scan of the
+//! source, tracking which delimiters were opened by a `name!` invocation.
+
+use crate::directive::parse_directive;
+
+/// Returns the 1 based line number of every alignment directive started by
+/// `marker` whose block starts inside a macro invocation, along with the name
+/// of the innermost macro.
+pub fn directives_inside_macros(source: &str, marker: &str) -> Vec<(usize, String)> {
+    let macro_at_line_start = innermost_macro_per_line(source);
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| matches!(parse_directive(line, marker), Ok(Some(_))))
+        .filter_map(|(i, _)| {
+            macro_at_line_start
+                .get(i + 1)
+                .cloned()
+                .flatten()
+                .map(|name| (i + 1, name))
+        })
+        .collect()
+}
+
+/// For every line, the name of the innermost macro invocation the line starts in.
+fn innermost_macro_per_line(source: &str) -> Vec<Option<String>> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut per_line = vec![None];
+    let mut delimiters: Vec<Option<String>> = Vec::new();
+    let mut identifier = String::new();
+    let mut pending_macro: Option<String> = None;
+    let innermost =
+        |delimiters: &[Option<String>]| delimiters.iter().rev().flatten().next().cloned();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\n' => per_line.push(innermost(&delimiters)),
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i + 1 < chars.len() && chars[i + 1] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    if chars[i] == '\n' {
+                        per_line.push(innermost(&delimiters));
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            '"' => {
+                let raw_hashes = raw_string_hashes(&chars[..i]);
+                i += 1;
+                while i < chars.len() {
+                    match chars[i] {
+                        '\\' if raw_hashes.is_none() => i += 1,
+                        '"' if (1..=raw_hashes.unwrap_or(0))
+                            .all(|n| chars.get(i + n) == Some(&'#')) =>
+                        {
+                            i += raw_hashes.unwrap_or(0);
+                            break;
+                        }
+                        '\n' => per_line.push(innermost(&delimiters)),
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+            '\'' => {
+                if chars.get(i + 1) == Some(&'\\') {
+                    while i + 1 < chars.len() && chars[i + 1] != '\'' {
+                        i += 1;
+                    }
+                    i += 1;
+                } else if chars.get(i + 2) == Some(&'\'') {
+                    i += 2;
+                }
+            }
+            '!' if !identifier.is_empty() => {
+                pending_macro = Some(std::mem::take(&mut identifier));
+                i += 1;
+                continue;
+            }
+            '(' | '[' | '{' => delimiters.push(pending_macro.take()),
+            ')' | ']' | '}' => {
+                delimiters.pop();
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                identifier.push(c);
+                i += 1;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                identifier.clear();
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        identifier.clear();
+        if !matches!(c, '(' | '[' | '{') && !c.is_whitespace() {
+            pending_macro = None;
+        }
+        i += 1;
+    }
+    per_line
+}
+
+/// If the quote ending `before` starts a raw string, returns its number of `#`s.
+fn raw_string_hashes(before: &[char]) -> Option<usize> {
+    let hashes = before.iter().rev().take_while(|c| **c == '#').count();
+    let mut prefix = before[..before.len() - hashes].iter().rev();
+    if prefix.next() != Some(&'r') {
+        return None;
+    }
+    let mut preceding = prefix.next();
+    if matches!(preceding, Some('b' | 'c')) {
+        preceding = prefix.next();
+    }
+    match preceding {
+        Some(c) if c.is_alphanumeric() || *c == '_' => None,
+        _ => Some(hashes),
+    }
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::directive::DEFAULT_MARKER;
+
+    #[test]
+    fn finding_macros() {
+        assert_eq!(directives_inside_macros(indoc! {r#"
+            // align_by "="
+            let a = 1;
+            let bb = 2;
+        "#}, DEFAULT_MARKER), vec![]);
+
+        assert_eq!(directives_inside_macros(indoc! {r#"
+            let v = vec![
+                // align_by ","
+                1, 2,
+                33, 4,
+            ];
+            // align_by "="
+            let a = 1;
+        "#}, DEFAULT_MARKER), vec![(2, "vec".to_string())]);
+
+        assert_eq!(directives_inside_macros(indoc! {r##"
+            foo! {
+                let s = "}";
+                let r = r#"}"#;
+                let c = '}';
+                /* } */
+                bar(
+                    // align_by "="
+                    a = 1,
+                )
+            }
+            if !(a) {
+                // align_by "="
+                a = 1;
+            }
+        "##}, DEFAULT_MARKER), vec![(7, "foo".to_string())]);
+    }
+}
@@ -1,450 +1,1082 @@
 use anyhow::Context;
 use anyhow::Result;
-use std::ops::Not;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();
-    let path_to_align = match args.len() {
-        1 => PathBuf::from(fetch_cargo_metadata()?),
-        2 => PathBuf::from(&args[1]),
-        len => panic!("Expected either 0 or 1 arguments, got {len}"),
+mod args;
+mod backups;
+mod cache;
+mod checkstyle;
+mod color;
+mod daemon;
+mod diff;
+mod editorconfig;
+mod encoding;
+mod formatter;
+mod glob;
+mod interactive;
+mod json;
+mod list;
+mod log;
+mod macros;
+mod metadata;
+mod parallel;
+mod progress;
+mod rename;
+mod report;
+mod snapshot;
+mod stats;
+
+use args::Args;
+use args::Command;
+use args::Emit;
+use args::ErrorFormat;
+use args::OutputFormat;
+use args::ReadOnly;
+use cargo_align::align::AlignReport;
+use cargo_align::align::AlignmentError;
+use cargo_align::directive;
+use cargo_align::directive::InvalidAlignmentStatement;
+use cargo_align::discovery::Discovery;
+use encoding::Encoding;
+
+/// Some files couldn't be read, backed up, or written.
+const EXIT_IO_ERROR: u8 = 2;
+/// Some files have malformed alignment statements.
+const EXIT_INVALID: u8 = 3;
+/// With `--diff` or `--output-format checkstyle`, some files aren't aligned.
+const EXIT_UNALIGNED: u8 = 4;
+
+fn main() -> Result<ExitCode> {
+    let mut args = Args::parse(args::cargo_args(std::env::args()))?;
+    if args.command == Command::Help {
+        print!("{}", args::HELP);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.command == Command::Version {
+        println!("cargo-align {}", env!("CARGO_PKG_VERSION"));
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Command::Explain(code) = &args.command {
+        let kind = InvalidAlignmentStatement::from_code(code)
+            .with_context(|| format!("`{code}` is not a known error code"))?;
+        print!("{}", kind.explanation());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let path_to_align = match (&args.path, args.files.first()) {
+        (Some(path), _) | (None, Some(path)) => path.clone(),
+        (None, None) => {
+            let metadata = metadata::Metadata::fetch()?;
+            args.package_directories = metadata.package_directories(&args.packages)?;
+            if !args.include_non_members {
+                args.member_directories = metadata.member_directories();
+            }
+            if args.rustfmt {
+                args.package_editions = metadata.package_editions();
+            }
+            metadata.workspace_root
+        }
     };
-    
+    if args.rustfmt && args.package_editions.is_empty() {
+        // Outside of a Cargo project rustfmt picks the edition on its own.
+        if let Ok(metadata) = metadata::Metadata::fetch() {
+            args.package_editions = metadata.package_editions();
+        }
+    }
+    let args = args;
+    if let Some(stdin_filepath) = &args.stdin_filepath {
+        align_stdin(&args, &path_to_align, stdin_filepath)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.daemon {
+        daemon::daemon(&args, &path_to_align)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let modifies_files = match args.command {
+        Command::Align => {
+            !args.diff && args.output_format == OutputFormat::Human && args.emit == Emit::Files
+        }
+        Command::RenameMarker { .. } => true,
+        Command::Explain(_) | Command::Help | Command::Version => false,
+        Command::Snapshot { .. } => false,
+        Command::Stats { .. } => false,
+        Command::List { .. } => false,
+        Command::Revert => true,
+    };
+    if modifies_files
+        && !args.allow_no_vcs
+        && !is_under_version_control(&path_to_align)
+        && !confirm_no_vcs(&path_to_align)?
+    {
+        println!("Cancelled, no files were modified.");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    match &args.command {
+        Command::RenameMarker { from, to } => rename_marker(&args, path_to_align, from, to)?,
+        Command::Snapshot { out, verify } => {
+            snapshot::snapshot(&args, &path_to_align, out, *verify)?
+        }
+        Command::Stats {
+            history: Some(commits),
+        } => stats::history(&args, &path_to_align, *commits)?,
+        Command::Stats { history: None } => stats::stats(&args, &path_to_align)?,
+        Command::List { json } => list::list(&args, &path_to_align, *json)?,
+        Command::Revert => revert(&args, path_to_align)?,
+        Command::Align | Command::Explain(_) | Command::Help | Command::Version => {
+            return align(&args, path_to_align)
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Aligns the files under `path_to_align`, the exit code tells whether any
+/// couldn't be.
+fn align(args: &Args, path_to_align: PathBuf) -> Result<ExitCode> {
     let mut files_failed_to_align = 0;
+    let mut files_failed_to_write = 0;
+    let mut files_invalid = 0;
     let mut files_unchanged = 0;
     let mut files_aligned = 0;
-    let files_to_process = get_files_recursively(path_to_align);
-    for file_path in files_to_process.iter() {
-        let file_content = match std::fs::read_to_string(file_path) {
-            Err(_) => {
-                files_failed_to_align += 1;
+    let mut files_not_sampled = 0;
+    let mut files_binary = 0;
+    let mut files_read_only = 0;
+    let mut run_stats = stats::RunStats::default();
+    let mut report = report::Report::default();
+    let mut checkstyle_files = Vec::new();
+    let files_to_process = discover_files(path_to_align.clone(), args);
+    let print_file_names = files_to_process.len() > 1;
+    let mut cache = if args.incremental {
+        let workspace_root = metadata::Metadata::fetch()
+            .context("`--incremental` keeps its cache in the `target` directory of the workspace")?
+            .workspace_root;
+        // Everything that changes the aligned content of a file.
+        let options = format!(
+            "{:?} {} {}",
+            args.align_options(),
+            args.rustfmt,
+            args.no_editorconfig
+        );
+        Some(cache::Cache::load(
+            workspace_root.join("target/cargo-align-cache"),
+            &options,
+        ))
+    } else {
+        None
+    };
+    let mut backups = match args.backup {
+        true => Some(backups::Backups::load(backups_path()?)),
+        false => None,
+    };
+    // Files found aligned, added to the cache once the workers are done with it.
+    let mut newly_aligned = Vec::new();
+    // Diffs and aligned content go to the terminal too, a bar would be drawn over them.
+    let mut progress = progress::Progress::new(
+        files_to_process.len(),
+        !args.quiet
+            && !args.diff
+            && args.output_format == OutputFormat::Human
+            && !args.interactive
+            && args.emit != Emit::Stdout
+            && std::io::stderr().is_terminal(),
+    );
+    let mut review = args
+        .interactive
+        .then(|| interactive::Review::new(std::io::stdin().lock(), args.color.stderr()));
+    // Reading and aligning happens on worker threads, everything else in order.
+    let process = |file_path: &PathBuf| -> Result<ProcessedFile> {
+        let (content, encoding) = read_file(file_path, args.encoding)?;
+        let start = std::time::Instant::now();
+        let cached = cache.as_ref().is_some_and(|cache| {
+            cache.is_aligned(
+                &cache::Cache::key(file_path),
+                cache::hash(content.as_bytes()),
+            )
+        });
+        let aligned = if cached {
+            Ok((content.clone(), AlignReport::default()))
+        } else {
+            let formatted = rustfmt_if_enabled(args, file_path, &content)?;
+            align_content(&formatted, file_path, args)
+        };
+        Ok(ProcessedFile {
+            content,
+            encoding,
+            aligned,
+            elapsed: start.elapsed(),
+        })
+    };
+    let jobs = args.jobs.unwrap_or_else(parallel::default_jobs);
+    std::thread::scope(|scope| {
+        let processed_files = parallel::map_ordered(scope, &files_to_process, jobs, &process);
+        for (scanned, (file_path, processed)) in
+            files_to_process.iter().zip(processed_files).enumerate()
+        {
+            if args.fail_fast && files_failed_to_align + files_failed_to_write + files_invalid > 0 {
+                progress.clear();
+                eprintln!(
+                    "Stopped at the first error because of `--fail-fast`, {} files weren't looked at.",
+                    files_to_process.len() - scanned
+                );
+                break;
+            }
+            progress.update(scanned, files_aligned);
+            let ProcessedFile {
+                content: file_content,
+                encoding,
+                aligned,
+                elapsed,
+            } = match processed {
+                Err(err) if err.is::<BinaryFile>() => {
+                    files_binary += 1;
+                    report.record(file_path, report::Outcome::Binary);
+                    continue;
+                }
+                Err(err) => {
+                    progress.clear();
+                    eprintln!("{err}");
+                    files_failed_to_align += 1;
+                    report.record(file_path, report::Outcome::Failed(err.to_string()));
+                    continue;
+                }
+                Ok(processed) => processed,
+            };
+            let (aligned_content, align_report) = match aligned {
+                Err(err) => {
+                    progress.clear();
+                    let message = args.error_format.render(&err, file_path);
+                    if args.color.stderr() {
+                        eprintln!("{}", color::error(&message));
+                    } else {
+                        eprintln!("{message}");
+                    }
+                    files_invalid += 1;
+                    report.record(file_path, report::Outcome::Invalid(err.to_string()));
+                    checkstyle_files.push((
+                        file_path.clone(),
+                        vec![checkstyle::Diagnostic {
+                            line: err.line(),
+                            column: err.column(),
+                            message: err.message(),
+                            source: format!("cargo-align.{}", err.code()),
+                        }],
+                    ));
+                    continue;
+                }
+                Ok(aligned) => aligned,
+            };
+            // Warnings are printed on every run, files with any aren't cached.
+            let cacheable = cache.is_some() && align_report.warnings.is_empty();
+            for warning in &align_report.warnings {
+                progress.clear();
+                eprintln!(
+                    "Warning: {}:{}: {}",
+                    file_path.display(),
+                    warning.line,
+                    warning.message
+                );
+            }
+
+            if args.expand_macros && file_path.extension().is_some_and(|ext| ext == "rs") {
+                for (line, macro_name) in
+                    macros::directives_inside_macros(&file_content, &args.marker)
+                {
+                    progress.clear();
+                    eprintln!(
+                        "Warning: {}:{line}: the alignment block is inside an invocation of `{macro_name}!`, whose expansion may not preserve the alignment whitespace.",
+                        file_path.display()
+                    );
+                }
+            }
+
+            if args.warn_formatters {
+                let extension = file_path.extension().unwrap_or_default().to_string_lossy();
+                for (line, message) in
+                    formatter::unprotected_directives(&file_content, &extension, &args.marker)
+                {
+                    progress.clear();
+                    eprintln!("Warning: {}:{line}: {message}", file_path.display());
+                }
+            }
+
+            let sampled_out = file_content != aligned_content
+                && args.sample.is_some_and(|percent| {
+                    let relative_path = file_path.strip_prefix(&path_to_align).unwrap_or(file_path);
+                    !is_sampled(relative_path, percent, args.seed)
+                });
+            let aligned_content = if sampled_out {
+                files_not_sampled += 1;
+                file_content.clone()
+            } else {
+                aligned_content
+            };
+            if args.stats {
+                run_stats.record(
+                    file_path,
+                    &file_content,
+                    &aligned_content,
+                    &args.marker,
+                    elapsed,
+                );
+            }
+
+            if args.emit == Emit::Stdout {
+                if print_file_names {
+                    println!("{}:\n", file_path.display());
+                }
+                print!("{aligned_content}");
+            }
+
+            if file_content == aligned_content {
+                if sampled_out {
+                    report.record(file_path, report::Outcome::NotSampled);
+                } else {
+                    files_unchanged += 1;
+                    report.record(file_path, report::Outcome::Unchanged);
+                    if cacheable {
+                        newly_aligned.push((
+                            cache::Cache::key(file_path),
+                            cache::hash(file_content.as_bytes()),
+                        ));
+                    }
+                }
                 continue;
             }
-            Ok(s) => s,
-        };
-        let aligned_content = align_string(&file_content);
 
-        if file_content == aligned_content {
-            files_unchanged += 1;
-            continue;
-        }
+            if args.output_format == OutputFormat::Checkstyle {
+                let mut diagnostics = align_report
+                    .blocks
+                    .iter()
+                    .filter(|block| block.changed)
+                    .map(|block| checkstyle::Diagnostic {
+                        line: *block.lines.start(),
+                        column: 1,
+                        message: format!(
+                            "lines {} to {} aren't aligned by the alignment statement on line {}",
+                            block.lines.start(),
+                            block.lines.end(),
+                            block.statement_line
+                        ),
+                        source: "cargo-align.misaligned".to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                // Lines of the report are off once rustfmt has changed the file, and
+                // changes outside of blocks, like to the final newline, have no block.
+                if args.rustfmt || diagnostics.is_empty() {
+                    diagnostics = diff::change_starts(&file_content, &aligned_content)
+                        .into_iter()
+                        .map(|line| checkstyle::Diagnostic {
+                            line,
+                            column: 1,
+                            message: "this block isn't aligned".to_string(),
+                            source: "cargo-align.misaligned".to_string(),
+                        })
+                        .collect();
+                }
+                checkstyle_files.push((file_path.clone(), diagnostics));
+                files_aligned += 1;
+                report.record(file_path, report::Outcome::Aligned);
+                continue;
+            }
 
-        if let Err(err) = std::fs::write(file_path, aligned_content).with_context(|| {
-            format!(
-                "Failed to write aligned content to file at path {}",
-                file_path.display()
-            )
-        }) {
+            if args.diff {
+                if let Some(diff) = diff::render_diff(
+                    &file_path.display().to_string(),
+                    &file_content,
+                    &aligned_content,
+                ) {
+                    if args.color.stdout() {
+                        print!("{}", color::diff(&diff));
+                    } else {
+                        print!("{diff}");
+                    }
+                }
+                files_aligned += 1;
+                report.record(file_path, report::Outcome::Aligned);
+                continue;
+            }
+
+            if args.emit == Emit::Files && is_read_only(file_path) {
+                progress.clear();
+                match args.readonly {
+                    ReadOnly::Skip | ReadOnly::Warn => {
+                        if args.readonly == ReadOnly::Warn {
+                            eprintln!("Skipped read-only file at path {}", file_path.display());
+                        }
+                        files_read_only += 1;
+                        report.record(file_path, report::Outcome::ReadOnly);
+                    }
+                    ReadOnly::Error => {
+                        let message = format!(
+                            "Failed to write content to file at path {} because it is read-only",
+                            file_path.display()
+                        );
+                        eprintln!("{message}");
+                        files_failed_to_write += 1;
+                        report.record(file_path, report::Outcome::Failed(message));
+                    }
+                }
+                continue;
+            }
+
+            let aligned_content = match &mut review {
+                None => aligned_content,
+                Some(review) => match review.review(file_path, &file_content, &aligned_content) {
+                    Err(err) => {
+                        eprintln!("{err}");
+                        report.record(file_path, report::Outcome::Unchanged);
+                        continue;
+                    }
+                    Ok(reviewed) if reviewed == file_content => {
+                        files_unchanged += 1;
+                        report.record(file_path, report::Outcome::Unchanged);
+                        continue;
+                    }
+                    Ok(reviewed) => reviewed,
+                },
+            };
+
+            let output_path = match args.emit {
+                Emit::Files => file_path.clone(),
+                Emit::Stdout => {
+                    files_aligned += 1;
+                    report.record(file_path, report::Outcome::Aligned);
+                    continue;
+                }
+                Emit::NewFiles => {
+                    let mut output_path = file_path.clone().into_os_string();
+                    output_path.push(".aligned");
+                    PathBuf::from(output_path)
+                }
+            };
+
+            if let Some(backups) = &mut backups {
+                if let Err(err) = backups.back_up(file_path) {
+                    let message = format!("{err:#}");
+                    progress.clear();
+                    eprintln!("{message}");
+                    files_failed_to_align += 1;
+                    report.record(file_path, report::Outcome::Failed(message));
+                    continue;
+                }
+            }
+
+            let modified = args
+                .preserve_mtime
+                .then(|| std::fs::metadata(file_path).and_then(|metadata| metadata.modified()))
+                .and_then(Result::ok);
+            let written = write_file(&output_path, &aligned_content, encoding).and_then(|()| {
+                modified.map_or(Ok(()), |modified| set_modified(&output_path, modified))
+            });
+            if let Err(err) = written {
+                progress.clear();
+                eprintln!("{err}");
+                files_failed_to_write += 1;
+                report.record(file_path, report::Outcome::Failed(err.to_string()));
+            } else {
+                files_aligned += 1;
+                report.record(file_path, report::Outcome::Aligned);
+                if cacheable && args.emit == Emit::Files {
+                    newly_aligned.push((
+                        cache::Cache::key(file_path),
+                        cache::hash(aligned_content.as_bytes()),
+                    ));
+                }
+            }
+        }
+    });
+    progress.clear();
+    if let Some(cache) = &mut cache {
+        for (file, content_hash) in newly_aligned {
+            cache.insert(file, content_hash);
+        }
+        if let Err(err) = cache.save() {
             eprintln!("{err}");
-        } else {
-            files_aligned += 1;
         }
     }
+    if let Err(err) = backups.as_ref().map_or(Ok(()), backups::Backups::save) {
+        eprintln!("{err}");
+    }
     
-    println!("Aligning finished, {files_failed_to_align} failed to align because of non-utf-8 data, {files_unchanged} unchanged, {files_aligned} aligned.");
-    Ok(())
+    if args.output_format == OutputFormat::Checkstyle {
+        print!("{}", checkstyle::render(&checkstyle_files));
+    } else if !args.quiet {
+        let color = if !args.diff && args.emit == Emit::Stdout {
+            args.color.stderr()
+        } else {
+            args.color.stdout()
+        };
+        let count =
+            |count: usize, style| color::paint(color && count > 0, style, &count.to_string());
+        let failed = count(files_failed_to_align, color::RED);
+        let invalid = count(files_invalid, color::RED);
+        let aligned = count(files_aligned, color::GREEN);
+        if args.diff {
+            println!("Diffing finished, {failed} failed to be read, {invalid} have invalid alignment statements, {files_unchanged} unchanged, {aligned} would be aligned.");
+        } else if args.emit == Emit::Stdout {
+            eprintln!("Aligning finished, {failed} failed to be read, {invalid} have invalid alignment statements, {files_unchanged} unchanged, {aligned} would be aligned.");
+        } else {
+            println!("Aligning finished, {failed} failed to be read, {invalid} have invalid alignment statements, {files_unchanged} unchanged, {aligned} aligned.");
+        }
+        if files_binary > 0 {
+            eprintln!("{files_binary} files were skipped because they look binary.");
+        }
+        if files_read_only > 0 {
+            eprintln!("{files_read_only} read-only files that need aligning were skipped.");
+        }
+    }
+    if let Some(percent) = args.sample {
+        eprintln!("{files_not_sampled} files that would be aligned were left out of the {percent}% sample, run again with a different `--seed` or a larger `--sample` to align them.");
+    }
+    if args.stats {
+        // Aligned content may be on stdout, keep it free of anything else.
+        eprint!("{run_stats}");
+    }
+    if let Some(report_path) = &args.report {
+        report.write(report_path)?;
+    }
+    let checking = args.diff || args.output_format == OutputFormat::Checkstyle;
+    Ok(if files_failed_to_align + files_failed_to_write > 0 {
+        ExitCode::from(EXIT_IO_ERROR)
+    } else if files_invalid > 0 {
+        ExitCode::from(EXIT_INVALID)
+    } else if checking && files_aligned > 0 && !args.no_error_on_unaligned {
+        ExitCode::from(EXIT_UNALIGNED)
+    } else {
+        ExitCode::SUCCESS
+    })
 }
 
-fn fetch_cargo_metadata() -> Result<String> {
-    let metadata_raw = std::process::Command::new("cargo")
-        .arg("metadata")
-        .output()
-        .context("Failed to run `cargo metadata`.")?
-        .stdout;
-    let metadata =
-        std::str::from_utf8(&metadata_raw).context("`cargo metadata` produced invalid utf-8.")?;
-    let workspace_root_plus_garbage = metadata
-        .split_once("\"workspace_root\":\"")
-        .context("Failed to find `workspace_root` in the output of `cargo metadata`.")?
-        .1;
-    Ok(extract_quote(
-        workspace_root_plus_garbage,
-    ))
+/// Aligns stdin to stdout for editors, reporting problems as if the content
+/// was the file at `file_path`. Content of files that aligning `root` would
+/// skip, like ignored files, is passed through unchanged.
+fn align_stdin(args: &Args, root: &Path, file_path: &Path) -> Result<()> {
+    let mut bytes = Vec::new();
+    std::io::Read::read_to_end(&mut std::io::stdin(), &mut bytes)
+        .context("Failed to read content from stdin.")?;
+    let (content, encoding) = decode_file(file_path, &bytes, args.encoding)?;
+    let discovery = buffer_discovery(args, root);
+    let (aligned_content, align_report) = align_buffer(args, &discovery, file_path, content)?;
+    for warning in align_report.warnings {
+        eprintln!(
+            "Warning: {}:{}: {}",
+            file_path.display(),
+            warning.line,
+            warning.message
+        );
+    }
+    std::io::stdout()
+        .write_all(&encoding.encode(&aligned_content)?)
+        .context("Failed to write aligned content to stdout.")
 }
 
-fn get_files_recursively(path: PathBuf) -> Vec<PathBuf> {
-    let path_metadata = match std::fs::metadata(&path)
-        .with_context(|| format!("Failed to get metadata of path {}", path.display()))
-    {
-        Err(err) => {
-            eprintln!("{err}");
-            return vec![];
-        }
-        Ok(meta) => meta,
-    };
+/// The [`Discovery`] of the files under `root` that [`align_buffer`] checks
+/// buffers against.
+fn buffer_discovery(args: &Args, root: &Path) -> Discovery {
+    discovery(std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf()), args)
+}
 
-    if path_metadata.is_file() {
-        if path_metadata.len() > 1 << 20 {
-            eprintln!(
-                "Skipping file {} because it is over 1 MB in size.",
-                path.display()
-            );
-            return vec![];
-        } else {
-            return vec![path];
-        }
+/// Aligns `content` as if it was the file at `file_path`, passing it through
+/// unchanged if `discovery` would skip that file.
+fn align_buffer(
+    args: &Args,
+    discovery: &Discovery,
+    file_path: &Path,
+    content: String,
+) -> Result<(String, AlignReport)> {
+    let absolute = std::path::absolute(file_path).unwrap_or_else(|_| file_path.to_path_buf());
+    if !discovery.includes(&absolute) {
+        return Ok((content, AlignReport::default()));
     }
+    let formatted = rustfmt_if_enabled(args, file_path, &content)?;
+    align_content(&formatted, file_path, args)
+        .map_err(|err| anyhow::anyhow!(args.error_format.render(&err, file_path)))
+}
 
-    if path.file_name() == Some(std::ffi::OsStr::new(".git")) {
-        return vec![];
+/// `content` formatted by rustfmt if `--rustfmt` is on and the file at
+/// `file_path` is a Rust file, with the edition of its package like `cargo fmt`.
+fn rustfmt_if_enabled<'a>(
+    args: &Args,
+    file_path: &Path,
+    content: &'a str,
+) -> Result<std::borrow::Cow<'a, str>> {
+    if args.rustfmt && file_path.extension().is_some_and(|ext| ext == "rs") {
+        let file_path = std::path::absolute(file_path).unwrap_or_else(|_| file_path.to_path_buf());
+        // The package with the closest directory above the file.
+        let edition = args
+            .package_editions
+            .iter()
+            .filter(|(directory, _)| file_path.starts_with(directory))
+            .max_by_key(|(directory, _)| directory.components().count())
+            .map(|(_, edition)| edition.as_str());
+        let formatted = formatter::rustfmt(content, &file_path, edition)
+            .with_context(|| format!("Failed to format {}", file_path.display()))?;
+        Ok(std::borrow::Cow::Owned(formatted))
+    } else {
+        Ok(std::borrow::Cow::Borrowed(content))
     }
+}
 
-    let dir_contents = match std::fs::read_dir(&path)
-        .with_context(|| format!("Failed to read contents of path {}", path.display()))
-    {
-        Err(err) => {
+/// Aligns `content` of the file at `file_path` with the options of `args`,
+/// measuring tabs, indenting and ending the last line like `.editorconfig`
+/// says.
+fn align_content(
+    content: &str,
+    file_path: &Path,
+    args: &Args,
+) -> Result<(String, AlignReport), AlignmentError> {
+    let mut options = args.align_options_for(file_path);
+    if args.no_editorconfig {
+        return options.align_string_with_report(content);
+    }
+    let properties = editorconfig::Properties::for_file(file_path);
+    // `--tab-width` wins over the file.
+    if args.tab_width.is_none() {
+        options.tab_width(properties.tab_width());
+    }
+    options.indent_with_tabs(properties.indent_with_tabs == Some(true));
+    let (aligned, report) = options.align_string_with_report(content)?;
+    Ok((properties.apply_to(content, aligned), report))
+}
+
+/// Whether the file at `relative_path` is part of a `percent` sized sample.
+///
+/// The choice only depends on the path and `seed`, so repeated runs pick the
+/// same files and a larger sample includes every file of a smaller one.
+fn is_sampled(relative_path: &Path, percent: f64, seed: u64) -> bool {
+    let path = relative_path.to_string_lossy().replace('\\', "/");
+    let hash = cache::hash(&[&seed.to_le_bytes(), path.as_bytes()].concat());
+    ((hash % 10_000) as f64) < percent * 100.0
+}
+
+fn rename_marker(args: &Args, path: PathBuf, from: &str, to: &str) -> Result<()> {
+    let mut files_failed_to_rename = 0;
+    let mut markers_renamed = 0;
+    let mut files_renamed = 0;
+    for file_path in discover_files(path, args) {
+        let (file_content, encoding) = match read_file(&file_path, args.encoding) {
+            Err(err) if err.is::<BinaryFile>() => continue,
+            Err(err) => {
+                eprintln!("{err}");
+                files_failed_to_rename += 1;
+                continue;
+            }
+            Ok(read) => read,
+        };
+        let (renamed_content, renamed) = rename::rename_marker(&file_content, from, to);
+        if renamed == 0 {
+            continue;
+        }
+        if let Err(err) = write_file(&file_path, &renamed_content, encoding) {
             eprintln!("{err}");
-            return vec![];
+            files_failed_to_rename += 1;
+        } else {
+            markers_renamed += renamed;
+            files_renamed += 1;
         }
-        Ok(read_dir) => read_dir,
     }
-    .filter_map(|x| {
-        match x.with_context(|| {
-            format!(
-                "Failure occured on reading one item from the contents of path {}",
-                path.display()
-            )
-        }) {
+
+    println!("Renaming finished, {files_failed_to_rename} failed, {markers_renamed} markers renamed in {files_renamed} files.");
+    Ok(())
+}
+
+/// The list of the backups `--backup` wrote, next to the `--incremental` cache.
+fn backups_path() -> Result<PathBuf> {
+    let workspace_root = metadata::Metadata::fetch()
+        .context("`--backup` keeps its list of backups in the `target` directory of the workspace")?
+        .workspace_root;
+    Ok(workspace_root.join("target/cargo-align-backups"))
+}
+
+/// Moves the backups `--backup` wrote of the files under `path` back over them.
+fn revert(args: &Args, path: PathBuf) -> Result<()> {
+    let mut backups = backups::Backups::load(backups_path()?);
+    let (files_reverted, files_failed_to_revert) =
+        restore_backups(&mut backups, &discover_files(path, args));
+    backups.save()?;
+
+    println!(
+        "Reverting finished, {files_failed_to_revert} failed, {files_reverted} files restored."
+    );
+    Ok(())
+}
+
+/// Restores the backups of `files` that are in `backups`, returning how many
+/// were restored and how many failed to be.
+fn restore_backups(backups: &mut backups::Backups, files: &[PathBuf]) -> (usize, usize) {
+    let mut files_failed_to_revert = 0;
+    let mut files_reverted = 0;
+    for file_path in files {
+        match backups.restore(file_path) {
             Err(err) => {
-                eprintln!("{err}");
-                None
+                eprintln!("{err:#}");
+                files_failed_to_revert += 1;
             }
-            Ok(dir_entry) => Some(dir_entry),
+            Ok(true) => files_reverted += 1,
+            Ok(false) => {}
         }
-    })
-    .collect::<Vec<_>>();
-
-    let dir_contents = match dir_contents.iter().find(|d| d.file_name() == ".gitignore") {
-        None => dir_contents,
-        Some(git_ignore_dir) => {
-            match std::fs::read_to_string(git_ignore_dir.path()).with_context(|| {
-                format!(
-                    "Failed to read content of `.gitignore` at path {}",
-                    git_ignore_dir.path().display()
-                )
-            }) {
-                Err(err) => {
-                    eprintln!("{err}");
-                    dir_contents
-                }
-                Ok(git_ignore_content) => {
-                    let ignored_directories = git_ignore_content
-                        .lines()
-                        .filter_map(|line| {
-                            if line.len() > 1 && line.starts_with('/') && !line[1..].contains('/') {
-                                Some(&line[1..])
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>();
-                    dir_contents
-                        .into_iter()
-                        .filter(|d| {
-                            ignored_directories
-                                .iter()
-                                .all(|line| d.file_name() != *line)
-                        })
-                        .collect()
-                }
+    }
+    (files_reverted, files_failed_to_revert)
+}
+
+/// A [`Discovery`] of the files under `path`, or the directories of the
+/// `--package`s under it, configured by the walking flags of `args`.
+fn discovery(path: impl Into<PathBuf>, args: &Args) -> Discovery {
+    let root = path.into();
+    let mut discovery = match args.package_directories.split_first() {
+        None => Discovery::new(&root),
+        Some((first, rest)) => {
+            let mut discovery = Discovery::new(first);
+            for directory in rest {
+                discovery.add_root(directory);
             }
+            discovery
         }
     };
+    discovery
+        .ignore_files(!args.no_ignore)
+        .git_ignore(!args.no_git_ignore)
+        .follow_links(!args.no_follow)
+        .max_depth(args.max_depth)
+        .filesize_limit(args.filesize_limit);
+    // Aligning a backup would make reverting restore aligned content.
+    discovery.filter(|path| path.extension().is_none_or(|extension| extension != "orig"));
+    if !args.globs.is_empty() {
+        let globs = args.globs.clone();
+        discovery.filter(move |path| {
+            let relative_path = path.strip_prefix(&root).unwrap_or(path);
+            let relative_path = relative_path.to_string_lossy().replace('\\', "/");
+            globs.iter().any(|glob| glob::matches(glob, &relative_path))
+        });
+    }
+    if !args.member_directories.is_empty() {
+        let members = args.member_directories.clone();
+        // A file belongs to the package with the closest manifest above it,
+        // which for vendored and excluded crates isn't a workspace member.
+        discovery.filter(move |path| {
+            path.ancestors()
+                .skip(1)
+                .find(|directory| directory.join("Cargo.toml").is_file())
+                .is_none_or(|directory| members.iter().any(|member| member == directory))
+        });
+    }
+    if !args.extensions.is_empty() {
+        let extensions = args.extensions.clone();
+        discovery.filter(move |path| {
+            path.extension()
+                .is_some_and(|extension| extensions.iter().any(|e| extension == e.as_str()))
+        });
+    }
+    discovery
+}
 
-    dir_contents
-        .into_iter()
-        .flat_map(|d| get_files_recursively(d.path()))
-        .collect()
+/// The files under `path`, or the files passed with `--file` if there are any.
+/// The [`discovery`] of the files to align, which are the `--files` instead
+/// of the files under `path` if there are any.
+fn files_discovery(path: PathBuf, args: &Args) -> Discovery {
+    match args.files.split_first() {
+        None => discovery(path, args),
+        Some((first, rest)) => {
+            let mut discovery = discovery(first, args);
+            for file in rest {
+                discovery.add_root(file);
+            }
+            discovery
+        }
+    }
 }
 
-fn align_string(s: &str) -> String {
-    let mut lines = s.lines().peekable();
-    let mut aligned_file = Vec::new();
-    let mut stopped = false;
+fn discover_files(path: PathBuf, args: &Args) -> Vec<PathBuf> {
+    let file_set = files_discovery(path, args).build();
+    for warning in file_set.warnings {
+        eprintln!("{warning}");
+    }
+    file_set.files
+}
 
-    while let Some(line) = lines.next() {
-        aligned_file.push(line.to_string());
-        aligned_file.push("\n".to_string());
-        if line.contains(&["align_by", " stop"].concat()) {
-            stopped = true;
-        }
+fn read_file(path: &Path, encoding: Option<Encoding>) -> Result<(String, Encoding)> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read file at path {}", path.display()))?;
+    decode_file(path, &bytes, encoding)
+}
 
-        if stopped {
-            continue;
-        }
+/// The error reading a file that looks binary, which is skipped instead of
+/// counted as a failure.
+#[derive(Debug)]
+pub struct BinaryFile(PathBuf);
 
-        let Some(align_by_index) = line.find("align_by") else {
-            continue;
-        };
-        let align_by_index = align_by_index + 8;
-        let line = &line[align_by_index..];
-
-        let (alignment_statement, sort) = if line.starts_with(" sort \"") {
-            (
-                match line.get(7..) {
-                    Some(x) => x,
-                    None => continue,
-                },
-                true,
-            )
-        } else if line.starts_with(" \"") {
-            (
-                match line.get(2..) {
-                    Some(x) => x,
-                    None => continue,
-                },
-                false,
-            )
-        } else {
-            continue;
-        };
+impl std::fmt::Display for BinaryFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Skipped binary file at path {}", self.0.display())
+    }
+}
 
-        let alignment_parts = extract_quote(alignment_statement);
-        if alignment_parts.is_empty() {
-            continue;
-        }
-        let alignment_parts = alignment_parts
-            .split_ascii_whitespace()
-            .map(str::to_owned)
-            .collect::<Vec<_>>();
+impl std::error::Error for BinaryFile {}
 
-        let mut lines_to_be_modified = Vec::new();
+/// Decodes the `bytes` of the file at `path`, detecting the encoding if `None`.
+/// Binary files are a [`BinaryFile`] error.
+fn decode_file(
+    path: &Path,
+    bytes: &[u8],
+    encoding: Option<Encoding>,
+) -> Result<(String, Encoding)> {
+    if Encoding::is_binary(bytes, encoding) {
+        return Err(BinaryFile(path.to_path_buf()).into());
+    }
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => Encoding::detect(bytes)
+            .with_context(|| format!("File at path {} has an unknown encoding", path.display()))?,
+    };
+    let content = encoding.decode(bytes).with_context(|| {
+        format!(
+            "Failed to decode file at path {} as {encoding}",
+            path.display()
+        )
+    })?;
+    Ok((content, encoding))
+}
 
-        loop {
-            let Some(next_line) = lines.peek() else {
-                break;
-            };
-            if next_line.contains("align_by \"") || next_line.contains("align_by sort \""){
-                break;
-            }
+/// Whether the permissions of `path` forbid writing to it, even for users
+/// like root that could write to it anyway.
+fn is_read_only(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly())
+}
 
-            let Some(next_line) = lines.peek() else {
-                break;
+/// Writes `content` encoded as `encoding` to the file at `path`. A file that
+/// already exists is replaced through a temporary file next to it, so it is
+/// never left half written, and keeps its permissions.
+fn write_file(path: &Path, content: &str, encoding: Encoding) -> Result<()> {
+    encoding
+        .encode(content)
+        .and_then(|bytes| {
+            // Symlinks stay symlinks, it's the file they point to that is replaced.
+            let Ok(path) = std::fs::canonicalize(path) else {
+                return Ok(std::fs::write(path, bytes)?);
             };
-
-            if let Some(broken_str) = seperate_str_on_alignments(
-                next_line
-                    .split_ascii_whitespace()
-                    .flat_map(|x| [x, " "])
-                    .collect::<String>()
-                    .trim_end()
-                    .to_string(),
-                &alignment_parts,
-            ) {
-                lines.next();
-                lines_to_be_modified.push(broken_str);
-            } else {
-                break;
+            let mut file_name = std::ffi::OsString::from(".");
+            file_name.push(path.file_name().unwrap_or_default());
+            file_name.push(".cargo-align");
+            let temporary_path = path.with_file_name(file_name);
+            let replaced = std::fs::metadata(&path).and_then(|metadata| {
+                std::fs::write(&temporary_path, bytes)?;
+                std::fs::set_permissions(&temporary_path, metadata.permissions())?;
+                std::fs::rename(&temporary_path, &path)
+            });
+            if replaced.is_err() {
+                let _ = std::fs::remove_file(&temporary_path);
             }
-        }
-
-        if lines_to_be_modified.is_empty() {
-            continue;
-        }
+            Ok(replaced?)
+        })
+        .with_context(|| format!("Failed to write content to file at path {}", path.display()))
+}
 
-        let transposed_unmodified_lines = (0..lines_to_be_modified[0].len())
-            .map(|col| {
-                (0..lines_to_be_modified.len())
-                    .map(|row| lines_to_be_modified[row][col].clone())
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        let mut modified_columns = Vec::new();
-        for unaligned_line in
-            transposed_unmodified_lines[..transposed_unmodified_lines.len() - 2].iter()
-        {
-            let column_max_len = unaligned_line.iter().map(String::len).max().unwrap();
-            let adjustment_line = unaligned_line
-                .iter()
-                .map(|s| " ".repeat(column_max_len - s.len()))
-                .collect::<Vec<_>>();
-            modified_columns.push(unaligned_line.clone());
-            modified_columns.push(adjustment_line);
-        }
-        modified_columns
-            .push(transposed_unmodified_lines[transposed_unmodified_lines.len() - 2].clone());
-        modified_columns
-            .push(transposed_unmodified_lines[transposed_unmodified_lines.len() - 1].clone());
-
-        let mut modified_lines = (0..modified_columns[0].len())
-            .map(|col| {
-                (0..modified_columns.len())
-                    .map(|row| modified_columns[row][col].clone())
-                    .collect::<Vec<_>>()
-                    .concat()
-            })
-            .collect::<Vec<_>>();
+/// Sets the modification time of the file at `path`, for `--preserve-mtime`.
+fn set_modified(path: &Path, modified: std::time::SystemTime) -> Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(modified))
+        .with_context(|| {
+            format!(
+                "Failed to restore the modification time of file at path {}",
+                path.display()
+            )
+        })
+}
 
-        if sort {
-            modified_lines.sort();
-        }
+/// Checks if `path` or any of its ancestors contains a version control directory,
+/// mirroring the check `cargo fix` does before rewriting files.
+fn is_under_version_control(path: &Path) -> bool {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    path.ancestors().any(|dir| {
+        [".git", ".hg", ".pijul", ".fossil", ".jj", ".svn"]
+            .iter()
+            .any(|vcs_dir| dir.join(vcs_dir).exists())
+    })
+}
 
-        aligned_file.push(modified_lines.concat());
+fn confirm_no_vcs(path: &Path) -> Result<bool> {
+    eprintln!(
+        "Warning: {} is not under version control, aligning may modify files in ways that are hard to undo.",
+        path.display()
+    );
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Refusing to align files outside of version control without confirmation, pass `--allow-no-vcs` to align anyway.");
     }
+    eprint!("Continue anyway? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("Failed to read confirmation from stdin.")?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
 
-    [aligned_file.concat().trim_end(), "\n"].concat()
+/// A file that was read and aligned.
+struct ProcessedFile {
+    content: String,
+    encoding: Encoding,
+    aligned: Result<(String, AlignReport), AlignmentError>,
+    /// How long aligning took.
+    elapsed: std::time::Duration,
 }
 
-fn extract_quote(s: &str) -> String {
-    s.chars().scan(false, |escaped, x| {
-        if x == '\\' {
-            *escaped = true;
-            Some(x)
-        } else if x == '"' && escaped.not() {
-            None
-        } else {
-            *escaped = false;
-            Some(x)
-        }
-}).collect()
+/// An error of `--error-format json`.
+#[derive(serde::Serialize)]
+struct JsonError<'a> {
+    file: String,
+    #[serde(flatten)]
+    error: &'a AlignmentError,
 }
 
-fn seperate_str_on_alignments(s: String, alignment_parts: &[String]) -> Option<Vec<String>> {
-    if alignment_parts.is_empty() {
-        return Some(vec![s, "\n".to_string()]);
+impl ErrorFormat {
+    /// `err` in `file_path`, printed in this format.
+    fn render(self, err: &AlignmentError, file_path: &Path) -> String {
+        let file = file_path.display();
+        match self {
+            ErrorFormat::Human => format!("{file}:{err}"),
+            ErrorFormat::Short => format!(
+                "{file}:{}:{}: {}: {}",
+                err.line(),
+                err.column(),
+                err.code(),
+                err.message()
+            ),
+            ErrorFormat::Json => serde_json::to_string(&JsonError {
+                file: file.to_string(),
+                error: err,
+            })
+            .expect("errors only hold strings and numbers"),
+        }
     }
-
-    let (x, y) = s.split_once(alignment_parts.first()?)?;
-    Some(
-        [
-            vec![x.to_string(), alignment_parts.first()?.clone()],
-            seperate_str_on_alignments(y.to_string(), &alignment_parts[1..])?,
-        ]
-        .concat(),
-    )
 }
 
 
 #[cfg(test)]
-#[rustfmt::skip] // align_by stop
+#[rustfmt::skip]
 mod tests {
-    use indoc::indoc;
-
     use super::*;
 
     #[test]
-    fn quote_gathering() {
-        assert_eq!(extract_quote(""), "");
-        assert_eq!(extract_quote("\\"), "\\");
-        assert_eq!(extract_quote("\""), "");
-        assert_eq!(extract_quote("\\\""), "\\\"");
-        assert_eq!(extract_quote("=\""), "=");
-        assert_eq!(extract_quote("hello \\\"world\\\"!\""),"hello \\\"world\\\"!");
+    fn sampling() {
+        let paths = (0..1000).map(|i| PathBuf::from(format!("src/file_{i}.rs"))).collect::<Vec<_>>();
+        let sampled = |percent, seed| paths.iter().filter(|path| is_sampled(path, percent, seed)).count();
+        assert_eq!(sampled(0.0, 0), 0);
+        assert_eq!(sampled(100.0, 0), 1000);
+        assert!((50..150).contains(&sampled(10.0, 0)));
+        assert!(paths.iter().filter(|path| is_sampled(path, 10.0, 0)).all(|path| is_sampled(path, 20.0, 0)));
+        assert_ne!(
+            paths.iter().map(|path| is_sampled(path, 10.0, 0)).collect::<Vec<_>>(),
+            paths.iter().map(|path| is_sampled(path, 10.0, 1)).collect::<Vec<_>>()
+        );
+        assert_eq!(is_sampled(Path::new("a\\b.rs"), 50.0, 3), is_sampled(Path::new("a/b.rs"), 50.0, 3));
+    }
+
+    #[test]
+    fn rendering_errors() {
+        let err = AlignmentError::UnknownKeyword { line: 2, column: 15, word: "sotr".to_string() };
+        assert_eq!(ErrorFormat::Human.render(&err, Path::new("a.rs")), "a.rs:2:15: error[A0016]: unknown keyword `sotr` (did you mean `sort`?), run `cargo align --explain A0016` for the list of keywords");
+        assert_eq!(ErrorFormat::Short.render(&err, Path::new("a.rs")), "a.rs:2:15: A0016: unknown keyword `sotr` (did you mean `sort`?)");
+        assert_eq!(ErrorFormat::Json.render(&err, Path::new("a.rs")), r#"{"file":"a.rs","line":2,"column":15,"code":"A0016","message":"unknown keyword `sotr` (did you mean `sort`?)"}"#);
+    }
+
+    #[test]
+    fn skipping_binary_files() {
+        let err = decode_file(Path::new("a.png"), b"\x89PNG\r\n\x1a\n\0\0", None).unwrap_err();
+        assert!(err.is::<BinaryFile>());
+        assert_eq!(err.to_string(), "Skipped binary file at path a.png");
+        assert!(decode_file(Path::new("a.rs"), b"a = 1\0", Some(Encoding::Utf8)).unwrap_err().is::<BinaryFile>());
+        assert_eq!(decode_file(Path::new("a.rs"), b"a\0=\0", None).unwrap().0, "a=");
+        let err = decode_file(Path::new("a.rs"), b"\xe9", Some(Encoding::Utf8)).unwrap_err();
+        assert!(!err.is::<BinaryFile>());
     }
 
     #[test]
-    fn aligning() {
-        assert_eq!(align_string(""), "\n");
-        assert_eq!(align_string("\n"), "\n");
-        assert_eq!(align_string(indoc! {r#"
-            align_by "="aa
-        "#}), indoc! {r#"
-            align_by "="aa
-        "#});
-
-        assert_eq!(align_string(indoc! {r#"
-            align_by "="
-            1 = 222
-            111 = 2
-        "#}), indoc! {r#"
-            align_by "="
-            1   = 222
-            111 = 2
-        "#});
-
-        assert_eq!(align_string(indoc! {r#"
-            align_by "="
-            1      = 222
-            111 =     2
-        "#}), indoc! {r#"
-            align_by "="
-            1   = 222
-            111 = 2
-        "#});
-
-        assert_eq!(align_string(indoc! {r#"
-            align_by "= ;"
-            1 = 222;
-            111 = 2;
-        "#}), indoc! {r#"
-            align_by "= ;"
-            1   = 222;
-            111 = 2  ;
-        "#});
-
-        assert_eq!(align_string(indoc! {r#"
-            align_by "="
-            align_by "="
-            1=1
-        "#}), indoc! {r#"
-            align_by "="
-            align_by "="
-            1=1
-        "#});
-
-        assert_eq!(align_string(indoc! {r#"
-            align_by "="
-            1=1
-            22=2
-        "#}), indoc! {r#"
-            align_by "="
-            1 =1
-            22=2
-        "#});
+    fn detecting_read_only_files() {
+        let path = std::env::temp_dir().join(format!("cargo-align-readonly-{}", std::process::id()));
+        std::fs::write(&path, "a = 1\n").unwrap();
+        assert!(!is_read_only(&path));
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions.clone()).unwrap();
+        assert!(is_read_only(&path));
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&path, permissions).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!is_read_only(&path));
     }
 
     #[test]
-    fn sorting() {
-        assert_eq!(align_string(indoc! {r#"
-            align_by sort "="
-            2=2
-            1=1
-        "#}), indoc! {r#"
-            align_by sort "="
-            1=1
-            2=2
-        "#});
-
-        assert_eq!(align_string(indoc! {r#"
-            align_by sort "="
-            22=2
-            1=1
-        "#}), indoc! {r#"
-            align_by sort "="
-            1 =1
-            22=2
-        "#});
-        
-        assert_eq!(align_string(indoc! {r#"
-            align_by sort "="
-            align_by sort "="
-            22=2
-            1=1
-        "#}), indoc! {r#"
-            align_by sort "="
-            align_by sort "="
-            1 =1
-            22=2
-        "#});
+    fn preserving_modification_times() {
+        let path = std::env::temp_dir().join(format!("cargo-align-mtime-{}", std::process::id()));
+        std::fs::write(&path, "a = 1\n").unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        set_modified(&path, modified).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), modified);
+        std::fs::remove_file(&path).unwrap();
+        assert!(set_modified(&path, modified).is_err());
     }
 
     #[test]
-    fn stop() {
-        assert_eq!(align_string(indoc! {r#"
-            1
-            2
-            3
-            4
-            5
-            align_by stop
-        "#}), indoc! {r#"
-            1
-            2
-            3
-            4
-            5
-            align_by stop
-        "#});
+    fn reverting_backups() {
+        let dir = std::env::temp_dir().join(format!("cargo-align-revert-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.rs"), dir.join("b.rs"));
+        let original = "// align_by \"=\"\na = 1\nbbb = 2\n";
+        std::fs::write(&a, original).unwrap();
+        std::fs::write(&b, "b = 1\n").unwrap();
+        // Left behind by a merge tool, not by `--backup`.
+        std::fs::write(dir.join("b.rs.orig"), "b = 0\n").unwrap();
+        let manifest = dir.join("target/cargo-align-backups");
+
+        let mut backups = backups::Backups::load(manifest.clone());
+        backups.back_up(&a).unwrap();
+        backups.save().unwrap();
+        let aligned = cargo_align::align::AlignOptions::new().align_string(original).unwrap();
+        write_file(&a, &aligned, Encoding::Utf8).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("a.rs.orig")).unwrap(), original);
+
+        let mut backups = backups::Backups::load(manifest.clone());
+        assert_eq!(restore_backups(&mut backups, &[a.clone(), b.clone()]), (1, 0));
+        backups.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), original);
+        assert!(!dir.join("a.rs.orig").exists());
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "b = 1\n");
+        assert_eq!(std::fs::read_to_string(dir.join("b.rs.orig")).unwrap(), "b = 0\n");
+        assert!(!manifest.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replacing_files() {
+        let dir = std::env::temp_dir().join(format!("cargo-align-replace-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.rs");
+        write_file(&path, "a = 1\n", Encoding::Utf8).unwrap();
+        write_file(&path, "é = 1\n", Encoding::Utf16Le).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"\xe9\0 \0=\0 \x001\0\n\0");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let link = dir.join("b.rs");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            write_file(&link, "a = 2\n", Encoding::Utf8).unwrap();
+            assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "a = 2\n");
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+            std::fs::remove_file(&link).unwrap();
+        }
+        // No temporary files are left behind.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
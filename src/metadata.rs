@@ -0,0 +1,163 @@
+//! The packages of the workspace, read from `cargo metadata`.
+
+use crate::json;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub struct Package {
+    pub name: String,
+    pub manifest_path: PathBuf,
+    /// The Rust edition, like `2021`.
+    pub edition: String,
+}
+
+impl Package {
+    /// The directory with the manifest, and usually the sources, of the package.
+    pub fn directory(&self) -> &Path {
+        self.manifest_path.parent().unwrap_or(&self.manifest_path)
+    }
+}
+
+pub struct Metadata {
+    pub workspace_root: PathBuf,
+    /// The members of the workspace.
+    pub packages: Vec<Package>,
+}
+
+impl Metadata {
+    pub fn fetch() -> Result<Metadata> {
+        let output = std::process::Command::new("cargo")
+            .args(["metadata", "--no-deps", "--format-version", "1"])
+            .output()
+            .context("Failed to run `cargo metadata`.")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "`cargo metadata` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let metadata = std::str::from_utf8(&output.stdout)
+            .context("`cargo metadata` produced invalid utf-8.")?;
+        Metadata::parse(metadata)
+    }
+
+    fn parse(metadata: &str) -> Result<Metadata> {
+        let metadata = json::parse(metadata).context("Failed to parse `cargo metadata`.")?;
+        let workspace_root = metadata
+            .get("workspace_root")
+            .and_then(json::Json::as_str)
+            .context("Failed to find `workspace_root` in the output of `cargo metadata`.")?;
+        let packages = metadata
+            .get("packages")
+            .map(json::Json::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|package| {
+                Some(Package {
+                    name: package.get("name")?.as_str()?.to_string(),
+                    manifest_path: PathBuf::from(package.get("manifest_path")?.as_str()?),
+                    // What Cargo assumes for manifests without one.
+                    edition: package
+                        .get("edition")
+                        .and_then(json::Json::as_str)
+                        .unwrap_or("2015")
+                        .to_string(),
+                })
+            })
+            .collect();
+        Ok(Metadata {
+            workspace_root: PathBuf::from(workspace_root),
+            packages,
+        })
+    }
+
+    /// The directories of every member and of the workspace root.
+    pub fn member_directories(&self) -> Vec<PathBuf> {
+        let mut directories = vec![self.workspace_root.clone()];
+        directories.extend(
+            self.packages
+                .iter()
+                .map(|package| package.directory().to_path_buf()),
+        );
+        directories
+    }
+
+    /// The directory and edition of every member.
+    pub fn package_editions(&self) -> Vec<(PathBuf, String)> {
+        self.packages
+            .iter()
+            .map(|package| (package.directory().to_path_buf(), package.edition.clone()))
+            .collect()
+    }
+
+    /// The directories of the packages called `names`.
+    pub fn package_directories(&self, names: &[String]) -> Result<Vec<PathBuf>> {
+        let mut directories = Vec::new();
+        for name in names {
+            let Some(package) = self.packages.iter().find(|package| &package.name == name) else {
+                let members = self
+                    .packages
+                    .iter()
+                    .map(|package| format!("`{}`", package.name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                anyhow::bail!(
+                    "`{name}` is not a member of the workspace, expected one of {members}"
+                );
+            };
+            directories.push(package.directory().to_path_buf());
+        }
+        Ok(directories)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selecting_packages() {
+        let metadata = Metadata::parse(r#"{
+            "packages": [
+                {"name": "core", "dependencies": [{"name": "anyhow"}], "manifest_path": "/ws/crates/core/Cargo.toml", "edition": "2024"},
+                {"name": "cli", "manifest_path": "/ws/Cargo.toml"}
+            ],
+            "workspace_root": "/ws"
+        }"#).unwrap();
+        assert_eq!(metadata.workspace_root, PathBuf::from("/ws"));
+        assert_eq!(
+            metadata.member_directories(),
+            [
+                PathBuf::from("/ws"),
+                PathBuf::from("/ws/crates/core"),
+                PathBuf::from("/ws")
+            ]
+        );
+        assert_eq!(
+            metadata
+                .package_directories(&["core".to_string(), "cli".to_string()])
+                .unwrap(),
+            [PathBuf::from("/ws/crates/core"), PathBuf::from("/ws")]
+        );
+        assert_eq!(
+            metadata.package_editions(),
+            [
+                (PathBuf::from("/ws/crates/core"), "2024".to_string()),
+                (PathBuf::from("/ws"), "2015".to_string())
+            ]
+        );
+        let err = metadata
+            .package_directories(&["anyhow".to_string()])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`anyhow` is not a member of the workspace, expected one of `core`, `cli`"
+        );
+        assert!(Metadata::parse("{}").is_err());
+        // Windows roots have their backslashes escaped.
+        let metadata = Metadata::parse(r#"{"workspace_root": "C:\\Users\\ws"}"#).unwrap();
+        assert_eq!(metadata.workspace_root, PathBuf::from(r"C:\Users\ws"));
+    }
+}
@@ -0,0 +1,101 @@
+//! Work spread over several threads with the results kept in order.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::Scope;
+
+/// The number of threads used when `--jobs` isn't given, one per CPU.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+/// The results of [`map_ordered`], in the order of its items.
+pub struct Ordered<R> {
+    receiver: mpsc::Receiver<(usize, R)>,
+    /// Results that finished before the ones in front of them.
+    pending: BTreeMap<usize, R>,
+    next: usize,
+}
+
+impl<R> Iterator for Ordered<R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        loop {
+            if let Some(result) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Some(result);
+            }
+            let (index, result) = self.receiver.recv().ok()?;
+            self.pending.insert(index, result);
+        }
+    }
+}
+
+/// Calls `f` on every item on up to `jobs` threads of `scope`. The results
+/// come out in the order of `items`, each as soon as it and the ones before it
+/// are done, so they can be used while later items are still being worked on.
+pub fn map_ordered<'scope, 'env, T, R, F>(
+    scope: &'scope Scope<'scope, 'env>,
+    items: &'env [T],
+    jobs: usize,
+    f: &'env F,
+) -> Ordered<R>
+where
+    T: Sync,
+    R: Send + 'scope,
+    F: Fn(&T) -> R + Sync,
+{
+    let (sender, receiver) = mpsc::channel();
+    let next_item = Arc::new(AtomicUsize::new(0));
+    for _ in 0..jobs.clamp(1, items.len().max(1)) {
+        let sender = sender.clone();
+        let next_item = Arc::clone(&next_item);
+        scope.spawn(move || loop {
+            let index = next_item.fetch_add(1, Ordering::Relaxed);
+            let Some(item) = items.get(index) else {
+                break;
+            };
+            // The results stopped being read, there is no point in going on.
+            if sender.send((index, f(item))).is_err() {
+                break;
+            }
+        });
+    }
+    Ordered {
+        receiver,
+        pending: BTreeMap::new(),
+        next: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordering() {
+        let items = (0..100).collect::<Vec<u64>>();
+        // Later items finish first, so they have to wait for the earlier ones.
+        let slow_start = |&item: &u64| {
+            std::thread::sleep(std::time::Duration::from_micros(100 - item));
+            item * 2
+        };
+        for jobs in [1, 4, 200] {
+            let results = std::thread::scope(|scope| {
+                map_ordered(scope, &items, jobs, &slow_start).collect::<Vec<_>>()
+            });
+            assert_eq!(
+                results,
+                items.iter().map(|item| item * 2).collect::<Vec<_>>()
+            );
+        }
+        let empty: [u64; 0] = [];
+        let results =
+            std::thread::scope(|scope| map_ordered(scope, &empty, 4, &slow_start).count());
+        assert_eq!(results, 0);
+    }
+}
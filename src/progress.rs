@@ -0,0 +1,95 @@
+//! A progress bar on stderr for aligning many files.
+
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Redrawing more often than this only makes the terminal flicker.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+const BAR_WIDTH: usize = 30;
+
+pub struct Progress {
+    total: usize,
+    enabled: bool,
+    /// When the bar was last drawn, `None` while nothing is on screen.
+    drawn_at: Option<Instant>,
+}
+
+impl Progress {
+    /// A progress bar over `total` files that only draws when `enabled`.
+    pub fn new(total: usize, enabled: bool) -> Progress {
+        Progress {
+            total,
+            enabled,
+            drawn_at: None,
+        }
+    }
+
+    /// Shows that `scanned` files were processed, `aligned` of them changed.
+    pub fn update(&mut self, scanned: usize, aligned: usize) {
+        if !self.enabled
+            || self
+                .drawn_at
+                .is_some_and(|drawn_at| drawn_at.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        eprint!("\r\x1b[2K{}", render(scanned, self.total, aligned));
+        let _ = std::io::stderr().flush();
+        self.drawn_at = Some(Instant::now());
+    }
+
+    /// Removes the bar, so other output doesn't end up on the same line. The
+    /// next update draws it again.
+    pub fn clear(&mut self) {
+        if self.drawn_at.take().is_some() {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+/// `[=====>      ] 10/40 files, 3 aligned`
+fn render(scanned: usize, total: usize, aligned: usize) -> String {
+    let filled = (scanned * BAR_WIDTH)
+        .checked_div(total)
+        .unwrap_or(BAR_WIDTH);
+    let bar = match filled {
+        0 => " ".repeat(BAR_WIDTH),
+        BAR_WIDTH => "=".repeat(BAR_WIDTH),
+        _ => format!(
+            "{}>{}",
+            "=".repeat(filled - 1),
+            " ".repeat(BAR_WIDTH - filled)
+        ),
+    };
+    format!("[{bar}] {scanned}/{total} files, {aligned} aligned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendering() {
+        assert_eq!(
+            render(0, 3, 0),
+            format!("[{}] 0/3 files, 0 aligned", " ".repeat(30))
+        );
+        assert_eq!(
+            render(1, 3, 1),
+            format!(
+                "[{}>{}] 1/3 files, 1 aligned",
+                "=".repeat(9),
+                " ".repeat(20)
+            )
+        );
+        assert_eq!(
+            render(3, 3, 2),
+            format!("[{}] 3/3 files, 2 aligned", "=".repeat(30))
+        );
+        assert_eq!(
+            render(0, 0, 0),
+            format!("[{}] 0/0 files, 0 aligned", "=".repeat(30))
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Python bindings to the alignment engine, so pre-commit hooks and code
+//! generators can align text without running `cargo align`. Built with
+//! `maturin build`, which reads the features from `pyproject.toml`.
+
+use crate::align::AlignOptions;
+use crate::directive;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// An invalid alignment statement, raised by `align`.
+#[pyclass(extends = PyValueError, module = "cargo_align")]
+pub struct AlignmentError {
+    /// 1 based line number.
+    #[pyo3(get)]
+    line: usize,
+    /// 1 based column, in characters.
+    #[pyo3(get)]
+    column: usize,
+    /// The code `cargo align --explain` takes, like `A0001`.
+    #[pyo3(get)]
+    code: String,
+    /// What is wrong, without the location.
+    #[pyo3(get)]
+    message: String,
+}
+
+#[pymethods]
+impl AlignmentError {
+    fn __str__(&self) -> String {
+        format!(
+            "{}:{}: error[{}]: {}",
+            self.line, self.column, self.code, self.message
+        )
+    }
+}
+
+impl AlignmentError {
+    fn raise(py: Python<'_>, err: crate::align::AlignmentError) -> PyErr {
+        let err = AlignmentError {
+            line: err.line(),
+            column: err.column(),
+            code: err.code().to_string(),
+            message: err.message(),
+        };
+        match Py::new(py, err) {
+            Ok(err) => PyErr::from_value(err.into_bound(py).into_any()),
+            Err(err) => err,
+        }
+    }
+}
+
+/// Aligns `text` like `cargo align` aligns a file, with `marker` starting the
+/// alignment statements. `pad_to`, `gap`, `tab_width` and `unicode` are
+/// `--pad-to`, `--gap`, `--tab-width` and `--unicode`.
+#[pyfunction]
+#[pyo3(signature = (
+    text, *, marker = directive::DEFAULT_MARKER, pad_to = None, gap = None, tab_width = None, unicode = false
+))]
+fn align(
+    py: Python<'_>,
+    text: &str,
+    marker: &str,
+    pad_to: Option<usize>,
+    gap: Option<usize>,
+    tab_width: Option<usize>,
+    unicode: bool,
+) -> PyResult<String> {
+    let mut options = AlignOptions::new();
+    options
+        .marker(marker)
+        .pad_to(pad_to)
+        .gap(gap)
+        .tab_width(tab_width)
+        .unicode(unicode);
+    py.allow_threads(|| options.align_string(text))
+        .map_err(|err| AlignmentError::raise(py, err))
+}
+
+#[pymodule]
+fn cargo_align(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(align, m)?)?;
+    m.add_class::<AlignmentError>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn aligning_from_python() {
+        pyo3::append_to_inittab!(cargo_align);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = py.import("cargo_align").unwrap();
+            let aligned = module
+                .getattr("align")
+                .unwrap()
+                .call1(("# align_by \"=\"\na = 1\nbbb = 2\n",))
+                .unwrap();
+            assert_eq!(
+                aligned.extract::<String>().unwrap(),
+                "# align_by \"=\"\na   = 1\nbbb = 2\n"
+            );
+
+            let err = align(
+                py,
+                "// align_by sort\n",
+                "align_by",
+                None,
+                None,
+                None,
+                false,
+            )
+            .unwrap_err();
+            let value = err.value(py);
+            assert!(err.is_instance_of::<AlignmentError>(py));
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert_eq!(
+                value.getattr("line").unwrap().extract::<usize>().unwrap(),
+                1
+            );
+            assert_eq!(
+                value.getattr("column").unwrap().extract::<usize>().unwrap(),
+                17
+            );
+            assert_eq!(
+                value.getattr("code").unwrap().extract::<String>().unwrap(),
+                "A0001"
+            );
+            assert_eq!(
+                value.str().unwrap().to_string(),
+                format!("1:17: error[A0001]: {}", value.getattr("message").unwrap())
+            );
+
+            // Python code catches it as the class the module exports.
+            let locals = PyDict::new(py);
+            locals.set_item("cargo_align", &module).unwrap();
+            py.run(
+                c"try:
+    cargo_align.align('// align_by sort\\n')
+    caught = None
+except cargo_align.AlignmentError as err:
+    caught = err.code",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            assert_eq!(
+                locals
+                    .get_item("caught")
+                    .unwrap()
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                "A0001"
+            );
+        });
+    }
+}
@@ -0,0 +1,66 @@
+use crate::directive::comment_prefix;
+
+/// Replaces every `from` marker that is in a comment position with `to`.
+///
+/// A marker is only in a comment position when the text before it has a
+/// [`comment_prefix`]. Returns the new content and the number of markers
+/// replaced.
+pub fn rename_marker(s: &str, from: &str, to: &str) -> (String, usize) {
+    let mut renamed = 0;
+    let content = s
+        .split_inclusive('\n')
+        .map(|line| {
+            let mut new_line = String::new();
+            let mut rest = line;
+            while let Some(index) = rest.find(from) {
+                let before = &rest[..index];
+                let after = &rest[index + from.len()..];
+                new_line.push_str(before);
+                let ends_word = !after.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+                if ends_word && comment_prefix(&new_line).is_some() {
+                    new_line.push_str(to);
+                    renamed += 1;
+                } else {
+                    new_line.push_str(from);
+                }
+                rest = after;
+            }
+            new_line.push_str(rest);
+            new_line
+        })
+        .collect();
+    (content, renamed)
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn renaming() {
+        assert_eq!(rename_marker(indoc! {r#"
+            // align_by "="
+            let align_by = 1;
+            # align_by sort "="
+            <!-- align_by "|" -->
+             * align_by "="
+            -- align_by stop
+            ;align_by "="
+            // align_by_other "="
+        "#}, "align_by", "@align"), (indoc! {r#"
+            // @align "="
+            let align_by = 1;
+            # @align sort "="
+            <!-- @align "|" -->
+             * @align "="
+            -- @align stop
+            ;@align "="
+            // align_by_other "="
+        "#}.to_string(), 6));
+
+        assert_eq!(rename_marker("a\r\n// align_by \"=\"\r\n", "align_by", "x"), ("a\r\n// x \"=\"\r\n".to_string(), 1));
+    }
+}
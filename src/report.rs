@@ -0,0 +1,227 @@
+//! What happened to each file of a run, written out by `--report` for build
+//! systems to archive.
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::ser::SerializeMap;
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+use serde::Serializer;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The file couldn't be read or written.
+    Failed(String),
+    /// The file has malformed alignment statements.
+    Invalid(String),
+    Unchanged,
+    /// The file would change but was left out of the `--sample`.
+    NotSampled,
+    /// The file was aligned, or would be with `--diff`.
+    Aligned,
+    /// The file looks binary and was skipped.
+    Binary,
+    /// The file needs aligning but is read-only and was skipped.
+    ReadOnly,
+}
+
+impl Outcome {
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Failed(_) => "failed",
+            Outcome::Invalid(_) => "invalid",
+            Outcome::Unchanged => "unchanged",
+            Outcome::NotSampled => "not_sampled",
+            Outcome::Aligned => "aligned",
+            Outcome::Binary => "binary",
+            Outcome::ReadOnly => "readonly",
+        }
+    }
+
+    fn message(&self) -> Option<&str> {
+        match self {
+            Outcome::Failed(message) | Outcome::Invalid(message) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+const OUTCOME_NAMES: [&str; 7] = [
+    "failed",
+    "invalid",
+    "unchanged",
+    "not_sampled",
+    "aligned",
+    "binary",
+    "readonly",
+];
+
+#[derive(Default)]
+pub struct Report {
+    pub files: Vec<(PathBuf, Outcome)>,
+}
+
+impl Report {
+    pub fn record(&mut self, path: &Path, outcome: Outcome) {
+        self.files.push((path.to_path_buf(), outcome));
+    }
+
+    fn count(&self, name: &str) -> usize {
+        self.files
+            .iter()
+            .filter(|(_, outcome)| outcome.name() == name)
+            .count()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports only hold strings and numbers") + "\n"
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::from("[summary]\n");
+        for name in OUTCOME_NAMES {
+            toml.push_str(&format!("{name} = {}\n", self.count(name)));
+        }
+        for (path, outcome) in &self.files {
+            toml.push_str(&format!(
+                "\n[[files]]\npath = {}\nstatus = \"{}\"\n",
+                toml_string(&path.display().to_string()),
+                outcome.name()
+            ));
+            if let Some(message) = outcome.message() {
+                toml.push_str(&format!("message = {}\n", toml_string(message)));
+            }
+        }
+        toml
+    }
+
+    /// Writes the report to `path`, as TOML if it ends in `.toml` and JSON otherwise.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let is_toml = path
+            .extension()
+            .is_some_and(|extension| extension == "toml");
+        let content = if is_toml {
+            self.to_toml()
+        } else {
+            self.to_json()
+        };
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write report to {}", path.display()))
+    }
+}
+
+/// A TOML basic string, which has the same escapes as a JSON string.
+fn toml_string(s: &str) -> String {
+    serde_json::to_string(s).expect("strings always serialize")
+}
+
+/// The JSON report, a `summary` with the count of every outcome and the
+/// `files` with their `path`, `status` and, for failures, `message`.
+impl Serialize for Report {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Summary<'a>(&'a Report);
+        impl Serialize for Summary<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut summary = serializer.serialize_map(Some(OUTCOME_NAMES.len()))?;
+                for name in OUTCOME_NAMES {
+                    summary.serialize_entry(name, &self.0.count(name))?;
+                }
+                summary.end()
+            }
+        }
+
+        struct File<'a>(&'a Path, &'a Outcome);
+        impl Serialize for File<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let File(path, outcome) = self;
+                let mut file = serializer.serialize_struct("File", 3)?;
+                file.serialize_field("path", &path.display().to_string())?;
+                file.serialize_field("status", outcome.name())?;
+                match outcome.message() {
+                    Some(message) => file.serialize_field("message", message)?,
+                    None => file.skip_field("message")?,
+                }
+                file.end()
+            }
+        }
+
+        let files = self
+            .files
+            .iter()
+            .map(|(path, outcome)| File(path, outcome))
+            .collect::<Vec<_>>();
+        let mut report = serializer.serialize_struct("Report", 2)?;
+        report.serialize_field("summary", &Summary(self))?;
+        report.serialize_field("files", &files)?;
+        report.end()
+    }
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        let mut report = Report::default();
+        report.record(Path::new("src/a.rs"), Outcome::Aligned);
+        report.record(Path::new("src/b.rs"), Outcome::Invalid("1:9: error[A0001]: \"x\"".to_string()));
+        report.record(Path::new("src/c.rs"), Outcome::Unchanged);
+
+        assert_eq!(report.to_json(), indoc::indoc! {r#"
+            {
+              "summary": {
+                "failed": 0,
+                "invalid": 1,
+                "unchanged": 1,
+                "not_sampled": 0,
+                "aligned": 1,
+                "binary": 0,
+                "readonly": 0
+              },
+              "files": [
+                {
+                  "path": "src/a.rs",
+                  "status": "aligned"
+                },
+                {
+                  "path": "src/b.rs",
+                  "status": "invalid",
+                  "message": "1:9: error[A0001]: \"x\""
+                },
+                {
+                  "path": "src/c.rs",
+                  "status": "unchanged"
+                }
+              ]
+            }
+        "#});
+        assert_eq!(report.to_toml(), indoc::indoc! {r#"
+            [summary]
+            failed = 0
+            invalid = 1
+            unchanged = 1
+            not_sampled = 0
+            aligned = 1
+            binary = 0
+            readonly = 0
+
+            [[files]]
+            path = "src/a.rs"
+            status = "aligned"
+
+            [[files]]
+            path = "src/b.rs"
+            status = "invalid"
+            message = "1:9: error[A0001]: \"x\""
+
+            [[files]]
+            path = "src/c.rs"
+            status = "unchanged"
+        "#});
+        assert!(Report::default().to_json().ends_with("\"files\": []\n}\n"));
+    }
+}
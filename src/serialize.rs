@@ -0,0 +1,130 @@
+//! `Serialize` for the errors and reports of the engine, which the JSON output
+//! of `cargo align` is built from too, so tools get the same shapes. An
+//! alignment statement is an object like the ones of `cargo align list
+//! --json`, without the file.
+
+use crate::align::AlignReport;
+use crate::align::AlignmentError;
+use crate::align::Block;
+use crate::align::Edit;
+use crate::align::Warning;
+use crate::directive::Directive;
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+use serde::Serializer;
+
+impl Serialize for AlignmentError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("AlignmentError", 4)?;
+        error.serialize_field("line", &self.line())?;
+        error.serialize_field("column", &self.column())?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.message())?;
+        error.end()
+    }
+}
+
+impl Serialize for Warning {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut warning = serializer.serialize_struct("Warning", 2)?;
+        warning.serialize_field("line", &self.line)?;
+        warning.serialize_field("message", &self.message)?;
+        warning.end()
+    }
+}
+
+/// The `mode` and `delimiters` of an alignment statement.
+impl Serialize for Directive {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let delimiters = self
+            .delimiters
+            .iter()
+            .map(|delimiter| Delimiter {
+                text: &delimiter.text,
+                side: delimiter.alignment.name(),
+            })
+            .collect::<Vec<_>>();
+        let mut directive = serializer.serialize_struct("Directive", 2)?;
+        directive.serialize_field("mode", self.mode())?;
+        directive.serialize_field("delimiters", &delimiters)?;
+        directive.end()
+    }
+}
+
+/// An alignment statement with its 1 based line.
+#[derive(Serialize)]
+struct Statement<'a> {
+    line: usize,
+    #[serde(flatten)]
+    directive: &'a Directive,
+}
+
+#[derive(Serialize)]
+struct Delimiter<'a> {
+    text: &'a str,
+    side: &'static str,
+}
+
+impl Serialize for Block {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut block = serializer.serialize_struct("Block", 4)?;
+        block.serialize_field("statement_line", &self.statement_line)?;
+        block.serialize_field("first_line", self.lines.start())?;
+        block.serialize_field("last_line", self.lines.end())?;
+        block.serialize_field("changed", &self.changed)?;
+        block.end()
+    }
+}
+
+impl Serialize for Edit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut edit = serializer.serialize_struct("Edit", 2)?;
+        edit.serialize_field("original", &[self.original.start, self.original.end])?;
+        edit.serialize_field("aligned", &[self.aligned.start, self.aligned.end])?;
+        edit.end()
+    }
+}
+
+impl Serialize for AlignReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let statements = self
+            .statements
+            .iter()
+            .map(|(line, directive)| Statement {
+                line: *line,
+                directive,
+            })
+            .collect::<Vec<_>>();
+        let mut report = serializer.serialize_struct("AlignReport", 4)?;
+        report.serialize_field("statements", &statements)?;
+        report.serialize_field("blocks", &self.blocks)?;
+        report.serialize_field("edits", &self.edits)?;
+        report.serialize_field("warnings", &self.warnings)?;
+        report.end()
+    }
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use crate::align::AlignOptions;
+
+    #[test]
+    fn serializing() {
+        let (_, report) = AlignOptions::new().align_string_with_report("// align_by \"=\" \",\":right\na = 1, 2\nbbb = 3, 44\n").unwrap();
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            concat!(
+                r#"{"statements":[{"line":1,"mode":"normal","delimiters":[{"text":"=","side":"left"},{"text":",","side":"right"}]}],"#,
+                r#""blocks":[{"statement_line":1,"first_line":2,"last_line":3,"changed":true}],"#,
+                r#""edits":[{"original":[28,28],"aligned":[28,30]}],"#,
+                r#""warnings":[]}"#,
+            )
+        );
+        let err = AlignOptions::new().align_string("// align_by\"=\"\n").unwrap_err();
+        assert_eq!(
+            serde_json::to_string(&err).unwrap(),
+            r#"{"line":1,"column":12,"code":"A0002","message":"expected a space after the marker"}"#
+        );
+    }
+}
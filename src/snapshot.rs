@@ -0,0 +1,176 @@
+//! Golden file snapshots of aligned output.
+//!
+//! Recording writes the aligned content of every file under a root to the same
+//! relative path under an output directory, verifying compares the current
+//! aligned content against those files. Files with invalid alignment
+//! statements snapshot their error instead, so error behavior is covered too.
+
+use crate::args::Args;
+use crate::discovery;
+use crate::read_file;
+use crate::BinaryFile;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The content a snapshot of `file_content` of `file` is expected to have.
+fn snapshot_content(args: &Args, file: &Path, file_content: &str) -> String {
+    match args.align_options_for(file).align_string(file_content) {
+        Ok(aligned_content) => aligned_content,
+        Err(err) => format!("{err}\n"),
+    }
+}
+
+/// Where the snapshot of `file` lives, or `None` if `file` isn't under `root`.
+fn snapshot_path(root: &Path, out: &Path, file: &Path) -> Option<PathBuf> {
+    Some(out.join(file.strip_prefix(root).ok()?))
+}
+
+/// Records or verifies snapshots of every file under `root`, skipping the
+/// snapshot directory `out` itself.
+pub fn snapshot(args: &Args, root: &Path, out: &Path, verify_snapshots: bool) -> Result<()> {
+    let excluded = out.canonicalize().unwrap_or_else(|_| out.to_path_buf());
+    let file_set = discovery(root, args)
+        .filter(move |path| {
+            path.canonicalize()
+                .map_or(true, |path| !path.starts_with(&excluded))
+        })
+        .build();
+    for warning in file_set.warnings {
+        eprintln!("{warning}");
+    }
+    // Snapshots of a single file are named after the file.
+    let root = if root.is_file() {
+        root.parent().unwrap_or(root)
+    } else {
+        root
+    };
+    if verify_snapshots {
+        verify(args, root, out, &file_set.files)
+    } else {
+        record(args, root, out, &file_set.files)
+    }
+}
+
+fn record(args: &Args, root: &Path, out: &Path, files: &[PathBuf]) -> Result<()> {
+    let mut recorded = 0;
+    let mut failed_to_read = 0;
+    for file in files {
+        let Some(snapshot_path) = snapshot_path(root, out, file) else {
+            continue;
+        };
+        let file_content = match read_file(file, args.encoding) {
+            Err(err) if err.is::<BinaryFile>() => continue,
+            Err(err) => {
+                eprintln!("{err}");
+                failed_to_read += 1;
+                continue;
+            }
+            Ok((content, _)) => content,
+        };
+        if let Some(parent) = snapshot_path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create snapshot directory {}", parent.display())
+            })?;
+        }
+        std::fs::write(&snapshot_path, snapshot_content(args, file, &file_content))
+            .with_context(|| format!("Failed to write snapshot {}", snapshot_path.display()))?;
+        recorded += 1;
+    }
+    println!("Recorded {recorded} snapshots in {}.", out.display());
+    if failed_to_read > 0 {
+        anyhow::bail!("{failed_to_read} files failed to be read and have no snapshot");
+    }
+    Ok(())
+}
+
+fn verify(args: &Args, root: &Path, out: &Path, files: &[PathBuf]) -> Result<()> {
+    let mut mismatched = Vec::new();
+    let mut failed_to_read = 0;
+    for file in files {
+        let Some(snapshot_path) = snapshot_path(root, out, file) else {
+            continue;
+        };
+        let file_content = match read_file(file, args.encoding) {
+            Err(err) if err.is::<BinaryFile>() => continue,
+            Err(err) => {
+                eprintln!("{err}");
+                failed_to_read += 1;
+                continue;
+            }
+            Ok((content, _)) => content,
+        };
+        let current = snapshot_content(args, file, &file_content);
+        match std::fs::read_to_string(&snapshot_path) {
+            Err(_) => {
+                eprintln!(
+                    "Missing snapshot {} for {}",
+                    snapshot_path.display(),
+                    file.display()
+                );
+                mismatched.push(file);
+            }
+            Ok(recorded) if recorded != current => {
+                let path = file.display().to_string();
+                if let Some(diff) = crate::diff::render_diff(&path, &recorded, &current) {
+                    if args.color.stderr() {
+                        eprint!("{}", crate::color::diff(&diff));
+                    } else {
+                        eprint!("{diff}");
+                    }
+                }
+                mismatched.push(file);
+            }
+            Ok(_) => {}
+        }
+    }
+    if !mismatched.is_empty() || failed_to_read > 0 {
+        anyhow::bail!(
+            "{} of {} files differ from their snapshots in {}, {failed_to_read} failed to be read",
+            mismatched.len(),
+            files.len(),
+            out.display()
+        );
+    }
+    println!("All {} snapshots in {} match.", files.len(), out.display());
+    Ok(())
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_and_verifying() {
+        let dir = std::env::temp_dir().join(format!("cargo-align-snapshot-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let root = dir.join("src");
+        let out = dir.join("snapshots");
+        std::fs::create_dir_all(root.join("nested")).unwrap();
+        std::fs::write(root.join("a.rs"), "// align_by \"=\"\na = 1\nbbb = 2\n").unwrap();
+        std::fs::write(root.join("nested/b.rs"), "// align_by sort\n").unwrap();
+        let files = vec![root.join("a.rs"), root.join("nested/b.rs")];
+        let args = Args::parse(std::iter::empty()).unwrap();
+
+        record(&args, &root, &out, &files).unwrap();
+        assert_eq!(std::fs::read_to_string(out.join("a.rs")).unwrap(), "// align_by \"=\"\na   = 1\nbbb = 2\n");
+        assert!(std::fs::read_to_string(out.join("nested/b.rs")).unwrap().contains("A0001"));
+        verify(&args, &root, &out, &files).unwrap();
+
+        std::fs::write(root.join("a.rs"), "// align_by \"=\"\na = 1\nbbbb = 2\n").unwrap();
+        assert!(verify(&args, &root, &out, &files).is_err());
+
+        // A file that can't be read fails the run, but the others still get
+        // their snapshot.
+        std::fs::write(root.join("c.rs"), b"\xff\xfe\x00").unwrap();
+        let files = vec![root.join("c.rs"), root.join("a.rs")];
+        assert!(record(&args, &root, &out, &files).is_err());
+        assert_eq!(std::fs::read_to_string(out.join("a.rs")).unwrap(), "// align_by \"=\"\na    = 1\nbbbb = 2\n");
+        assert!(verify(&args, &root, &out, &files[1..]).is_ok());
+        assert!(verify(&args, &root, &out, &files).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,424 @@
+//! Counts of alignment blocks and how many of them are misaligned, for the
+//! working tree or for recent commits.
+
+use crate::args::has_literals;
+use crate::args::Args;
+use crate::diff;
+use crate::directive;
+use crate::discover_files;
+use crate::encoding::Encoding;
+use crate::files_discovery;
+use crate::read_file;
+use crate::BinaryFile;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Block counts of one or more files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Counts {
+    files: usize,
+    blocks: usize,
+    misaligned_blocks: usize,
+    misaligned_files: usize,
+    invalid_files: usize,
+}
+
+impl std::ops::AddAssign for Counts {
+    fn add_assign(&mut self, other: Counts) {
+        self.files += other.files;
+        self.blocks += other.blocks;
+        self.misaligned_blocks += other.misaligned_blocks;
+        self.misaligned_files += other.misaligned_files;
+        self.invalid_files += other.invalid_files;
+    }
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} blocks misaligned in {} of {} files, {} files have invalid alignment statements",
+            self.misaligned_blocks, self.blocks, self.misaligned_files, self.files, self.invalid_files
+        )
+    }
+}
+
+/// Counts the blocks of the file at `file_path` with `content`.
+///
+/// A block is misaligned when aligning changes any of its lines. Directive
+/// lines are never changed, so the lines from one directive up to the next
+/// are compared as a whole, which also catches blocks that lost lines to `dedup`.
+fn count_blocks(file_path: &Path, content: &str, args: &Args) -> Counts {
+    let mut counts = Counts {
+        files: 1,
+        ..Counts::default()
+    };
+    let aligned = match args.align_options_for(file_path).align_string(content) {
+        Err(_) => {
+            counts.invalid_files = 1;
+            return counts;
+        }
+        Ok(aligned) => aligned,
+    };
+    let original_blocks = blocks(content, &args.marker);
+    let aligned_blocks = blocks(&aligned, &args.marker);
+    counts.blocks = original_blocks.len();
+    counts.misaligned_blocks = original_blocks
+        .iter()
+        .zip(&aligned_blocks)
+        .filter(|(original, aligned)| original != aligned)
+        .count();
+    counts.misaligned_files = usize::from(counts.misaligned_blocks > 0);
+    counts
+}
+
+/// The lines between each directive and the next one, or the previous one for
+/// `above N`, ignoring everything after the stop marker.
+fn blocks<'a>(content: &'a str, marker: &str) -> Vec<Vec<&'a str>> {
+    // The lines between consecutive directives, and which of them each directive aligns.
+    let mut segments: Vec<Vec<&str>> = vec![Vec::new()];
+    let mut owned_segments = Vec::new();
+    for line in content.lines() {
+        if directive::is_stop(line, marker) {
+            break;
+        }
+        match directive::parse_directive(line, marker) {
+            // A `cancel_block` belongs to the block before it rather than starting one.
+            Ok(Some(directive)) if !directive.cancel_block => {
+                owned_segments.push(match directive.above {
+                    Some(_) => segments.len() - 1,
+                    None => segments.len(),
+                });
+                segments.push(Vec::new());
+            }
+            _ => segments.last_mut().unwrap().push(line),
+        }
+    }
+    owned_segments
+        .into_iter()
+        .map(|index| segments[index].clone())
+        .collect()
+}
+
+/// How many files `--stats` lists as the slowest.
+const SLOWEST_FILES: usize = 5;
+
+/// Metrics of an aligning run, for `--stats`.
+#[derive(Debug, Default)]
+pub struct RunStats {
+    directives: usize,
+    blocks_aligned: usize,
+    lines_touched: usize,
+    /// The change in size of every file together, negative when padding was removed.
+    bytes_changed: i64,
+    /// How long aligning took for each file.
+    file_times: Vec<(PathBuf, Duration)>,
+}
+
+impl RunStats {
+    /// Adds a file that was aligned from `content` into `aligned` in `elapsed`.
+    pub fn record(
+        &mut self,
+        path: &Path,
+        content: &str,
+        aligned: &str,
+        marker: &str,
+        elapsed: Duration,
+    ) {
+        let original_blocks = blocks(content, marker);
+        let aligned_blocks = blocks(aligned, marker);
+        self.directives += original_blocks.len();
+        self.blocks_aligned += original_blocks
+            .iter()
+            .zip(&aligned_blocks)
+            .filter(|(original, aligned)| original != aligned)
+            .count();
+        self.lines_touched += diff::changed_lines(content, aligned);
+        self.bytes_changed += aligned.len() as i64 - content.len() as i64;
+        self.file_times.push((path.to_path_buf(), elapsed));
+    }
+}
+
+impl std::fmt::Display for RunStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self
+            .file_times
+            .iter()
+            .map(|(_, time)| *time)
+            .sum::<Duration>();
+        writeln!(f, "{} directives processed", self.directives)?;
+        writeln!(f, "{} blocks aligned", self.blocks_aligned)?;
+        writeln!(f, "{} lines touched", self.lines_touched)?;
+        writeln!(f, "{:+} bytes changed", self.bytes_changed)?;
+        writeln!(
+            f,
+            "{} files processed in {total:.2?}, {:.2?} per file",
+            self.file_times.len(),
+            total / self.file_times.len().max(1) as u32
+        )?;
+        let mut slowest = self.file_times.iter().collect::<Vec<_>>();
+        slowest.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
+        if !slowest.is_empty() {
+            writeln!(f, "Slowest files:")?;
+        }
+        for (path, time) in slowest.into_iter().take(SLOWEST_FILES) {
+            writeln!(f, "  {time:.2?} {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the block counts of the files under `path`.
+pub fn stats(args: &Args, path: &Path) -> Result<()> {
+    let mut counts = Counts::default();
+    for file_path in discover_files(path.to_path_buf(), args) {
+        match read_file(&file_path, args.encoding) {
+            Err(err) if err.is::<BinaryFile>() => {}
+            Err(err) => eprintln!("{err}"),
+            Ok((content, _)) => counts += count_blocks(&file_path, &content, args),
+        }
+    }
+    println!("{counts}");
+    Ok(())
+}
+
+fn git(path: &Path, git_args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(git_args)
+        .output()
+        .context("Failed to run `git`.")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`git {}` failed: {}",
+            git_args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).context("`git` produced invalid utf-8.")
+}
+
+/// Prints the block counts of the files under `path` for each of the last
+/// `commits` commits, oldest first, along with the change from the commit before.
+pub fn history(args: &Args, path: &Path, commits: usize) -> Result<()> {
+    let mut previous: Option<Counts> = None;
+    for (short_hash, date, counts) in commit_counts(args, path, commits)? {
+        let trend = match previous {
+            Some(previous) => format!(
+                " ({:+})",
+                counts.misaligned_blocks as i64 - previous.misaligned_blocks as i64
+            ),
+            None => String::new(),
+        };
+        println!("{short_hash} {date}: {counts}{trend}");
+        previous = Some(counts);
+    }
+    Ok(())
+}
+
+/// The short hash, date and block counts of each of the last `commits`
+/// commits, oldest first.
+///
+/// Files are read from the commits themselves, but selected like [`stats`]
+/// selects them, with the ignore files and manifests of the working tree.
+/// Blobs that are the same in several commits are only counted once.
+fn commit_counts(
+    args: &Args,
+    path: &Path,
+    commits: usize,
+) -> Result<Vec<(String, String, Counts)>> {
+    let discovery = files_discovery(path.to_path_buf(), args);
+    let (directory, pathspec) = if path.is_file() {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        (
+            path.parent().unwrap_or(Path::new(".")),
+            file_name.to_string(),
+        )
+    } else {
+        (path, ".".to_string())
+    };
+    let log = git(
+        directory,
+        &[
+            "log",
+            "--format=%H %h %cs",
+            &format!("--max-count={commits}"),
+        ],
+    )?;
+    let mut cat_file = std::process::Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["cat-file", "--batch"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .context("Failed to run `git cat-file`.")?;
+    let mut cat_file_in = cat_file
+        .stdin
+        .take()
+        .context("`git cat-file` has no stdin")?;
+    let mut cat_file_out = std::io::BufReader::new(
+        cat_file
+            .stdout
+            .take()
+            .context("`git cat-file` has no stdout")?,
+    );
+    let mut blob_counts = HashMap::new();
+    let mut commit_counts = Vec::new();
+
+    for commit in log.lines().rev() {
+        let mut parts = commit.splitn(3, ' ');
+        let (Some(hash), Some(short_hash), Some(date)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let tree = git(directory, &["ls-tree", "-r", "-l", hash, "--", &pathspec])?;
+        let mut counts = Counts::default();
+        // `<mode> blob <object> <size>\t<path>`
+        for entry in tree.lines() {
+            let Some((info, path)) = entry.split_once('\t') else {
+                continue;
+            };
+            let info = info.split_ascii_whitespace().collect::<Vec<_>>();
+            let [_, "blob", object, size] = info[..] else {
+                continue;
+            };
+            if size.parse::<u64>().map_or(true, |size| {
+                args.filesize_limit.is_some_and(|limit| size > limit)
+            }) || !discovery.includes(&directory.join(path))
+            {
+                continue;
+            }
+            // The same blob can count differently under another extension.
+            let path = Path::new(path);
+            let key = (object.to_string(), has_literals(path));
+            if let Some(blob) = blob_counts.get(&key) {
+                counts += *blob;
+                continue;
+            }
+            writeln!(cat_file_in, "{object}")?;
+            cat_file_in.flush()?;
+            let mut header = String::new();
+            cat_file_out.read_line(&mut header)?;
+            let size = header
+                .split_ascii_whitespace()
+                .nth(2)
+                .and_then(|size| size.parse::<usize>().ok())
+                .with_context(|| format!("Unexpected `git cat-file` output `{}`", header.trim()))?;
+            // The content is followed by a newline.
+            let mut bytes = vec![0; size + 1];
+            cat_file_out.read_exact(&mut bytes)?;
+            bytes.pop();
+            let blob = args
+                .encoding
+                .or_else(|| Encoding::detect(&bytes))
+                .and_then(|encoding| encoding.decode(&bytes).ok())
+                .map_or_else(Counts::default, |content| {
+                    count_blocks(path, &content, args)
+                });
+            blob_counts.insert(key, blob);
+            counts += blob;
+        }
+        commit_counts.push((short_hash.to_string(), date.to_string(), counts));
+    }
+    drop(cat_file_in);
+    cat_file.wait()?;
+    Ok(commit_counts)
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::directive::DEFAULT_MARKER;
+
+    #[test]
+    fn counting() {
+        let args = Args::parse(std::iter::empty()).unwrap();
+        assert_eq!(count_blocks(Path::new("a.rs"), indoc! {r#"
+            // align_by "="
+            a = 1
+            bb = 2
+            // align_by "="
+            a = 1
+            b = 2
+            // align_by sort "="
+            b = 1
+            a = 2
+        "#}, &args), Counts { files: 1, blocks: 3, misaligned_blocks: 2, misaligned_files: 1, invalid_files: 0 });
+        assert_eq!(count_blocks(Path::new("a.rs"), indoc! {r#"
+            // align_by dedup "="
+            a = 1
+            a = 1
+            // align_by "="
+            a = 1
+        "#}, &args), Counts { files: 1, blocks: 2, misaligned_blocks: 1, misaligned_files: 1, invalid_files: 0 });
+        assert_eq!(count_blocks(Path::new("a.rs"), indoc! {r#"
+            // align_by "="
+            a = 1
+            bb = 2
+            // align_by cancel_block
+        "#}, &args), Counts { files: 1, blocks: 1, misaligned_blocks: 0, misaligned_files: 0, invalid_files: 0 });
+        assert_eq!(count_blocks(Path::new("a.rs"), indoc! {r#"
+            a = 1
+            bb = 2
+            // align_by above 2 "="
+        "#}, &args), Counts { files: 1, blocks: 1, misaligned_blocks: 1, misaligned_files: 1, invalid_files: 0 });
+        assert_eq!(count_blocks(Path::new("a.rs"), "// align_by sort\n", &args), Counts { files: 1, invalid_files: 1, ..Counts::default() });
+    }
+
+    #[test]
+    fn counting_history() {
+        let dir = std::env::temp_dir().join(format!("cargo-align-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let misaligned = "// align_by \"=\"\na = 1\nbb = 2\n";
+        std::fs::write(dir.join("a.rs"), misaligned).unwrap();
+        std::fs::write(dir.join("b.txt"), misaligned).unwrap();
+        // Committed, but ignored like vendored code often is.
+        std::fs::write(dir.join(".gitignore"), "/vendor.rs\n").unwrap();
+        std::fs::write(dir.join("vendor.rs"), misaligned).unwrap();
+        for git_args in [&["init", "-q"][..], &["add", "-f", "."], &["-c", "user.name=a", "-c", "user.email=a@a", "commit", "-q", "-m", "a"]] {
+            git(&dir, git_args).unwrap();
+        }
+
+        let args = Args::parse(["--ext".to_string(), "rs".to_string()].into_iter()).unwrap();
+        let counts = commit_counts(&args, &dir, 1).unwrap();
+        let expected = Counts { files: 1, blocks: 1, misaligned_blocks: 1, misaligned_files: 1, invalid_files: 0 };
+        assert_eq!(counts[0].2, expected);
+        // The same as counting the working tree.
+        let mut working_tree = Counts::default();
+        for file_path in discover_files(dir.clone(), &args) {
+            working_tree += count_blocks(&file_path, &std::fs::read_to_string(&file_path).unwrap(), &args);
+        }
+        assert_eq!(working_tree, expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn run_stats() {
+        let mut stats = RunStats::default();
+        stats.record(Path::new("a.rs"), "// align_by \"=\"\na = 1\nbb = 2\n", "// align_by \"=\"\na  = 1\nbb = 2\n", DEFAULT_MARKER, Duration::from_millis(3));
+        stats.record(Path::new("b.rs"), "// align_by \"=\"\na = 1\n", "// align_by \"=\"\na = 1\n", DEFAULT_MARKER, Duration::from_millis(1));
+        assert_eq!(stats.to_string(), indoc! {"
+            2 directives processed
+            1 blocks aligned
+            1 lines touched
+            +1 bytes changed
+            2 files processed in 4.00ms, 2.00ms per file
+            Slowest files:
+              3.00ms a.rs
+              1.00ms b.rs
+        "});
+    }
+}
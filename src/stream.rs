@@ -0,0 +1,496 @@
+//! Aligning input that arrives a piece at a time, like a large generated file
+//! read from a pipe, without holding all of it in memory.
+//!
+//! A block is final once a line arrives that can't be part of it, and lines
+//! outside of blocks are passed on as they arrive. `cancel_block` statements
+//! reach back into the block before them, so a block is only passed on with
+//! the line after it. `above N` statements reach back into the lines before
+//! them, which are only there if [`AlignStream::lookback`] holds them back.
+//! Named groups share widths across the whole input, so once a
+//! `group(<name>)` statement arrives everything after it is held until
+//! [`AlignStream::finish`].
+//!
+//! [`align_io`] and [`align_file`] drive a stream from a reader, writing the
+//! output through a buffer as blocks become final.
+
+use crate::align::end_with;
+use crate::align::split_line_ending;
+use crate::align::AlignOptions;
+use crate::align::AlignmentError;
+use crate::directive;
+use crate::directive::Directive;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+/// Aligns input pushed to it in pieces the same way
+/// [`AlignOptions::align_string`] aligns all of it at once.
+pub struct AlignStream {
+    options: AlignOptions,
+    /// The start of a line whose line ending hasn't been pushed yet.
+    partial: String,
+    /// Complete lines that could still change, with their line endings.
+    pending: String,
+    /// The number of lines before `pending`, which locate errors in the whole
+    /// input.
+    lines_before: usize,
+    pending_lines: usize,
+    /// The statement of the block at the end of `pending`, with its number of
+    /// lines so far, or `None` if no block is open.
+    block: Option<(Directive, usize)>,
+    /// The number of lines outside of blocks held back for `above N`
+    /// statements.
+    lookback: usize,
+    /// Whitespace at the end of the output so far, which is dropped if
+    /// nothing but whitespace follows it.
+    trailing_whitespace: String,
+    stopped: bool,
+    grouped: bool,
+    /// The number of lines ending in `\n` and `\r\n`.
+    line_endings: (usize, usize),
+    /// The line ending of the last line that isn't blank.
+    final_line_ending: &'static str,
+}
+
+impl AlignStream {
+    /// A stream that aligns with `options`.
+    pub fn new(options: &AlignOptions) -> AlignStream {
+        AlignStream {
+            options: options.clone(),
+            partial: String::new(),
+            pending: String::new(),
+            lines_before: 0,
+            pending_lines: 0,
+            block: None,
+            lookback: 0,
+            trailing_whitespace: String::new(),
+            stopped: false,
+            grouped: false,
+            line_endings: (0, 0),
+            final_line_ending: "",
+        }
+    }
+
+    /// Holds back the last `lines` lines outside of blocks, so `above N`
+    /// statements can align up to that many lines before them. Without it
+    /// those lines are passed on right away and `above N` statements align
+    /// nothing.
+    pub fn lookback(&mut self, lines: usize) -> &mut AlignStream {
+        self.lookback = lines;
+        self
+    }
+
+    /// Adds `chunk` to the input, returning the output that is final now.
+    /// Chunks don't have to end at line breaks.
+    pub fn push(&mut self, chunk: &str) -> Result<String, AlignmentError> {
+        let input = std::mem::take(&mut self.partial) + chunk;
+        let mut output = String::new();
+        let mut rest = input.as_str();
+        while let Some(end) = rest.find('\n') {
+            let (line, after) = rest.split_at(end + 1);
+            self.push_line(line, &mut output)?;
+            rest = after;
+        }
+        self.partial = rest.to_string();
+        Ok(output)
+    }
+
+    fn push_line(&mut self, line: &str, output: &mut String) -> Result<(), AlignmentError> {
+        let (content, line_ending) = split_line_ending(line);
+        let line_ending = if line_ending == "\r\n" {
+            self.line_endings.1 += 1;
+            "\r\n"
+        } else {
+            self.line_endings.0 += 1;
+            "\n"
+        };
+        if !content.trim().is_empty() {
+            self.final_line_ending = line_ending;
+        }
+        if self.stopped {
+            self.emit(line, output);
+            return Ok(());
+        }
+        let is_start = self.lines_before + self.pending_lines == 0;
+        let content = if is_start {
+            content.strip_prefix('\u{FEFF}').unwrap_or(content)
+        } else {
+            content
+        };
+        if directive::is_stop(content, &self.options.marker) {
+            self.pend(line);
+            self.flush(output)?;
+            self.stopped = true;
+            return Ok(());
+        }
+        match self.options.parse_directive(content) {
+            Ok(None) => match &mut self.block {
+                Some((directive, block_len))
+                    if self
+                        .options
+                        .may_continue_block(directive, content, *block_len) =>
+                {
+                    *block_len += 1;
+                    self.pend(line);
+                }
+                _ => {
+                    // The line after a block is what could cancel it, so the
+                    // block is final now.
+                    if self.block.take().is_some() {
+                        self.flush(output)?;
+                    }
+                    self.pend(line);
+                    self.release(output);
+                }
+            },
+            Ok(Some(directive)) if directive.above.is_some() || directive.cancel_block => {
+                self.block = None;
+                self.pend(line);
+                self.flush(output)?;
+            }
+            Ok(Some(directive)) => {
+                self.flush(output)?;
+                self.grouped |= directive.group.is_some();
+                self.block = Some((directive, 0));
+                self.pend(line);
+            }
+            Err(_) => {
+                // Aligning `pending` with this line first reports the error.
+                self.block = None;
+                self.flush(output)?;
+                self.pend(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes on the pending lines outside of blocks that are further back
+    /// than the lookback.
+    fn release(&mut self, output: &mut String) {
+        // Releasing them a lookback's worth at a time keeps the cost per line
+        // constant.
+        if self.grouped || self.pending_lines <= self.lookback.saturating_mul(2) {
+            return;
+        }
+        let released = self.pending_lines - self.lookback;
+        let end = self
+            .pending
+            .match_indices('\n')
+            .nth(released - 1)
+            .map_or(self.pending.len(), |(index, _)| index + 1);
+        let lines = self.pending.drain(..end).collect::<String>();
+        self.emit(&lines, output);
+        self.lines_before += released;
+        self.pending_lines -= released;
+    }
+
+    fn pend(&mut self, line: &str) {
+        self.pending.push_str(line);
+        self.pending_lines += 1;
+    }
+
+    /// Aligns the pending lines into `output`, unless they have to wait for
+    /// the widths of their named groups.
+    fn flush(&mut self, output: &mut String) -> Result<(), AlignmentError> {
+        if self.grouped || self.pending.is_empty() {
+            return Ok(());
+        }
+        let aligned = self
+            .options
+            .align_untrimmed(&self.pending)
+            .map_err(|err| err.moved_down(self.lines_before))?
+            .0
+            .into_owned();
+        self.emit(&aligned, output);
+        self.lines_before += self.pending_lines;
+        self.pending_lines = 0;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Adds `aligned` to `output`, holding back the whitespace at its end.
+    fn emit(&mut self, aligned: &str, output: &mut String) {
+        let content = aligned.trim_end();
+        if !content.is_empty() {
+            output.push_str(&std::mem::take(&mut self.trailing_whitespace));
+            output.push_str(content);
+        }
+        self.trailing_whitespace.push_str(&aligned[content.len()..]);
+    }
+
+    /// Ends the input, returning the rest of the output.
+    pub fn finish(mut self) -> Result<String, AlignmentError> {
+        let last_line = std::mem::take(&mut self.partial);
+        if !last_line.trim().is_empty() {
+            self.final_line_ending = "";
+        }
+        let mut output = String::new();
+        if self.stopped {
+            self.emit(&last_line, &mut output);
+        } else {
+            self.pend(&last_line);
+            self.grouped = false;
+            self.flush(&mut output)?;
+        }
+        let final_line_ending = match self.final_line_ending {
+            "" if self.line_endings.1 > self.line_endings.0 => "\r\n",
+            "" => "\n",
+            line_ending => line_ending,
+        };
+        // Like aligning all of the input at once, blank lines at the end are
+        // dropped and the last line gets a line ending.
+        Ok(end_with(&output, final_line_ending))
+    }
+}
+
+/// Aligns everything `reader` reads, a line at a time, passing the output to
+/// `write` as it becomes final.
+fn align_lines_with(
+    options: &AlignOptions,
+    mut reader: impl BufRead,
+    mut write: impl FnMut(&str) -> io::Result<()>,
+) -> io::Result<()> {
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+    let mut stream = AlignStream::new(options);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        write(&stream.push(&line).map_err(invalid)?)?;
+        line.clear();
+    }
+    write(&stream.finish().map_err(invalid)?)
+}
+
+/// Aligns everything `reader` reads into `writer`, holding only the lines
+/// that could still change in memory. An invalid alignment statement is an
+/// [`io::ErrorKind::InvalidData`] error wrapping the [`AlignmentError`], by
+/// which point the output before it has been written.
+pub fn align_io(
+    options: &AlignOptions,
+    reader: impl BufRead,
+    writer: impl Write,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    align_lines_with(options, reader, |aligned| {
+        writer.write_all(aligned.as_bytes())
+    })?;
+    writer.flush()
+}
+
+/// Aligns the file at `path` in place like [`align_io`], writing to a
+/// temporary file next to it that replaces it once aligning succeeded. The
+/// file is left alone if aligning wouldn't change it. Returns whether it did.
+pub fn align_file(path: impl AsRef<Path>, options: &AlignOptions) -> io::Result<bool> {
+    let path = path.as_ref();
+    let temporary_path = temporary_path(path);
+    let aligned = (|| {
+        let permissions = std::fs::metadata(path)?.permissions();
+        // The original is read a second time alongside the output to tell
+        // whether anything changed.
+        let mut original = BufReader::new(std::fs::File::open(path)?);
+        let mut changed = false;
+        let mut temporary = BufWriter::new(std::fs::File::create(&temporary_path)?);
+        align_lines_with(
+            options,
+            BufReader::new(std::fs::File::open(path)?),
+            |aligned| {
+                changed = changed || !continues_with(&mut original, aligned.as_bytes())?;
+                temporary.write_all(aligned.as_bytes())
+            },
+        )?;
+        changed = changed || !original.fill_buf()?.is_empty();
+        temporary
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        std::fs::set_permissions(&temporary_path, permissions)?;
+        Ok(changed)
+    })();
+    match aligned {
+        Ok(true) => std::fs::rename(&temporary_path, path).map(|()| true),
+        Ok(false) => std::fs::remove_file(&temporary_path).map(|()| false),
+        Err(err) => {
+            let _ = std::fs::remove_file(&temporary_path);
+            Err(err)
+        }
+    }
+}
+
+/// The temporary file next to `path` that aligned output is written to before
+/// it replaces the file at `path`.
+pub(crate) fn temporary_path(path: &Path) -> std::path::PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".cargo-align");
+    path.with_file_name(file_name)
+}
+
+/// Whether `reader` continues with `bytes`, reading as far as they match.
+fn continues_with(reader: &mut impl BufRead, mut bytes: &[u8]) -> io::Result<bool> {
+    while !bytes.is_empty() {
+        let buffer = reader.fill_buf()?;
+        let len = buffer.len().min(bytes.len());
+        if len == 0 || buffer[..len] != bytes[..len] {
+            return Ok(false);
+        }
+        reader.consume(len);
+        bytes = &bytes[len..];
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+#[rustfmt::skip] // align_by stop
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    
+    /// `s` pushed `chunk_len` characters at a time, holding back `lookback`
+    /// lines outside of blocks.
+    fn align_streamed(s: &str, chunk_len: usize, lookback: usize) -> Result<String, AlignmentError> {
+        let chars = s.chars().collect::<Vec<_>>();
+        let mut stream = AlignStream::new(&AlignOptions::new());
+        stream.lookback(lookback);
+        let mut output = String::new();
+        for chunk in chars.chunks(chunk_len) {
+            output.push_str(&stream.push(&chunk.iter().collect::<String>())?);
+        }
+        output.push_str(&stream.finish()?);
+        Ok(output)
+    }
+
+    #[test]
+    fn streaming() {
+        let inputs = [
+            indoc! {r#"
+                let a = Foo {
+                    a: 1,
+                    bbb: 2,
+                }; // align_by above 2 ":"
+                align_by "="
+                a = 1
+                bb = 2
+                c = 3
+                // align_by above 2 "="
+            "#},
+            indoc! {r#"
+                align_by "="
+                a = 1
+                bbb = 2
+                // align_by cancel_block
+                align_by "="
+                a = 1
+                bbb = 2
+                align_by table
+                | a | b |
+                | - | - |
+                // align_by cancel_block
+                align_by table
+                | a | b |
+                |-|:-:|
+            "#},
+            indoc! {r#"
+                align_by "="
+                a = 1
+                bbb = 2
+                align_by group(fields) "= ;"
+                a = 1;
+                b = 22;
+                }
+                align_by group(fields) "= ;"
+                ccc = 3;
+            "#},
+            indoc! {r#"
+                align_by "="
+                a = 1
+                bbb = 2
+                align_by stop
+                align_by"="
+                a = 1
+            "#},
+            "",
+            "\n\n",
+            "align_by \"=\"\na = 1\n\n\nbb = 2   \n\nalign_by \"=\"\nx = 1\n\n\n  \n",
+            "align_by keep_blank \"=\"\na = 1\n\nbbb = 2\n\n// align_by above 1 \"=\"\n\n",
+            "a\nalign_by stop\n x  \n\n\n",
+            "\u{FEFF}// align_by \"=\"\r\na = 1\r\nbbb = 2\r\nx = 1\r\n// align_by \"=\"\nc = 3\r\ndd = 4",
+        ];
+        for (index, input) in inputs.into_iter().enumerate() {
+            for chunk_len in [1, 5, input.len().max(1)] {
+                // Only the first input has an `above N` statement reaching
+                // lines outside of blocks.
+                let lookbacks = if index == 0 { &[2, 3, usize::MAX][..] } else { &[0, 2, usize::MAX] };
+                for &lookback in lookbacks {
+                    assert_eq!(align_streamed(input, chunk_len, lookback), AlignOptions::new().align_string(input), "{input}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_output_early() {
+        let mut stream = AlignStream::new(&AlignOptions::new());
+        assert_eq!(stream.push("align_by \"=\"\na = 1\nbbb").unwrap(), "");
+        // The line ending after `x` waits for a line that isn't blank.
+        assert_eq!(stream.push(" = 2\nx\nalign_by \"=\"\n").unwrap(), "align_by \"=\"\na   = 1\nbbb = 2\nx");
+        assert_eq!(stream.finish().unwrap(), "\nalign_by \"=\"\n");
+
+        // Lines outside of blocks are passed on right away.
+        let mut stream = AlignStream::new(&AlignOptions::new());
+        assert_eq!(stream.push("a = 1\nbbb = 2\n").unwrap(), "a = 1\nbbb = 2");
+        assert_eq!(stream.push("align_by count 1 \"=\"\nc = 1\n").unwrap(), "");
+        assert_eq!(stream.push("dd = 2\n").unwrap(), "\nalign_by count 1 \"=\"\nc = 1\ndd = 2");
+        assert_eq!(stream.push("x = 1 // align_by above 1 \"=\"\n").unwrap(), "\nx = 1 // align_by above 1 \"=\"");
+        let mut stream = AlignStream::new(&AlignOptions::new());
+        stream.lookback(2);
+        assert_eq!(stream.push("u\nv\nw\na = 1\nbbb = 2\n").unwrap(), "u\nv\nw");
+        assert_eq!(stream.push("// align_by above 2 \"=\"\n").unwrap(), "\na   = 1\nbbb = 2\n// align_by above 2 \"=\"");
+    }
+
+    #[test]
+    fn streaming_io() {
+        let input = "x\n// align_by \"=\"\na = 1\nbbb = 2\n\n\n";
+        let mut output = Vec::new();
+        align_io(&AlignOptions::new(), input.as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), AlignOptions::new().align_string(input).unwrap());
+        let err = align_io(&AlignOptions::new(), "a\n// align_by\"=\"\n".as_bytes(), Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.into_inner().unwrap().downcast::<AlignmentError>().unwrap().line(), 2);
+
+        let dir = std::env::temp_dir().join(format!("cargo-align-stream-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (aligned, unchanged, invalid) = (dir.join("a.rs"), dir.join("b.rs"), dir.join("c.rs"));
+        std::fs::write(&aligned, input).unwrap();
+        std::fs::write(&unchanged, "a = 1\n").unwrap();
+        std::fs::write(&invalid, "// align_by\"=\"\n").unwrap();
+        assert!(align_file(&aligned, &AlignOptions::new()).unwrap());
+        assert_eq!(std::fs::read_to_string(&aligned).unwrap(), "x\n// align_by \"=\"\na   = 1\nbbb = 2\n");
+        assert!(!align_file(&aligned, &AlignOptions::new()).unwrap());
+        assert!(!align_file(&unchanged, &AlignOptions::new()).unwrap());
+        assert!(align_file(&invalid, &AlignOptions::new()).is_err());
+        assert_eq!(std::fs::read_to_string(&invalid).unwrap(), "// align_by\"=\"\n");
+        assert!(align_file(dir.join("d.rs"), &AlignOptions::new()).is_err());
+        #[cfg(unix)]
+        {
+            // Executable scripts stay executable once replaced.
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::write(&aligned, input).unwrap();
+            std::fs::set_permissions(&aligned, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(align_file(&aligned, &AlignOptions::new()).unwrap());
+            assert_eq!(std::fs::metadata(&aligned).unwrap().permissions().mode() & 0o777, 0o755);
+        }
+        // No temporary files are left behind.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn streaming_errors() {
+        let input = "align_by \"=\"\na = 1\nbbb = 2\nx\n  // align_by\"=\"\n";
+        assert_eq!(align_streamed(input, 3, 0), AlignOptions::new().align_string(input));
+        assert_eq!(align_streamed(input, 3, 0).unwrap_err().line(), 5);
+    }
+}
@@ -0,0 +1,221 @@
+//! Formatting of Markdown pipe tables for `align_by table`.
+
+use crate::align::Mode;
+use crate::directive::comment_prefix;
+
+/// How a table column is aligned, given by the `:` markers of the separator row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnAlignment {
+    /// No `:` markers.
+    Default,
+    Left,
+    Right,
+    Center,
+}
+
+/// Separator row cells need at least this many characters to stay valid.
+const MIN_SEPARATOR_WIDTH: usize = 3;
+
+/// Splits a table row into its prefix, the indentation or comment opener
+/// before the table, and its trimmed cells.
+///
+/// A leading and trailing `|` don't start extra cells, and `\|` stays part of
+/// its cell.
+fn split_row(row: &str) -> (&str, Vec<&str>) {
+    let before_pipe = &row[..row.find('|').unwrap_or(0)];
+    let is_prefix = before_pipe.trim().is_empty()
+        || comment_prefix(before_pipe)
+            .is_some_and(|span| before_pipe[..span.start].trim().is_empty());
+    let start = if is_prefix {
+        before_pipe.len()
+    } else {
+        row.len() - row.trim_start().len()
+    };
+    let (prefix, rest) = row.split_at(start);
+    let rest = rest.trim_end();
+    let rest = rest.strip_prefix('|').unwrap_or(rest);
+    let rest = match rest.strip_suffix('|') {
+        Some(stripped) if !stripped.ends_with('\\') => stripped,
+        _ => rest,
+    };
+    let mut cells = Vec::new();
+    let mut cell_start = 0;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '|' if !escaped => {
+                cells.push(rest[cell_start..i].trim());
+                cell_start = i + 1;
+            }
+            _ => {}
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.push(rest[cell_start..].trim());
+    (prefix, cells)
+}
+
+/// The column alignment of a separator row cell, or `None` if it isn't one.
+fn separator_alignment(cell: &str) -> Option<ColumnAlignment> {
+    let left = cell.starts_with(':');
+    let right = cell.len() > 1 && cell.ends_with(':');
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+    if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+        return None;
+    }
+    Some(match (left, right) {
+        (false, false) => ColumnAlignment::Default,
+        (true, false) => ColumnAlignment::Left,
+        (false, true) => ColumnAlignment::Right,
+        (true, true) => ColumnAlignment::Center,
+    })
+}
+
+/// The built in `table` mode, a block of rows with a `|` formatted by
+/// [`format_table`].
+pub struct Table;
+
+impl Mode for Table {
+    fn continues(&self, line: &str) -> bool {
+        line.contains('|')
+    }
+
+    fn transform(&self, lines: &[&str]) -> Vec<String> {
+        format_table(lines.iter().copied())
+    }
+}
+
+/// Pads every cell of the table `rows` to the widest cell of its column, and
+/// rewrites the separator row to match while keeping its `:` markers.
+///
+/// Text before the first `|` of each row, like a doc comment opener, is kept.
+pub fn format_table<'a>(rows: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let rows = rows.into_iter().map(split_row).collect::<Vec<_>>();
+    let column_count = rows.iter().map(|(_, cells)| cells.len()).max().unwrap_or(0);
+    let separator_index = rows
+        .iter()
+        .position(|(_, cells)| cells.iter().all(|cell| separator_alignment(cell).is_some()));
+    let alignments = (0..column_count)
+        .map(|column| {
+            separator_index
+                .and_then(|index| rows[index].1.get(column).copied())
+                .and_then(separator_alignment)
+                .unwrap_or(ColumnAlignment::Default)
+        })
+        .collect::<Vec<_>>();
+    let widths = (0..column_count)
+        .map(|column| {
+            rows.iter()
+                .enumerate()
+                .filter(|(index, _)| Some(*index) != separator_index)
+                .filter_map(|(_, (_, cells))| cells.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(MIN_SEPARATOR_WIDTH)
+        })
+        .collect::<Vec<_>>();
+
+    rows.iter()
+        .enumerate()
+        .map(|(index, (prefix, cells))| {
+            let mut row = prefix.to_string();
+            row.push('|');
+            for column in 0..column_count {
+                let width = widths[column];
+                let cell = if Some(index) == separator_index {
+                    separator_cell(alignments[column], width)
+                } else {
+                    pad_cell(
+                        cells.get(column).copied().unwrap_or(""),
+                        alignments[column],
+                        width,
+                    )
+                };
+                row.push(' ');
+                row.push_str(&cell);
+                row.push_str(" |");
+            }
+            row
+        })
+        .collect()
+}
+
+fn separator_cell(alignment: ColumnAlignment, width: usize) -> String {
+    match alignment {
+        ColumnAlignment::Default => "-".repeat(width),
+        ColumnAlignment::Left => format!(":{}", "-".repeat(width - 1)),
+        ColumnAlignment::Right => format!("{}:", "-".repeat(width - 1)),
+        ColumnAlignment::Center => format!(":{}:", "-".repeat(width - 2)),
+    }
+}
+
+fn pad_cell(cell: &str, alignment: ColumnAlignment, width: usize) -> String {
+    let padding = width - cell.chars().count();
+    match alignment {
+        ColumnAlignment::Default | ColumnAlignment::Left => {
+            format!("{cell}{}", " ".repeat(padding))
+        }
+        ColumnAlignment::Right => format!("{}{cell}", " ".repeat(padding)),
+        ColumnAlignment::Center => format!(
+            "{}{cell}{}",
+            " ".repeat(padding / 2),
+            " ".repeat(padding - padding / 2)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn format(table: &str) -> String {
+        format_table(table.lines()).join("\n") + "\n"
+    }
+
+    #[test]
+    fn splitting() {
+        assert_eq!(split_row("/// | a | b |"), ("/// ", vec!["a", "b"]));
+        assert_eq!(split_row("a|b"), ("", vec!["a", "b"]));
+        assert_eq!(
+            split_row(r"| a \| b | c \|"),
+            ("", vec![r"a \| b", r"c \|"])
+        );
+        assert_eq!(separator_alignment(":-:"), Some(ColumnAlignment::Center));
+        assert_eq!(separator_alignment(":"), None);
+        assert_eq!(separator_alignment("-x-"), None);
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(
+            format(indoc! {"
+                | Name | Value | Notes |
+                |:-|--:|:---:|
+                | a | 1 | x |
+                | longer name | 100 |
+            "}),
+            indoc! {"
+                | Name        | Value | Notes |
+                | :---------- | ----: | :---: |
+                | a           |     1 |   x   |
+                | longer name |   100 |       |
+            "}
+        );
+
+        assert_eq!(
+            format(indoc! {"
+                /// |a|b|
+                /// |-|-|
+                /// |é|\\||
+            "}),
+            indoc! {"
+                /// | a   | b   |
+                /// | --- | --- |
+                /// | é   | \\|  |
+            "}
+        );
+    }
+}
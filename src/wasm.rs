@@ -0,0 +1,71 @@
+//! JavaScript bindings to the alignment engine, so a browser playground or a
+//! web based editor can align text client side. Built with
+//! `wasm-pack build --no-default-features --features wasm`.
+
+use crate::align::AlignOptions;
+use crate::align::AlignmentError;
+use crate::directive;
+use wasm_bindgen::prelude::*;
+
+/// Aligns `text` like `cargo align` aligns a file, with `marker` starting the
+/// alignment statements instead of `align_by` if given. Throws the first
+/// invalid alignment statement.
+#[wasm_bindgen(js_name = alignString)]
+pub fn align_string(text: &str, marker: Option<String>) -> Result<String, JsError> {
+    let marker = marker.as_deref().unwrap_or(directive::DEFAULT_MARKER);
+    AlignOptions::new()
+        .marker(marker)
+        .align_string(text)
+        .map_err(|err| JsError::new(&err.to_string()))
+}
+
+/// An alignment statement, from [`parse_directive`].
+#[wasm_bindgen]
+pub struct Directive(directive::Directive);
+
+#[wasm_bindgen]
+impl Directive {
+    /// What the statement does, `table`, `cancel_block`, `sort` or `normal`.
+    #[wasm_bindgen(getter)]
+    pub fn mode(&self) -> String {
+        self.0.mode().to_string()
+    }
+
+    /// The text of each delimiter, in order.
+    #[wasm_bindgen(getter)]
+    pub fn delimiters(&self) -> Vec<String> {
+        self.0
+            .delimiters
+            .iter()
+            .map(|delimiter| delimiter.text.clone())
+            .collect()
+    }
+
+    /// The side of each delimiter, like `left` or `right`.
+    #[wasm_bindgen(getter)]
+    pub fn alignments(&self) -> Vec<String> {
+        self.0
+            .delimiters
+            .iter()
+            .map(|delimiter| delimiter.alignment.name().to_string())
+            .collect()
+    }
+}
+
+/// Parses the alignment statement on `line`, `undefined` if it has none.
+/// Throws if the statement is invalid, located as if `line` was a whole file.
+#[wasm_bindgen(js_name = parseDirective)]
+pub fn parse_directive(line: &str, marker: Option<String>) -> Result<Option<Directive>, JsError> {
+    let marker = marker.as_deref().unwrap_or(directive::DEFAULT_MARKER);
+    match directive::parse_directive(line, marker) {
+        Ok(directive) => Ok(directive.map(Directive)),
+        Err(err) => Err(JsError::new(
+            &AlignmentError::InvalidAlignmentStatement {
+                line: 1,
+                column: err.span.column(line),
+                kind: err.kind,
+            }
+            .to_string(),
+        )),
+    }
+}